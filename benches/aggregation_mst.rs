@@ -0,0 +1,210 @@
+#![feature(generic_const_exprs)]
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::BigUint;
+use rayon::prelude::*;
+use std::error::Error;
+use summa_aggregation::{
+    aggregation_merkle_sum_tree::{
+        build_merkle_tree_from_leaves_parallel, AggregationMerkleSumTree,
+    },
+    executor::MockSpawner,
+    json_mst::JsonEntry,
+    orchestrator::{EntrySource, Orchestrator},
+};
+use summa_backend::merkle_sum_tree::{
+    utils::build_merkle_tree_from_leaves, Cryptocurrency, Entry, MerkleSumTree, Node,
+};
+
+const N_CURRENCIES: usize = 2;
+// The aggregation tree over 2^20 mini tree roots
+const DEPTH: usize = 20;
+const N_BYTES: usize = 14;
+// The depth of the mini trees in the benchmarks of the whole aggregation
+const MINI_TREE_DEPTH: usize = 4;
+
+fn leaves() -> Vec<Node<N_CURRENCIES>> {
+    (0..1 << DEPTH)
+        .into_par_iter()
+        .map(|i: usize| {
+            Entry::<N_CURRENCIES>::new(
+                format!("user_{}", i),
+                [BigUint::from(i), BigUint::from(2 * i)],
+            )
+            .unwrap()
+            .compute_leaf()
+        })
+        .collect()
+}
+
+fn accumulate_balances(c: &mut Criterion) {
+    let leaves = leaves();
+    let mut group = c.benchmark_group("accumulate_balances_2^20");
+    group.sample_size(10);
+
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            let mut balances_acc = [Fp::from(0); N_CURRENCIES];
+            for leaf in &leaves {
+                for (i, balance) in leaf.balances.iter().enumerate() {
+                    balances_acc[i] += *balance;
+                }
+            }
+            balances_acc
+        })
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            leaves.par_iter().map(|leaf| leaf.balances).reduce(
+                || [Fp::from(0); N_CURRENCIES],
+                |mut balances_acc, balances| {
+                    for (i, balance) in balances.iter().enumerate() {
+                        balances_acc[i] += *balance;
+                    }
+                    balances_acc
+                },
+            )
+        })
+    });
+    group.finish();
+}
+
+fn build_tree(c: &mut Criterion) {
+    let leaves = leaves();
+    let mut group = c.benchmark_group("build_tree_2^20");
+    group.sample_size(10);
+
+    group.bench_function("serial", |b| {
+        b.iter(|| {
+            let mut nodes = vec![];
+            build_merkle_tree_from_leaves(&leaves, DEPTH, &mut nodes).unwrap()
+        })
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| {
+            let mut nodes = vec![];
+            build_merkle_tree_from_leaves_parallel(&leaves, DEPTH, &mut nodes).unwrap()
+        })
+    });
+    group.finish();
+}
+
+// The entries of the mini tree at `tree_index`, with usernames unique over all mini trees
+fn mini_tree_entries(tree_index: usize) -> Vec<Entry<N_CURRENCIES>> {
+    (0..1 << MINI_TREE_DEPTH)
+        .map(|i: usize| {
+            Entry::<N_CURRENCIES>::new(
+                format!("user_{}_{}", tree_index, i),
+                [BigUint::from(i), BigUint::from(2 * i)],
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+fn cryptocurrencies() -> Vec<Cryptocurrency> {
+    vec![
+        Cryptocurrency {
+            name: "DUMMY".to_string(),
+            chain: "ETH".to_string(),
+        };
+        N_CURRENCIES
+    ]
+}
+
+fn aggregation_mst_new(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregation_mst_new");
+    group.sample_size(10);
+
+    for n_mini_trees in [16, 256, 4096] {
+        let mini_trees = (0..n_mini_trees)
+            .into_par_iter()
+            .map(|tree_index| {
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+                    mini_tree_entries(tree_index),
+                    cryptocurrencies(),
+                    false,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_mini_trees),
+            &mini_trees,
+            |b, mini_trees| {
+                b.iter_batched(
+                    || mini_trees.clone(),
+                    |mini_trees| {
+                        AggregationMerkleSumTree::new(mini_trees, cryptocurrencies()).unwrap()
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+// Holds the entries of a mini tree in memory, so the benchmark does not read any files
+struct InMemoryEntrySource(Vec<JsonEntry>);
+
+impl EntrySource for InMemoryEntrySource {
+    fn name(&self) -> String {
+        "in-memory".to_string()
+    }
+
+    fn load_entries(&self) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>> {
+        Ok(self.0.clone())
+    }
+}
+
+// The whole aggregation by the Orchestrator, with the mini trees built by local servers of the MockSpawner
+fn create_aggregation_mst(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("create_aggregation_mst");
+    group.sample_size(10);
+
+    for n_mini_trees in [4, 16, 64] {
+        let entries = (0..n_mini_trees)
+            .map(|tree_index| {
+                mini_tree_entries(tree_index)
+                    .iter()
+                    .map(JsonEntry::from_entry)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(n_mini_trees),
+            &entries,
+            |b, entries| {
+                b.iter(|| {
+                    let entry_sources = entries
+                        .iter()
+                        .map(|entries| {
+                            Box::new(InMemoryEntrySource(entries.clone())) as Box<dyn EntrySource>
+                        })
+                        .collect();
+                    runtime.block_on(async {
+                        Orchestrator::<N_CURRENCIES, N_BYTES>::from_entry_sources(
+                            Box::new(MockSpawner::pooled(2)),
+                            entry_sources,
+                        )
+                        .create_aggregation_mst(2)
+                        .await
+                        .unwrap()
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    accumulate_balances,
+    build_tree,
+    aggregation_mst_new,
+    create_aggregation_mst
+);
+criterion_main!(benches);
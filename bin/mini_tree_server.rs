@@ -1,19 +1,101 @@
-use axum::{routing::post, Router};
-use std::net::SocketAddr;
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
 
-use summa_aggregation::mini_tree_generator::create_mst;
+use axum::Router;
+use summa_aggregation::{logging, mini_tree_generator::mini_tree_router};
+use tracing::info;
 
 #[tokio::main]
 async fn main() {
+    logging::init();
+
     // Define the app with a route
-    let app = Router::new().route("/", post(create_mst));
+    let app = mini_tree_router();
+
+    // Serve on a Unix domain socket if `MINI_TREE_SOCKET` is set, e.g. for a sidecar, otherwise on TCP
+    match env::var("MINI_TREE_SOCKET") {
+        Ok(path) if !path.is_empty() => serve_unix(app, &path).await,
+        _ => serve_tcp(app).await,
+    }
+    info!("server stopped");
+}
+
+// Serves on the address given by `MINI_TREE_HOST` and `MINI_TREE_PORT`, all interfaces on port 4000 by default
+async fn serve_tcp(app: Router) {
+    let host = match env::var("MINI_TREE_HOST") {
+        Ok(host) if !host.is_empty() => host
+            .parse::<IpAddr>()
+            .unwrap_or_else(|_| panic!("Invalid MINI_TREE_HOST: {}", host)),
+        _ => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+    let port = match env::var("MINI_TREE_PORT") {
+        Ok(port) if !port.is_empty() => port
+            .parse::<u16>()
+            .unwrap_or_else(|_| panic!("Invalid MINI_TREE_PORT: {}", port)),
+        _ => 4000,
+    };
+    let addr = SocketAddr::new(host, port);
+
+    // Start the server, which finishes the requests in flight before exiting on SIGTERM or SIGINT
+    let server = axum::Server::bind(&addr).serve(app.into_make_service());
+    info!(address = %server.local_addr(), "listening");
+    server
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+}
 
-    // Define the address to serve on
-    let addr = SocketAddr::from(([0, 0, 0, 0], 4000));
+#[cfg(unix)]
+async fn serve_unix(app: Router, path: &str) {
+    use std::task::Poll;
+    use tokio::net::UnixListener;
 
-    // Start the server
-    axum::Server::bind(&addr)
+    // A socket file left by a previous run would fail the bind
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)
+        .unwrap_or_else(|e| panic!("Failed to bind MINI_TREE_SOCKET {}: {}", path, e));
+    let accept = hyper::server::accept::poll_fn(move |cx| match listener.poll_accept(cx) {
+        Poll::Ready(accepted) => Poll::Ready(Some(accepted.map(|(stream, _)| stream))),
+        Poll::Pending => Poll::Pending,
+    });
+
+    info!(socket = path, "listening");
+    axum::Server::builder(accept)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_app: Router, _path: &str) {
+    panic!("MINI_TREE_SOCKET is only supported on Unix");
+}
+
+// Resolves on SIGINT, or on SIGTERM as sent by `docker stop` and Swarm updates
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("shutdown signal received, finishing the requests in flight");
 }
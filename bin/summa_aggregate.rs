@@ -0,0 +1,288 @@
+#![feature(generic_const_exprs)]
+use std::{env, error::Error, fs, path::Path, time::Instant};
+
+use serde::Deserialize;
+use summa_aggregation::{
+    executor::{CloudSpawner, ExecutorSpawner, LocalSpawner},
+    logging,
+    mini_tree_generator::{SUPPORTED_N_BYTES, SUPPORTED_N_CURRENCIES},
+    orchestrator::{CancellationToken, Orchestrator},
+};
+use summa_backend::merkle_sum_tree::Tree;
+
+const USAGE: &str = "Usage: summa-aggregate [--config <file.yml>] [--worker-url <url>]... \
+[--local-image <image> --local-container <name>] [--csv <file>]... [--csv-dir <dir>] \
+[--n-currencies <n>] [--n-bytes <n>] [--executors <n>] [--output <file.json>] [--dry-run]";
+
+/// Configuration of a round, read from a YAML file with `--config`, e.g.
+///
+/// ```yaml
+/// worker_urls: ["10.0.0.1:4000", "10.0.0.2:4000"]
+/// csv_dir: csv
+/// n_currencies: 2
+/// n_bytes: 14
+/// output: aggregation_mst.json
+/// ```
+///
+/// The command line arguments override the values of the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    // The workers are reached with a CloudSpawner at these URLs, unless `local_image` is set
+    worker_urls: Vec<String>,
+    // Spawns the workers as local containers of this image with a LocalSpawner instead
+    local_image: Option<String>,
+    local_container: Option<String>,
+    csvs: Vec<String>,
+    // Every `.csv` file in this directory is an entry source, in the order of their names
+    csv_dir: Option<String>,
+    n_currencies: Option<usize>,
+    n_bytes: Option<usize>,
+    // Defaults to the number of worker URLs
+    executors: Option<usize>,
+    // The aggregation tree is written here with `save_to_file`, if set
+    output: Option<String>,
+    // Only validates the entry sources with `Orchestrator::validate`, without spawning any executor
+    dry_run: bool,
+}
+
+impl Config {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut config = Config::default();
+        let mut overrides = Vec::new();
+        let mut config_dry_run = false;
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            if arg == "--dry-run" {
+                config_dry_run = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}\n{}", arg, USAGE))?;
+            if arg == "--config" {
+                config = serde_yaml::from_str(&fs::read_to_string(&value)?)
+                    .map_err(|e| format!("Invalid config {:?}: {}", value, e))?;
+            } else {
+                overrides.push((arg, value));
+            }
+        }
+
+        // Applied after reading the config, wherever `--config` appears
+        for (arg, value) in overrides {
+            match arg.as_str() {
+                "--worker-url" => config.worker_urls.push(value),
+                "--local-image" => config.local_image = Some(value),
+                "--local-container" => config.local_container = Some(value),
+                "--csv" => config.csvs.push(value),
+                "--csv-dir" => config.csv_dir = Some(value),
+                "--n-currencies" => config.n_currencies = Some(parse_number(&arg, &value)?),
+                "--n-bytes" => config.n_bytes = Some(parse_number(&arg, &value)?),
+                "--executors" => config.executors = Some(parse_number(&arg, &value)?),
+                "--output" => config.output = Some(value),
+                _ => return Err(format!("Unknown argument {}\n{}", arg, USAGE).into()),
+            }
+        }
+        config.dry_run |= config_dry_run;
+        Ok(config)
+    }
+
+    // The CSV files given one by one, followed by those of `csv_dir`
+    fn entry_csvs(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut entry_csvs = self.csvs.clone();
+        if let Some(csv_dir) = &self.csv_dir {
+            let mut dir_csvs = fs::read_dir(csv_dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|path| path.extension().map_or(false, |ext| ext == "csv"))
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            dir_csvs.sort();
+            entry_csvs.extend(dir_csvs);
+        }
+        if entry_csvs.is_empty() {
+            return Err(format!("No CSV files given\n{}", USAGE).into());
+        }
+        Ok(entry_csvs)
+    }
+
+    fn spawner(&self) -> Result<Box<dyn ExecutorSpawner>, Box<dyn Error>> {
+        match &self.local_image {
+            Some(image_name) => {
+                if self.executors.is_none() {
+                    return Err("--executors is required with --local-image".into());
+                }
+                let container_name = self
+                    .local_container
+                    .clone()
+                    .unwrap_or_else(|| "mini-tree-generator".to_string());
+                Ok(Box::new(LocalSpawner::new(
+                    image_name.clone(),
+                    container_name,
+                )))
+            }
+            None => {
+                if self.worker_urls.is_empty() {
+                    return Err(format!("No worker URLs given\n{}", USAGE).into());
+                }
+                // Without `executors`, the CloudSpawner spawns one executor per worker URL
+                let spawner = CloudSpawner::new(None, self.worker_urls.clone(), 4000)
+                    .map_err(|e| e as Box<dyn Error>)?;
+                Ok(Box::new(spawner))
+            }
+        }
+    }
+}
+
+fn parse_number(arg: &str, value: &str) -> Result<usize, Box<dyn Error>> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value {:?} for {}", value, arg).into())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    logging::init();
+    let config = Config::from_args(env::args().skip(1))?;
+    let entry_csvs = config.entry_csvs()?;
+    let spawner = config.spawner()?;
+    let n_currencies = config.n_currencies.unwrap_or(2);
+    let n_bytes = config.n_bytes.unwrap_or(14);
+
+    let round = Round {
+        spawner,
+        entry_csvs,
+        executors: config.executors,
+        output: config.output,
+        dry_run: config.dry_run,
+    };
+    match n_currencies {
+        1 => round.run_with_n_bytes::<1>(n_bytes).await,
+        2 => round.run_with_n_bytes::<2>(n_bytes).await,
+        3 => round.run_with_n_bytes::<3>(n_bytes).await,
+        4 => round.run_with_n_bytes::<4>(n_bytes).await,
+        _ => None,
+    }
+    .unwrap_or_else(|| {
+        Err(format!(
+            "Unsupported tree shape: {} currencies with {} bytes, supported are {:?} currencies with {:?} bytes",
+            n_currencies, n_bytes, SUPPORTED_N_CURRENCIES, SUPPORTED_N_BYTES
+        )
+        .into())
+    })
+}
+
+struct Round {
+    spawner: Box<dyn ExecutorSpawner>,
+    entry_csvs: Vec<String>,
+    executors: Option<usize>,
+    output: Option<String>,
+    dry_run: bool,
+}
+
+impl Round {
+    async fn run_with_n_bytes<const N_CURRENCIES: usize>(
+        self,
+        n_bytes: usize,
+    ) -> Option<Result<(), Box<dyn Error>>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        match n_bytes {
+            8 => Some(self.run::<N_CURRENCIES, 8>().await),
+            14 => Some(self.run::<N_CURRENCIES, 14>().await),
+            16 => Some(self.run::<N_CURRENCIES, 16>().await),
+            _ => None,
+        }
+    }
+
+    async fn run<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        self,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        // Pressing Ctrl-C cancels the round, the Orchestrator then terminates the Executors it has spawned
+        let cancel_token = CancellationToken::new();
+        let ctrl_c_token = cancel_token.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                ctrl_c_token.cancel();
+            }
+        });
+
+        let n_entry_csvs = self.entry_csvs.len();
+        let orchestrator =
+            Orchestrator::<N_CURRENCIES, N_BYTES>::new(self.spawner, self.entry_csvs)
+                .with_cancellation_token(cancel_token);
+        if self.dry_run {
+            let start = Instant::now();
+            let report = orchestrator.validate().unwrap_or_else(|report| report);
+            println!(
+                "Validated {} entries in {} CSV files in {:?}",
+                report.total_entries,
+                n_entry_csvs,
+                start.elapsed()
+            );
+            for (i, balance) in report.total_balances.iter().enumerate() {
+                println!("Total balance of currency {}: {}", i, balance);
+            }
+            for error in &report.errors {
+                println!("Error: {}", error);
+            }
+            if !report.is_valid() {
+                return Err(
+                    format!("Validation failed with {} errors", report.errors.len()).into(),
+                );
+            }
+            return Ok(());
+        }
+
+        let executors = self
+            .executors
+            .map_or("one executor per worker".to_string(), |executors| {
+                format!("{} executors", executors)
+            });
+        println!(
+            "Aggregating {} CSV files with {}, {} currencies with {} bytes",
+            n_entry_csvs, executors, N_CURRENCIES, N_BYTES
+        );
+        let (aggregation_merkle_sum_tree, round_stats) = orchestrator
+            .create_aggregation_mst_with_stats(self.executors)
+            .await?;
+        println!("Built the aggregation tree: {}", round_stats);
+        println!(
+            "Mini trees per executor: {:?}",
+            round_stats.chunks_per_executor
+        );
+
+        println!("Root hash: {:?}", aggregation_merkle_sum_tree.root().hash);
+        for (cryptocurrency, balance) in aggregation_merkle_sum_tree
+            .cryptocurrencies()
+            .iter()
+            .zip(aggregation_merkle_sum_tree.total_balances_as_big_uint())
+        {
+            println!(
+                "Total balance of {} on {}: {}",
+                cryptocurrency.name, cryptocurrency.chain, balance
+            );
+        }
+
+        if let Some(output) = self.output {
+            let start = Instant::now();
+            aggregation_merkle_sum_tree.save_to_file(Path::new(&output))?;
+            println!(
+                "Saved the aggregation tree to {} in {:?}",
+                output,
+                start.elapsed()
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 #![feature(generic_const_exprs)]
-use axum::{routing::post, Router};
 use std::error::Error;
 use std::net::SocketAddr;
 
 use summa_aggregation::{
-    executor::CloudSpawner, mini_tree_generator::create_mst, orchestrator::Orchestrator,
+    executor::CloudSpawner,
+    mini_tree_generator::mini_tree_router,
+    orchestrator::{CancellationToken, Orchestrator},
 };
 use summa_backend::{
     apis::round::Round,
@@ -21,7 +22,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Spawning Worker_1
     tokio::spawn(async move {
-        let app = Router::new().route("/", post(create_mst));
+        let app = mini_tree_router();
         let addr = SocketAddr::from(([0, 0, 0, 0], 4000));
         axum::Server::bind(&addr)
             .serve(app.into_make_service())
@@ -31,7 +32,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Spawning Worker_2
     tokio::spawn(async move {
-        let app = Router::new().route("/", post(create_mst));
+        let app = mini_tree_router();
         let addr = SocketAddr::from(([0, 0, 0, 0], 4001));
         axum::Server::bind(&addr)
             .serve(app.into_make_service())
@@ -69,14 +70,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // CloudSpawner does not depend on a `docker-compose.yml` file or a `service_name` for creating workers.
     // This implies that `service_info` is not necessary. When `service_info` is absent, CloudSpawner creates an Executor solely based on the `worker_node_url`.
-    let spawner = CloudSpawner::new(None, worker_node_urls, 4000);
+    let spawner =
+        CloudSpawner::new(None, worker_node_urls, 4000).map_err(|e| e as Box<dyn Error>)?;
+    // Pressing Ctrl-C cancels the processing, the Orchestrator then terminates the Executors it has spawned.
+    let cancel_token = CancellationToken::new();
+    let ctrl_c_token = cancel_token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrl_c_token.cancel();
+        }
+    });
+
     let orchestrator = Orchestrator::<N_CURRENCIES, N_BYTES>::new(
         Box::new(spawner),
         vec![
             "csv/entry_16_1.csv".to_string(),
             "csv/entry_16_2.csv".to_string(),
         ],
-    );
+    )
+    .with_cancellation_token(cancel_token);
 
     // The number of Executors must match the number of worker_node_urls.
     let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(2).await.unwrap();
@@ -1,14 +1,81 @@
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
+use num_bigint::{BigInt, BigUint};
+use rayon::prelude::*;
+use std::collections::HashMap;
 use std::error::Error;
-use summa_backend::merkle_sum_tree::utils::build_merkle_tree_from_leaves;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::OnceLock;
+use summa_backend::merkle_sum_tree::utils::fp_to_big_uint;
 use summa_backend::merkle_sum_tree::{
     Cryptocurrency, Entry, MerkleProof, MerkleSumTree, Node, Tree,
 };
 
+use crate::json_mst::{JsonAggregationMerkleSumTree, JsonEntry, JsonMerkleProof};
+
+/// A currency whose total balance differs from the expected one, see `AggregationMerkleSumTree::verify_round`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrencyMismatch {
+    pub currency_index: usize,
+    /// The currency as named in error messages, e.g. "currency 0 (ETH on ETH)"
+    pub currency: String,
+    pub expected: BigUint,
+    pub actual: BigUint,
+}
+
+impl CurrencyMismatch {
+    /// The total balance of the tree minus the expected one.
+    pub fn delta(&self) -> BigInt {
+        BigInt::from(self.actual.clone()) - BigInt::from(self.expected.clone())
+    }
+}
+
+/// Returned by `AggregationMerkleSumTree::verify_round`, holding every currency whose total balance differs from the expected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub currencies: Vec<CurrencyMismatch>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Total balances differ from the expected ones:")?;
+        for (i, mismatch) in self.currencies.iter().enumerate() {
+            write!(
+                f,
+                "{} {} is {}, expected {} (delta {})",
+                if i == 0 { "" } else { ";" },
+                mismatch.currency,
+                mismatch.actual,
+                mismatch.expected,
+                mismatch.delta()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Mismatch {}
+
+// Proofs generated per thread before they are written, bounding the proofs held by `export_proofs`
+const EXPORT_PROOFS_PER_THREAD: usize = 256;
+
+// Estimated heap bytes of an entry besides its inline size, namely its username, as string and number,
+// plus the digits of each balance
+const ENTRY_HEAP_BYTES: usize = 32;
+const BALANCE_HEAP_BYTES: usize = 16;
+
 /// Aggregation Merkle Sum Tree Data Structure.
 ///
-/// Starting from a set of "mini" Merkle Sum Tree of equal depth, N_CURRENCIES and N_BYTES, the Aggregation Merkle Sum Tree inherits the properties of a Merkle Sum Tree and adds the following:
+/// Starting from a set of "mini" Merkle Sum Tree of equal N_CURRENCIES and N_BYTES, the Aggregation Merkle Sum Tree inherits the properties of a Merkle Sum Tree and adds the following:
 /// * Each Leaf of the Aggregation Merkle Sum Tree is the root of a "mini" Merkle Sum Tree made of `hash` and `balances`
+/// * The mini trees may differ in depth, e.g. when the last chunk of entries is smaller than the others.
+///   The entries are indexed consecutively over the mini trees, including the padding entries of each mini tree,
+///   and the proofs of entries in shallower mini trees have a shorter path.
+/// * If the number of mini trees is not a power of two, the leaves are padded to the next power of two with `padding_leaf`,
+///   a node with zero balances whose hash has a known preimage, so the entries of the last mini tree have valid proofs too.
 ///
 /// # Type Parameters
 ///
@@ -21,6 +88,10 @@ pub struct AggregationMerkleSumTree<const N_CURRENCIES: usize, const N_BYTES: us
     depth: usize,
     cryptocurrencies: Vec<Cryptocurrency>,
     mini_trees: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>>,
+    // Index of the first entry of each mini tree
+    mini_tree_offsets: Vec<usize>,
+    // Index of the entry of each username, built on the first `index_of_username`
+    user_indices: OnceLock<HashMap<String, usize>>,
 }
 
 impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES, N_BYTES>
@@ -43,7 +114,7 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES, N_BYTES
     }
 
     fn get_entry(&self, user_index: usize) -> &Entry<N_CURRENCIES> {
-        let (mini_tree_index, entry_index) = self.get_entry_location(user_index);
+        let (mini_tree_index, entry_index) = self.locate(user_index);
 
         // Retrieve the mini tree
         let mini_tree = &self.mini_trees[mini_tree_index];
@@ -60,66 +131,70 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Tree<N_CURRENCIES, N_BYTES
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let (mini_tree_index, entry_index) = self.get_entry_location(index);
-
-        // Retrieve the mini tree
-        let mini_tree = &self.mini_trees[mini_tree_index];
-
-        // Retrieve sibling mini tree
-        let sibling_mini_tree_index = if mini_tree_index % 2 == 0 {
-            mini_tree_index + 1
-        } else {
-            mini_tree_index - 1
-        };
-        let sibling_mini_tree = &self.mini_trees[sibling_mini_tree_index];
+        let (mini_tree_index, entry_index) = self.locate(index);
 
         // Build the partial proof, namely from the leaf to the root of the mini tree
-        let mut partial_proof = mini_tree.generate_proof(entry_index)?;
-        let mut sibling_middle_node_hash_preimages = Vec::new();
-
-        // Retrieve sibling mini tree root hash preimage
-        let sibling_mini_tree_node_preimage = sibling_mini_tree
-            .get_middle_node_hash_preimage(*sibling_mini_tree.depth(), 0)
-            .unwrap();
+        let mut partial_proof = self.mini_trees[mini_tree_index].generate_proof(entry_index)?;
+        let top_proof = self.top_proof(mini_tree_index)?;
+        self.complete_proof(&mut partial_proof, &top_proof);
 
-        sibling_middle_node_hash_preimages.push(sibling_mini_tree_node_preimage);
+        Ok(partial_proof)
+    }
+}
 
-        // Build the rest of the proof (top_proof), namely from the root of the mini tree to the root of the aggregation tree
-        let mut current_index = mini_tree_index;
-        let mut path_indices = vec![Fp::from(0); self.depth];
+/// The root of an Aggregation Merkle Sum Tree without its nodes and mini trees, see `AggregationMerkleSumTree::into_root_only`.
+///
+/// It keeps what is needed to commit to the round, e.g. when the proofs are generated by a separate service.
+#[derive(Debug, Clone)]
+pub struct AggregationRoot<const N_CURRENCIES: usize> {
+    root: Node<N_CURRENCIES>,
+    depth: usize,
+    cryptocurrencies: Vec<Cryptocurrency>,
+}
 
-        #[allow(clippy::needless_range_loop)]
-        for level in 0..self.depth {
-            let position = current_index % 2;
-            path_indices[level] = Fp::from(position as u64);
+impl<const N_CURRENCIES: usize> AggregationRoot<N_CURRENCIES> {
+    pub fn root(&self) -> &Node<N_CURRENCIES> {
+        &self.root
+    }
 
-            let sibling_index = current_index - position + (1 - position);
-            if sibling_index < self.nodes[level].len() && level != 0 {
-                // Fetch hash preimage for sibling middle nodes
-                let sibling_node_preimage =
-                    self.get_middle_node_hash_preimage(level, sibling_index)?;
-                sibling_middle_node_hash_preimages.push(sibling_node_preimage);
-            }
-            current_index /= 2;
-        }
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
 
-        partial_proof.path_indices.extend(path_indices);
-        partial_proof
-            .sibling_middle_node_hash_preimages
-            .extend(sibling_middle_node_hash_preimages);
+    pub fn cryptocurrencies(&self) -> &[Cryptocurrency] {
+        &self.cryptocurrencies
+    }
 
-        // replace the root of the partial proof with the root of the aggregation tree
-        partial_proof.root = self.root.clone();
+    /// Returns the total balance of each currency over all entries of the round, in the order of `cryptocurrencies`.
+    pub fn total_balances(&self) -> &[Fp; N_CURRENCIES] {
+        &self.root.balances
+    }
 
-        Ok(partial_proof)
+    /// Returns `total_balances` as `BigUint`, e.g. to report the liabilities of the round.
+    pub fn total_balances_as_big_uint(&self) -> [BigUint; N_CURRENCIES] {
+        self.root.balances.map(fp_to_big_uint)
     }
 }
 
+// The part of a proof from the root of a mini tree to the root of the aggregation tree,
+// shared by all entries of the mini tree
+struct TopProof<const N_CURRENCIES: usize>
+where
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    sibling_middle_node_hash_preimages: Vec<[Fp; N_CURRENCIES + 2]>,
+    path_indices: Vec<Fp>,
+}
+
 impl<const N_CURRENCIES: usize, const N_BYTES: usize>
     AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>
 {
     /// Builds a AggregationMerkleSumTree from a set of mini MerkleSumTree
     /// The leaves of the AggregationMerkleSumTree are the roots of the mini MerkleSumTree
+    ///
+    /// Fails if `cryptocurrencies` does not hold N_CURRENCIES items, or if the mini trees differ in their cryptocurrencies.
+    /// Also fails if the root balances of a mini tree, or their accumulated balances, leave the range given by N_BYTES,
+    /// e.g. if a Worker built the mini tree with a larger N_BYTES.
     pub fn new(
         mini_trees: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>>,
         cryptocurrencies: Vec<Cryptocurrency>,
@@ -131,10 +206,13 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize>
         if mini_trees.is_empty() {
             return Err("Empty mini tree inputs".into());
         }
+        check_cryptocurrencies::<N_CURRENCIES, N_BYTES>(&cryptocurrencies, &mini_trees)?;
+        for (i, mini_tree) in mini_trees.iter().enumerate() {
+            check_mini_tree_range(i, mini_tree, &cryptocurrencies)?;
+        }
 
-        // assert that all mini trees have the same depth
-        let depth = mini_trees[0].depth();
-        assert!(mini_trees.iter().all(|x| x.depth() == depth));
+        // the mini trees may differ in depth, so their first entries are located by offset
+        let mini_tree_offsets = mini_tree_offsets(&mini_trees);
 
         // extract all the roots of the mini trees
         let roots = mini_trees
@@ -142,19 +220,38 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize>
             .map(|x| x.root().clone())
             .collect::<Vec<Node<N_CURRENCIES>>>();
 
-        let depth = (roots.len() as f64).log2().ceil() as usize;
+        let depth = roots.len().next_power_of_two().trailing_zeros() as usize;
 
-        // Calculate the accumulated balances for each asset
-        let mut balances_acc: Vec<Fp> = vec![Fp::from(0); N_CURRENCIES];
+        // Calculate the accumulated balances for each asset, reducing the roots in parallel
+        let balances_acc = roots
+            .par_iter()
+            .fold(
+                || [Fp::from(0); N_CURRENCIES],
+                |mut balances_acc, root| {
+                    for (i, balance) in root.balances.iter().enumerate() {
+                        balances_acc[i] += *balance;
+                    }
+                    balances_acc
+                },
+            )
+            .reduce(
+                || [Fp::from(0); N_CURRENCIES],
+                |mut balances_acc, balances| {
+                    for (i, balance) in balances.iter().enumerate() {
+                        balances_acc[i] += *balance;
+                    }
+                    balances_acc
+                },
+            );
 
-        for root in &roots {
-            for (i, balance) in root.balances.iter().enumerate() {
-                balances_acc[i] += *balance;
-            }
-        }
+        check_balances_range::<N_CURRENCIES, N_BYTES>(&balances_acc, &cryptocurrencies)?;
+
+        // Pad the leaves to a power of two, the padding leaves adding nothing to the balances
+        let mut roots = roots;
+        roots.resize(1 << depth, padding_leaf());
 
         let mut nodes = vec![];
-        let root = build_merkle_tree_from_leaves(&roots, depth, &mut nodes)?;
+        let root = build_merkle_tree_from_leaves_parallel(&roots, depth, &mut nodes)?;
 
         Ok(AggregationMerkleSumTree {
             root,
@@ -162,136 +259,1558 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize>
             depth,
             cryptocurrencies,
             mini_trees,
+            mini_tree_offsets,
+            user_indices: OnceLock::new(),
         })
     }
 
-    pub fn mini_tree(&self, tree_index: usize) -> &MerkleSumTree<N_CURRENCIES, N_BYTES> {
-        &self.mini_trees[tree_index]
+    /// Builds the mini trees from the batches of entries locally, in parallel, and aggregates them with `new`,
+    /// e.g. for tests or small rounds that do not need Workers.
+    ///
+    /// Every mini tree has a depth of `chunk_depth`, its batch being padded with empty entries.
+    /// Fails if a batch holds more than `2^chunk_depth` entries, or if one of its entries is invalid, see `JsonEntry::to_entry`.
+    pub fn from_entries(
+        entries: Vec<Vec<JsonEntry>>,
+        chunk_depth: usize,
+        cryptocurrencies: Vec<Cryptocurrency>,
+    ) -> Result<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let chunk_size = 1 << chunk_depth;
+
+        // The errors are converted to strings, as they are not `Send`
+        let mini_trees = entries
+            .into_par_iter()
+            .enumerate()
+            .map(|(batch_index, json_entries)| {
+                if json_entries.len() > chunk_size {
+                    return Err(format!(
+                        "Batch {} holds {} entries, more than the {} of a mini tree of depth {}",
+                        batch_index,
+                        json_entries.len(),
+                        chunk_size,
+                        chunk_depth
+                    ));
+                }
+                let mut entries = json_entries
+                    .iter()
+                    .map(|json_entry| json_entry.to_entry::<N_CURRENCIES>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Batch {}: {}", batch_index, e))?;
+                entries.resize(chunk_size, Entry::init_empty());
+                MerkleSumTree::from_entries(entries, cryptocurrencies.clone(), false)
+                    .map_err(|e| format!("Batch {}: {}", batch_index, e))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Self::new(mini_trees, cryptocurrencies)
     }
 
-    /// starting from a user_index, returns the index of the mini tree in which the entry is located and the index of the entry within the mini tree
-    fn get_entry_location(&self, user_index: usize) -> (usize, usize) {
-        let entries_per_mini_tree = 1 << self.mini_trees[0].depth();
+    /// Appends a mini tree as a new leaf, recomputing only the nodes on its path to the root.
+    ///
+    /// When the number of mini trees exceeds a power of two, the depth grows by one:
+    /// the previous root becomes the left child of the new root, and the right half of the tree is empty.
+    /// Empty leaves are `padding_leaf` nodes, as in a tree built with `new`, until they are replaced by appended mini trees.
+    ///
+    /// Fails without modifying the tree if the root balances of the mini tree or the accumulated balances
+    /// would leave the range given by N_BYTES, or if the cryptocurrencies of the mini tree differ from those of the mini trees in the tree.
+    pub fn push_mini_tree(
+        &mut self,
+        mini_tree: MerkleSumTree<N_CURRENCIES, N_BYTES>,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if !same_cryptocurrencies(
+            mini_tree.cryptocurrencies(),
+            self.mini_trees[0].cryptocurrencies(),
+        ) {
+            return Err(format!(
+                "Cryptocurrencies of mini tree {} differ from those of mini tree 0",
+                self.mini_trees.len()
+            )
+            .into());
+        }
+        check_mini_tree_range(self.mini_trees.len(), &mini_tree, &self.cryptocurrencies)?;
 
-        // Calculate which mini tree the entry is in
-        let mini_tree_index = user_index / entries_per_mini_tree;
+        // The root accumulates the balances of all leaves, the empty ones adding nothing
+        let mut balances_acc = self.root.balances;
+        for (i, balance) in mini_tree.root().balances.iter().enumerate() {
+            balances_acc[i] += *balance;
+        }
+        check_balances_range::<N_CURRENCIES, N_BYTES>(&balances_acc, &self.cryptocurrencies)?;
 
-        // Calculate the index within the mini tree
-        let entry_index = user_index % entries_per_mini_tree;
+        let index = self.mini_trees.len();
+        if index == 1 << self.depth {
+            self.grow();
+        }
 
-        (mini_tree_index, entry_index)
+        // Replace the empty leaf and rehash its path to the root
+        self.nodes[0][index] = mini_tree.root().clone();
+        for level in 1..=self.depth {
+            let node_index = index >> level;
+            self.nodes[level][node_index] = Node::middle(
+                &self.nodes[level - 1][2 * node_index],
+                &self.nodes[level - 1][2 * node_index + 1],
+            );
+        }
+        self.root = self.nodes[self.depth][0].clone();
+
+        let offset = self.mini_tree_offsets[index - 1] + (1 << self.mini_trees[index - 1].depth());
+        if let Some(user_indices) = self.user_indices.get_mut() {
+            index_usernames(user_indices, &mini_tree, offset);
+        }
+        self.mini_tree_offsets.push(offset);
+        self.mini_trees.push(mini_tree);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use summa_backend::merkle_sum_tree::{MerkleSumTree, Tree};
+    /// Appends the mini trees one by one with `push_mini_tree`, stopping at the first one that fails.
+    pub fn extend(
+        &mut self,
+        mini_trees: impl IntoIterator<Item = MerkleSumTree<N_CURRENCIES, N_BYTES>>,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        for mini_tree in mini_trees {
+            self.push_mini_tree(mini_tree)?;
+        }
+        Ok(())
+    }
 
-    use crate::aggregation_merkle_sum_tree::AggregationMerkleSumTree;
+    // Doubles the number of leaves, the new right half of every level being the root of an empty subtree
+    fn grow(&mut self)
+    where
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let mut empty_node = padding_leaf();
+        for level in 0..=self.depth {
+            let level_len = self.nodes[level].len();
+            self.nodes[level].resize(2 * level_len, empty_node.clone());
+            empty_node = Node::middle(&empty_node, &empty_node);
+        }
 
-    const N_CURRENCIES: usize = 2;
-    const N_BYTES: usize = 8;
+        self.depth += 1;
+        let root = Node::middle(
+            &self.nodes[self.depth - 1][0],
+            &self.nodes[self.depth - 1][1],
+        );
+        self.nodes.push(vec![root]);
+    }
 
-    #[test]
-    fn test_aggregation_mst() {
-        // create new mini merkle sum tree
-        let mini_tree_1 =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+    /// Estimates the peak memory in bytes taken by an AggregationMerkleSumTree over `n_mini_trees` mini trees
+    /// of `entries_per_tree` entries each, including their padding to a power of two.
+    ///
+    /// The estimate covers the nodes and entries of the mini trees, and the roots and nodes of the aggregation tree.
+    /// The heap allocated by the entries, e.g. for the usernames, is approximated.
+    pub fn estimate_memory(n_mini_trees: usize, entries_per_tree: usize) -> usize {
+        let node_bytes = size_of::<Node<N_CURRENCIES>>();
+        let entry_bytes =
+            size_of::<Entry<N_CURRENCIES>>() + ENTRY_HEAP_BYTES + N_CURRENCIES * BALANCE_HEAP_BYTES;
 
-        let mini_tree_2 =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+        // A tree of `n` leaves has `2n - 1` nodes
+        let leaves_per_tree = entries_per_tree.next_power_of_two();
+        let mini_tree_bytes =
+            (2 * leaves_per_tree - 1) * node_bytes + leaves_per_tree * entry_bytes;
 
-        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
-            vec![mini_tree_1.clone(), mini_tree_2.clone()],
-            mini_tree_1.cryptocurrencies().to_owned().to_vec(),
-        )
-        .unwrap();
+        let aggregation_leaves = n_mini_trees.next_power_of_two();
+        let aggregation_bytes =
+            n_mini_trees * node_bytes + (2 * aggregation_leaves - 1) * node_bytes;
 
-        // get root
-        let root = aggregation_mst.root();
+        n_mini_trees
+            .saturating_mul(mini_tree_bytes)
+            .saturating_add(aggregation_bytes)
+    }
 
-        // expect root hash to be different than 0
-        assert!(root.hash != 0.into());
-        // expect balance to match the sum of all entries
-        assert!(root.balances == [(556862 * 2).into(), (556862 * 2).into()]);
+    /// Builds a AggregationMerkleSumTree from its precomputed parts, e.g. when loading it with `load_from_file`
+    pub fn from_params(
+        root: Node<N_CURRENCIES>,
+        nodes: Vec<Vec<Node<N_CURRENCIES>>>,
+        depth: usize,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        mini_trees: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>>,
+    ) -> Result<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn std::error::Error>> {
+        if mini_trees.is_empty() {
+            return Err("Empty mini tree inputs".into());
+        }
 
-        // expect depth to be equal to merkle_sum_tree_1.depth (= merkle_sum_tree_2.depth) + 1
-        let depth = aggregation_mst.depth();
+        let mini_tree_offsets = mini_tree_offsets(&mini_trees);
+        Ok(AggregationMerkleSumTree {
+            root,
+            nodes,
+            depth,
+            cryptocurrencies,
+            mini_trees,
+            mini_tree_offsets,
+            user_indices: OnceLock::new(),
+        })
+    }
 
-        assert!(*depth == 1);
+    /// Writes the tree, including its mini trees, to a JSON file.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        self.write_json(BufWriter::new(File::create(path)?))
+    }
 
-        let mut index = rand::random::<usize>() % 32;
+    /// Writes the tree, including its mini trees, as a `JsonAggregationMerkleSumTree` to any writer,
+    /// e.g. to send it to another service.
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        serde_json::to_writer(writer, &JsonAggregationMerkleSumTree::from_tree(self))?;
+        Ok(())
+    }
 
-        // the entry fetched from the aggregation tree should be the same as the entry fetched from the corresponding mini tree
-        let entry = aggregation_mst.get_entry(index);
+    /// Reads a tree written by `write_json` or `save_to_file`, rebuilding it from its mini trees to check it,
+    /// see `JsonAggregationMerkleSumTree::to_verified_aggregation_mst`.
+    pub fn read_json<R: Read>(reader: R) -> Result<Self, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let json_tree: JsonAggregationMerkleSumTree = serde_json::from_reader(reader)?;
+        json_tree.to_verified_aggregation_mst()
+    }
 
-        if index < 16 {
-            assert!(entry.username() == mini_tree_1.get_entry(index).username());
-            assert!(entry.balances() == mini_tree_1.get_entry(index).balances());
+    /// Reads a tree written by `save_to_file`, without recomputing any of its nodes.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let reader = BufReader::new(File::open(path)?);
+        let json_tree: JsonAggregationMerkleSumTree = serde_json::from_reader(reader)?;
+        json_tree.to_aggregation_mst()
+    }
+
+    /// Returns the total balance of each currency over all entries of the round, in the order of `cryptocurrencies`.
+    ///
+    /// These are the balances of the root, which accumulates the balances of all mini trees.
+    /// They are checked to lie in the range given by N_BYTES when the tree is built or a mini tree is pushed.
+    pub fn total_balances(&self) -> &[Fp; N_CURRENCIES] {
+        &self.root.balances
+    }
+
+    /// Returns `total_balances` as `BigUint`, e.g. to report the liabilities of the round.
+    pub fn total_balances_as_big_uint(&self) -> [BigUint; N_CURRENCIES] {
+        self.root.balances.map(fp_to_big_uint)
+    }
+
+    /// Checks that the total balances of the round equal `expected_totals`, e.g. the sums computed independently from the CSV files.
+    ///
+    /// Fails with every currency whose total differs, along with its delta.
+    pub fn verify_round(&self, expected_totals: &[BigUint; N_CURRENCIES]) -> Result<(), Mismatch> {
+        let currencies = self
+            .total_balances_as_big_uint()
+            .into_iter()
+            .zip(expected_totals)
+            .enumerate()
+            .filter(|(_, (actual, expected))| actual != *expected)
+            .map(|(i, (actual, expected))| CurrencyMismatch {
+                currency_index: i,
+                currency: currency_name(i, &self.cryptocurrencies),
+                expected: expected.clone(),
+                actual,
+            })
+            .collect::<Vec<_>>();
+        if currencies.is_empty() {
+            Ok(())
         } else {
-            index -= 16;
-            assert!(entry.username() == mini_tree_2.get_entry(index).username());
-            assert!(entry.balances() == mini_tree_2.get_entry(index).balances());
+            Err(Mismatch { currencies })
         }
+    }
 
-        // Generate proof for the entry
-        let proof = aggregation_mst.generate_proof(index).unwrap();
+    /// Drops the nodes and mini trees, keeping only the root, depth and cryptocurrencies of the tree.
+    ///
+    /// The mini trees hold every entry of the round, so this frees most of the memory of the tree
+    /// once it is no longer needed for `get_entry` or `generate_proof`.
+    pub fn into_root_only(self) -> AggregationRoot<N_CURRENCIES> {
+        AggregationRoot {
+            root: self.root,
+            depth: self.depth,
+            cryptocurrencies: self.cryptocurrencies,
+        }
+    }
 
-        // verify proof
-        assert!(aggregation_mst.verify_proof(&proof));
+    pub fn mini_tree(&self, tree_index: usize) -> &MerkleSumTree<N_CURRENCIES, N_BYTES> {
+        &self.mini_trees[tree_index]
     }
 
-    #[test]
-    fn test_aggregation_mst_compare_mst_result() {
-        // create new mini merkle sum tree
-        let mut mini_trees = Vec::new();
-        for i in 1..=4 {
-            let mini_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!(
-                "csv/entry_16_{}.csv",
-                i
-            ))
-            .unwrap();
-            mini_trees.push(mini_tree);
-        }
-        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_owned().to_vec();
-        let aggregation_mst =
-            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
-                .unwrap();
+    pub fn mini_trees(&self) -> &[MerkleSumTree<N_CURRENCIES, N_BYTES>] {
+        &self.mini_trees
+    }
 
-        let aggregation_mst_root = aggregation_mst.root();
+    /// Returns the index of the entry of `username`, as used by `get_entry` and `generate_proof`,
+    /// or `None` if no mini tree contains it.
+    ///
+    /// The usernames are indexed on the first lookup, so later lookups do not search the mini trees.
+    /// If a username appears more than once, the index of its first entry is returned.
+    /// The padding entries are not indexed, so the empty username is never found.
+    pub fn index_of_username(&self, username: &str) -> Option<usize> {
+        self.user_indices
+            .get_or_init(|| {
+                let mut user_indices = HashMap::new();
+                for (mini_tree, offset) in self.mini_trees.iter().zip(&self.mini_tree_offsets) {
+                    index_usernames(&mut user_indices, mini_tree, *offset);
+                }
+                user_indices
+            })
+            .get(username)
+            .copied()
+    }
 
-        // The entry_64.csv file is the aggregation of entry_16_1, entry_16_2, entry_16_3, entry_16_4
-        let single_merkle_sum_tree =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_64.csv").unwrap();
+    /// Returns the index of the mini tree holding the entry at `user_index`, and the index of the entry within that mini tree.
+    ///
+    /// E.g. with mini trees built from one CSV file each, these are the index of the file and the row of the entry in it.
+    /// Like `get_entry`, expects an index below the number of entries, including the padding entries of each mini tree.
+    pub fn locate(&self, user_index: usize) -> (usize, usize) {
+        // Calculate which mini tree the entry is in, namely the last one starting at or before the entry
+        let mini_tree_index = self
+            .mini_tree_offsets
+            .partition_point(|offset| *offset <= user_index)
+            - 1;
 
-        assert_eq!(
-            aggregation_mst_root.hash,
-            single_merkle_sum_tree.root().hash
-        );
+        // Calculate the index within the mini tree
+        let entry_index = user_index - self.mini_tree_offsets[mini_tree_index];
+
+        (mini_tree_index, entry_index)
     }
 
-    #[test]
-    fn test_aggregation_mst_overflow() {
-        // create new mini merkle sum tree. The accumulated balance for each mini tree is in the expected range
-        // note that the accumulated balance of the tree generated from entry_16_4 is just in the expected range for 1 unit
-        let merkle_sum_tree_1 =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16.csv").unwrap();
+    /// Returns the mini tree and local index of the entry of `username`, see `locate` and `index_of_username`.
+    pub fn locate_username(&self, username: &str) -> Option<(usize, usize)> {
+        self.index_of_username(username)
+            .map(|user_index| self.locate(user_index))
+    }
 
-        let merkle_sum_tree_2 =
-            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_no_overflow.csv")
-                .unwrap();
+    /// Generates the proof of inclusion of the entry of `username`, see `index_of_username`.
+    pub fn generate_proof_for_username(
+        &self,
+        username: &str,
+    ) -> Result<MerkleProof<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let index = self
+            .index_of_username(username)
+            .ok_or_else(|| format!("Username {:?} not found", username))?;
+        self.generate_proof(index)
+    }
 
-        // When creating the aggregation merkle sum tree, the accumulated balance of the two mini trees is not in the expected range, an error is thrown
-        let result = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
-            vec![merkle_sum_tree_1, merkle_sum_tree_2.clone()],
-            merkle_sum_tree_2.cryptocurrencies().to_vec(),
-        );
+    /// Generates the proofs of the entries at `indices`, in the same order, like calling `generate_proof` for each of them.
+    ///
+    /// The part of the proof above the mini tree is computed once per mini tree and shared by its entries,
+    /// and the proofs are generated in parallel. Fails if the proof of any of the indices cannot be generated.
+    pub fn generate_proofs(
+        &self,
+        indices: &[usize],
+    ) -> Result<Vec<MerkleProof<N_CURRENCIES, N_BYTES>>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let locations = indices
+            .iter()
+            .map(|index| self.locate(*index))
+            .collect::<Vec<_>>();
 
-        if let Err(e) = result {
-            assert_eq!(
-                e.to_string(),
-                "Accumulated balance is not in the expected range, proof generation will fail!"
-            );
-        }
+        let mut mini_tree_indices = locations
+            .iter()
+            .map(|(mini_tree_index, _)| *mini_tree_index)
+            .collect::<Vec<_>>();
+        mini_tree_indices.sort_unstable();
+        mini_tree_indices.dedup();
+
+        // The errors are converted to strings, as they are not `Send`
+        let top_proofs = mini_tree_indices
+            .into_par_iter()
+            .map(|mini_tree_index| {
+                self.top_proof(mini_tree_index)
+                    .map(|top_proof| (mini_tree_index, top_proof))
+                    .map_err(|e| e.to_string())
+            })
+            .collect::<Result<HashMap<_, _>, String>>()?;
+
+        let proofs = locations
+            .into_par_iter()
+            .map(|(mini_tree_index, entry_index)| {
+                let mut partial_proof = self.mini_trees[mini_tree_index]
+                    .generate_proof(entry_index)
+                    .map_err(|e| e.to_string())?;
+                self.complete_proof(&mut partial_proof, &top_proofs[&mini_tree_index]);
+                Ok(partial_proof)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(proofs)
+    }
+
+    /// Writes the proofs of the entries at `indices` to `writer` as JSON lines, one `JsonMerkleProof` per line in the order of `indices`.
+    ///
+    /// The proofs are generated with `generate_proofs` on `concurrency` threads, in batches of 256 proofs per thread,
+    /// so that only one batch is held in memory however many proofs are exported. Returns the number of proofs written.
+    pub fn export_proofs<W: Write>(
+        &self,
+        indices: &[usize],
+        concurrency: usize,
+        writer: W,
+    ) -> Result<usize, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if concurrency == 0 {
+            return Err("The concurrency of the proof export must be at least 1".into());
+        }
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(concurrency)
+            .build()?;
+
+        let mut writer = BufWriter::new(writer);
+        for batch in indices.chunks(concurrency * EXPORT_PROOFS_PER_THREAD) {
+            // The errors are converted to strings, as they are not `Send`
+            let proofs =
+                thread_pool.install(|| self.generate_proofs(batch).map_err(|e| e.to_string()))?;
+            for proof in &proofs {
+                serde_json::to_writer(&mut writer, &JsonMerkleProof::from_proof(proof))?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(indices.len())
+    }
+
+    // Builds the part of the proofs above the mini tree at `mini_tree_index`, namely from its root to the root of the aggregation tree
+    fn top_proof(&self, mini_tree_index: usize) -> Result<TopProof<N_CURRENCIES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        // A single mini tree is the whole aggregation tree, there is nothing above its root
+        if self.depth == 0 {
+            return Ok(TopProof {
+                sibling_middle_node_hash_preimages: Vec::new(),
+                path_indices: Vec::new(),
+            });
+        }
+
+        // Retrieve sibling mini tree
+        let sibling_mini_tree_index = if mini_tree_index % 2 == 0 {
+            mini_tree_index + 1
+        } else {
+            mini_tree_index - 1
+        };
+
+        let mut sibling_middle_node_hash_preimages = Vec::new();
+
+        // Retrieve sibling mini tree root hash preimage, the sibling of the last mini tree may be a padding leaf
+        let sibling_mini_tree_node_preimage = match self.mini_trees.get(sibling_mini_tree_index) {
+            Some(sibling_mini_tree) => {
+                sibling_mini_tree.get_middle_node_hash_preimage(*sibling_mini_tree.depth(), 0)?
+            }
+            None => [Fp::from(0); N_CURRENCIES + 2],
+        };
+
+        sibling_middle_node_hash_preimages.push(sibling_mini_tree_node_preimage);
+
+        let mut current_index = mini_tree_index;
+        let mut path_indices = vec![Fp::from(0); self.depth];
+
+        #[allow(clippy::needless_range_loop)]
+        for level in 0..self.depth {
+            let position = current_index % 2;
+            path_indices[level] = Fp::from(position as u64);
+
+            let sibling_index = current_index - position + (1 - position);
+            if sibling_index < self.nodes[level].len() && level != 0 {
+                // Fetch hash preimage for sibling middle nodes
+                let sibling_node_preimage =
+                    self.get_middle_node_hash_preimage(level, sibling_index)?;
+                sibling_middle_node_hash_preimages.push(sibling_node_preimage);
+            }
+            current_index /= 2;
+        }
+
+        Ok(TopProof {
+            sibling_middle_node_hash_preimages,
+            path_indices,
+        })
+    }
+
+    // Extends the proof of an entry within its mini tree to the root of the aggregation tree
+    fn complete_proof(
+        &self,
+        partial_proof: &mut MerkleProof<N_CURRENCIES, N_BYTES>,
+        top_proof: &TopProof<N_CURRENCIES>,
+    ) where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        partial_proof
+            .path_indices
+            .extend_from_slice(&top_proof.path_indices);
+        partial_proof
+            .sibling_middle_node_hash_preimages
+            .extend_from_slice(&top_proof.sibling_middle_node_hash_preimages);
+
+        // replace the root of the partial proof with the root of the aggregation tree
+        partial_proof.root = self.root.clone();
+    }
+}
+
+// The tree must hold N_CURRENCIES cryptocurrencies, and all mini trees the same ones
+fn check_cryptocurrencies<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    cryptocurrencies: &[Cryptocurrency],
+    mini_trees: &[MerkleSumTree<N_CURRENCIES, N_BYTES>],
+) -> Result<(), Box<dyn Error>> {
+    if cryptocurrencies.len() != N_CURRENCIES {
+        return Err(format!(
+            "Expected {} cryptocurrencies, got {}",
+            N_CURRENCIES,
+            cryptocurrencies.len()
+        )
+        .into());
+    }
+    for (i, mini_tree) in mini_trees.iter().enumerate().skip(1) {
+        if !same_cryptocurrencies(
+            mini_tree.cryptocurrencies(),
+            mini_trees[0].cryptocurrencies(),
+        ) {
+            return Err(format!(
+                "Cryptocurrencies of mini tree {} differ from those of mini tree 0",
+                i
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn same_cryptocurrencies(a: &[Cryptocurrency], b: &[Cryptocurrency]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.name == b.name && a.chain == b.chain)
+}
+
+// Each accumulated balance must lie in the range given by N_BYTES, like in the mini trees.
+// The error names the first currency out of range, and by how much it exceeds the range.
+fn check_balances_range<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    balances_acc: &[Fp; N_CURRENCIES],
+    cryptocurrencies: &[Cryptocurrency],
+) -> Result<(), Box<dyn Error>> {
+    for (i, balance) in balances_acc.iter().enumerate() {
+        let balance = fp_to_big_uint(*balance);
+        if let Some(error) =
+            total_balance_overflow::<N_BYTES>("Accumulated", i, &balance, cryptocurrencies)
+        {
+            return Err(error.into());
+        }
+    }
+    Ok(())
+}
+
+// The error for a total balance of the currency at `index` beyond the range given by N_BYTES, `None` if it is in range.
+// `Orchestrator::validate` reports its total balances with it as well, so both checks word an overflow alike.
+pub(crate) fn total_balance_overflow<const N_BYTES: usize>(
+    kind: &str,
+    index: usize,
+    balance: &BigUint,
+    cryptocurrencies: &[Cryptocurrency],
+) -> Option<String> {
+    let max_balance = max_balance::<N_BYTES>();
+    (*balance > max_balance).then(|| {
+        format!(
+            "{} balance of {} is {}, which exceeds the maximum of 2^{} - 1 = {} by {}, \
+             proof generation will fail!",
+            kind,
+            currency_name(index, cryptocurrencies),
+            balance,
+            8 * N_BYTES,
+            max_balance,
+            balance - &max_balance
+        )
+    })
+}
+
+// Each mini tree is built by a Worker, which may assume a larger N_BYTES than the aggregation tree.
+// Its overflowing root balances would only be caught above if the accumulated balances happen to overflow.
+fn check_mini_tree_range<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    mini_tree_index: usize,
+    mini_tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>,
+    cryptocurrencies: &[Cryptocurrency],
+) -> Result<(), Box<dyn Error>> {
+    let max_balance = max_balance::<N_BYTES>();
+    for (i, balance) in mini_tree.root().balances.iter().enumerate() {
+        let balance = fp_to_big_uint(*balance);
+        if balance > max_balance {
+            return Err(format!(
+                "Root balance of {} in mini tree {} is {}, which exceeds the maximum of 2^{} - 1 = {}, \
+                 was the mini tree built with a larger N_BYTES?",
+                currency_name(i, cryptocurrencies),
+                mini_tree_index,
+                balance,
+                8 * N_BYTES,
+                max_balance
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn max_balance<const N_BYTES: usize>() -> BigUint {
+    BigUint::from(2_usize).pow(8 * N_BYTES as u32) - 1_u32
+}
+
+fn currency_name(index: usize, cryptocurrencies: &[Cryptocurrency]) -> String {
+    match cryptocurrencies.get(index) {
+        Some(cryptocurrency) => format!(
+            "currency {} ({} on {})",
+            index, cryptocurrency.name, cryptocurrency.chain
+        ),
+        None => format!("currency {}", index),
+    }
+}
+
+/// Builds the nodes of a Merkle Sum Tree from its leaves like `build_merkle_tree_from_leaves` of summa-backend,
+/// but hashes the nodes of each level in parallel. Returns the root.
+pub fn build_merkle_tree_from_leaves_parallel<const N_CURRENCIES: usize>(
+    leaves: &[Node<N_CURRENCIES>],
+    depth: usize,
+    tree: &mut Vec<Vec<Node<N_CURRENCIES>>>,
+) -> Result<Node<N_CURRENCIES>, Box<dyn Error>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    if leaves.len() != 1 << depth {
+        return Err(format!(
+            "Expected {} leaves for a tree of depth {}, got {}",
+            1usize << depth,
+            depth,
+            leaves.len()
+        )
+        .into());
+    }
+
+    tree.clear();
+    tree.push(leaves.to_vec());
+    for level in 1..=depth {
+        let next_level = tree[level - 1]
+            .par_chunks_exact(2)
+            .map(|children| Node::middle(&children[0], &children[1]))
+            .collect();
+        tree.push(next_level);
+    }
+
+    Ok(tree[depth][0].clone())
+}
+
+/// The leaf padding the Aggregation Merkle Sum Tree to a power of two leaves, a node with zero balances
+/// hashed like a middle node from a preimage of zeros.
+///
+/// Unlike a node with a zero hash, it can be the sibling in a proof, which is given by the preimage of the sibling hash.
+pub fn padding_leaf<const N_CURRENCIES: usize>() -> Node<N_CURRENCIES>
+where
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    Node::middle_node_from_preimage(&[Fp::from(0); N_CURRENCIES + 2])
+}
+
+// Index of the first entry of each mini tree, counting the padding entries
+fn mini_tree_offsets<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    mini_trees: &[MerkleSumTree<N_CURRENCIES, N_BYTES>],
+) -> Vec<usize> {
+    mini_trees
+        .iter()
+        .scan(0, |offset, mini_tree| {
+            let mini_tree_offset = *offset;
+            *offset += 1 << mini_tree.depth();
+            Some(mini_tree_offset)
+        })
+        .collect()
+}
+
+// Indexes the first entry of each username of the mini tree starting at `offset`, skipping its padding entries
+fn index_usernames<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    user_indices: &mut HashMap<String, usize>,
+    mini_tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>,
+    offset: usize,
+) {
+    for (entry_index, entry) in mini_tree.entries().iter().enumerate() {
+        if !entry.username().is_empty() {
+            user_indices
+                .entry(entry.username().to_string())
+                .or_insert(offset + entry_index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::halo2curves::bn256::Fr as Fp;
+    use num_bigint::{BigInt, BigUint};
+    use summa_backend::merkle_sum_tree::{
+        utils::parse_csv_to_entries, Cryptocurrency, Entry, MerkleSumTree, Tree,
+    };
+
+    use crate::aggregation_merkle_sum_tree::{
+        build_merkle_tree_from_leaves_parallel, padding_leaf, same_cryptocurrencies,
+        AggregationMerkleSumTree,
+    };
+    use crate::json_mst::{JsonAggregationMerkleSumTree, JsonEntry, JsonMerkleProof};
+
+    const N_CURRENCIES: usize = 2;
+    const N_BYTES: usize = 8;
+
+    #[test]
+    fn test_aggregation_mst() {
+        // create new mini merkle sum tree
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2.clone()],
+            mini_tree_1.cryptocurrencies().to_owned().to_vec(),
+        )
+        .unwrap();
+
+        // get root
+        let root = aggregation_mst.root();
+
+        // expect root hash to be different than 0
+        assert!(root.hash != 0.into());
+        // expect balance to match the sum of all entries
+        assert!(root.balances == [(556862 * 2).into(), (556862 * 2).into()]);
+        assert_eq!(aggregation_mst.total_balances(), &root.balances);
+        assert_eq!(
+            aggregation_mst.total_balances_as_big_uint(),
+            [BigUint::from(556862_u32 * 2), BigUint::from(556862_u32 * 2)]
+        );
+
+        // expect depth to be equal to merkle_sum_tree_1.depth (= merkle_sum_tree_2.depth) + 1
+        let depth = aggregation_mst.depth();
+
+        assert!(*depth == 1);
+
+        let mut index = rand::random::<usize>() % 32;
+
+        // the entry fetched from the aggregation tree should be the same as the entry fetched from the corresponding mini tree
+        let entry = aggregation_mst.get_entry(index);
+
+        if index < 16 {
+            assert!(entry.username() == mini_tree_1.get_entry(index).username());
+            assert!(entry.balances() == mini_tree_1.get_entry(index).balances());
+        } else {
+            index -= 16;
+            assert!(entry.username() == mini_tree_2.get_entry(index).username());
+            assert!(entry.balances() == mini_tree_2.get_entry(index).balances());
+        }
+
+        // Generate proof for the entry
+        let proof = aggregation_mst.generate_proof(index).unwrap();
+
+        // verify proof
+        assert!(aggregation_mst.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_aggregation_mst_from_entries() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+        let cryptocurrencies = mini_tree_1.cryptocurrencies().to_vec();
+        let batches = [&mini_tree_1, &mini_tree_2]
+            .iter()
+            .map(|mini_tree| {
+                mini_tree
+                    .entries()
+                    .iter()
+                    .map(JsonEntry::from_entry)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            batches.clone(),
+            4,
+            cryptocurrencies.clone(),
+        )
+        .unwrap();
+        let expected_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1, mini_tree_2],
+            cryptocurrencies.clone(),
+        )
+        .unwrap();
+        assert_eq!(aggregation_mst.root().hash, expected_mst.root().hash);
+
+        // A deeper chunk pads the mini trees with empty entries
+        let padded_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            batches.clone(),
+            5,
+            cryptocurrencies.clone(),
+        )
+        .unwrap();
+        assert_eq!(*padded_mst.mini_tree(1).depth(), 5);
+        assert_eq!(padded_mst.root().balances, expected_mst.root().balances);
+        assert_eq!(padded_mst.get_entry(32).username(), batches[1][0].username);
+        // The padding entries are not indexed by username
+        assert_eq!(
+            padded_mst.index_of_username(&batches[1][0].username),
+            Some(32)
+        );
+        assert_eq!(padded_mst.get_entry(16).username(), "");
+        assert_eq!(padded_mst.index_of_username(""), None);
+
+        let err = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            batches,
+            3,
+            cryptocurrencies,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Batch 0 holds 16 entries, more than the 8 of a mini tree of depth 3"
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_verify_round() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2],
+            mini_tree_1.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+
+        // Sum the balances of the CSV files independently of the tree
+        let mut expected_totals = [BigUint::from(0_u32), BigUint::from(0_u32)];
+        for path in ["csv/entry_16_1.csv", "csv/entry_16_2.csv"] {
+            let (_, entries) = parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>(path).unwrap();
+            for entry in entries {
+                for (total, balance) in expected_totals.iter_mut().zip(entry.balances()) {
+                    *total += balance;
+                }
+            }
+        }
+        assert!(aggregation_mst.verify_round(&expected_totals).is_ok());
+
+        expected_totals[1] += 5_u32;
+        let mismatch = aggregation_mst.verify_round(&expected_totals).unwrap_err();
+        assert_eq!(mismatch.currencies.len(), 1);
+        assert_eq!(mismatch.currencies[0].currency_index, 1);
+        assert_eq!(mismatch.currencies[0].delta(), BigInt::from(-5));
+        assert_eq!(
+            mismatch.to_string(),
+            "Total balances differ from the expected ones: currency 1 (USDT on ETH) is 1113724, \
+             expected 1113729 (delta -5)"
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_compare_mst_result() {
+        // create new mini merkle sum tree
+        let mut mini_trees = Vec::new();
+        for i in 1..=4 {
+            let mini_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!(
+                "csv/entry_16_{}.csv",
+                i
+            ))
+            .unwrap();
+            mini_trees.push(mini_tree);
+        }
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_owned().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        let aggregation_mst_root = aggregation_mst.root();
+
+        // The entry_64.csv file is the aggregation of entry_16_1, entry_16_2, entry_16_3, entry_16_4
+        let single_merkle_sum_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_64.csv").unwrap();
+
+        assert_eq!(
+            aggregation_mst_root.hash,
+            single_merkle_sum_tree.root().hash
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_overflow() {
+        // create new mini merkle sum tree. The accumulated balance for each mini tree is in the expected range
+        // note that the accumulated balance of the tree generated from entry_16_4 is just in the expected range for 1 unit
+        let merkle_sum_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16.csv").unwrap();
+
+        let merkle_sum_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_no_overflow.csv")
+                .unwrap();
+
+        // When creating the aggregation merkle sum tree, the accumulated balance of the two mini trees is not in the expected range, an error is thrown
+        let result = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![merkle_sum_tree_1, merkle_sum_tree_2.clone()],
+            merkle_sum_tree_2.cryptocurrencies().to_vec(),
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Accumulated balance of currency 0 (ETH on ETH) is 18446744073710108477, \
+             which exceeds the maximum of 2^64 - 1 = 18446744073709551615 by 556862, \
+             proof generation will fail!"
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_overflow_boundary() {
+        let half = BigUint::from(2_u32).pow(8 * N_BYTES as u32 - 1);
+        let mini_tree = |usernames: [&str; 2], first_balance: BigUint| {
+            let entries = vec![
+                Entry::new(
+                    usernames[0].to_string(),
+                    [first_balance, BigUint::from(1_u32)],
+                )
+                .unwrap(),
+                Entry::new(
+                    usernames[1].to_string(),
+                    [BigUint::from(0_u32), BigUint::from(1_u32)],
+                )
+                .unwrap(),
+            ];
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+                entries,
+                vec![
+                    Cryptocurrency {
+                        name: "ETH".to_string(),
+                        chain: "ETH".to_string(),
+                    },
+                    Cryptocurrency {
+                        name: "USDT".to_string(),
+                        chain: "ETH".to_string(),
+                    },
+                ],
+                false,
+            )
+            .unwrap()
+        };
+        let mini_tree_1 = mini_tree(["alice", "bob"], half.clone());
+        let cryptocurrencies = mini_tree_1.cryptocurrencies().to_vec();
+
+        // The accumulated balance is exactly 2^(8 * N_BYTES) - 1, the largest one in range
+        let mini_tree_2 = mini_tree(["carol", "dave"], &half - 1_u32);
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2],
+            cryptocurrencies.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            aggregation_mst.total_balances_as_big_uint()[0],
+            BigUint::from(2_u32).pow(8 * N_BYTES as u32) - 1_u32
+        );
+
+        // One more unit is out of range
+        let mini_tree_2 = mini_tree(["carol", "dave"], half);
+        let err = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1, mini_tree_2],
+            cryptocurrencies,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Accumulated balance of currency 0 (ETH on ETH) is 18446744073709551616, \
+             which exceeds the maximum of 2^64 - 1 = 18446744073709551615 by 1, \
+             proof generation will fail!"
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_mini_tree_overflow() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+        let cryptocurrencies = mini_tree_1.cryptocurrencies().to_vec();
+
+        // A root balance of 2^64 could only come from a Worker with a larger N_BYTES
+        let mut root = mini_tree_2.root().clone();
+        root.balances[1] = Fp::from(u64::MAX) + Fp::from(1);
+        let overflowing_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_params(
+            root,
+            mini_tree_2.nodes().to_vec(),
+            *mini_tree_2.depth(),
+            mini_tree_2.entries().to_vec(),
+            cryptocurrencies.clone(),
+            false,
+        )
+        .unwrap();
+
+        let expected_error =
+            "Root balance of currency 1 (USDT on ETH) in mini tree 1 is 18446744073709551616, \
+             which exceeds the maximum of 2^64 - 1 = 18446744073709551615, \
+             was the mini tree built with a larger N_BYTES?";
+        let err = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), overflowing_tree.clone()],
+            cryptocurrencies.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), expected_error);
+
+        let mut aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1],
+            cryptocurrencies,
+        )
+        .unwrap();
+        let err = aggregation_mst
+            .push_mini_tree(overflowing_tree)
+            .unwrap_err();
+        assert_eq!(err.to_string(), expected_error);
+    }
+
+    #[test]
+    fn test_aggregation_mst_cryptocurrencies() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let cryptocurrencies = mini_tree_1.cryptocurrencies().to_vec();
+
+        // One cryptocurrency short of N_CURRENCIES
+        let result = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_1.clone()],
+            cryptocurrencies[..1].to_vec(),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Expected 2 cryptocurrencies, got 1"
+        );
+
+        // The same entries on another chain
+        let (_, entries) =
+            parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>("csv/entry_16_2.csv").unwrap();
+        let other_chain = cryptocurrencies
+            .iter()
+            .map(|cryptocurrency| Cryptocurrency {
+                name: cryptocurrency.name.clone(),
+                chain: "BTC".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, other_chain, false)
+                .unwrap();
+
+        let result = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2.clone()],
+            cryptocurrencies.clone(),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Cryptocurrencies of mini tree 1 differ from those of mini tree 0"
+        );
+
+        let mut aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1],
+            cryptocurrencies,
+        )
+        .unwrap();
+        assert!(aggregation_mst.push_mini_tree(mini_tree_2).is_err());
+        assert_eq!(aggregation_mst.mini_trees().len(), 1);
+    }
+
+    #[test]
+    fn test_aggregation_mst_differing_depths() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+
+        // A smaller last chunk of 4 entries, making a mini tree of depth 2
+        let (_, entries) =
+            parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>("csv/entry_16_2.csv").unwrap();
+        let mini_tree_2 = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries[..4].to_vec(),
+            mini_tree_1.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(*mini_tree_2.depth(), 2);
+
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2.clone()],
+            mini_tree_1.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+
+        // The entries of the second mini tree follow the 16 entries of the first one
+        for (index, mini_tree, entry_index) in [(5, &mini_tree_1, 5), (18, &mini_tree_2, 2)] {
+            let entry = aggregation_mst.get_entry(index);
+            assert_eq!(
+                entry.username(),
+                mini_tree.get_entry(entry_index).username()
+            );
+            assert_eq!(
+                entry.balances(),
+                mini_tree.get_entry(entry_index).balances()
+            );
+
+            let proof = aggregation_mst.generate_proof(index).unwrap();
+            assert_eq!(proof.path_indices.len(), mini_tree.depth() + 1);
+            assert!(aggregation_mst.verify_proof(&proof));
+        }
+    }
+
+    #[test]
+    fn test_aggregation_mst_padding() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+
+        // A smaller last chunk of 5 entries, padded to a mini tree of depth 3
+        let (_, entries) =
+            parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>("csv/entry_16_3.csv").unwrap();
+        let mini_tree_3 = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+            entries[..5].to_vec(),
+            mini_tree_1.cryptocurrencies().to_vec(),
+            false,
+        )
+        .unwrap();
+
+        let mini_trees = vec![mini_tree_1.clone(), mini_tree_2, mini_tree_3.clone()];
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            mini_trees.clone(),
+            mini_tree_1.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+
+        // The 3 mini trees are padded to 4 leaves
+        assert_eq!(*aggregation_mst.depth(), 2);
+        assert_eq!(aggregation_mst.nodes()[0].len(), 4);
+        assert_eq!(
+            aggregation_mst.nodes()[0][3].hash,
+            padding_leaf::<N_CURRENCIES>().hash
+        );
+        assert_eq!(
+            aggregation_mst.root().balances,
+            mini_trees
+                .iter()
+                .map(|mini_tree| mini_tree.root().balances)
+                .fold([Fp::from(0); N_CURRENCIES], |acc, balances| {
+                    [acc[0] + balances[0], acc[1] + balances[1]]
+                })
+        );
+
+        // The entries of the last mini tree, whose sibling is the padding leaf, have valid proofs
+        for entry_index in [0, 4] {
+            let index = 32 + entry_index;
+            assert_eq!(
+                aggregation_mst.get_entry(index).username(),
+                mini_tree_3.get_entry(entry_index).username()
+            );
+            let proof = aggregation_mst.generate_proof(index).unwrap();
+            assert_eq!(proof.root.hash, aggregation_mst.root().hash);
+            assert!(aggregation_mst.verify_proof(&proof));
+        }
+
+        // Appending the mini trees one by one pads the same way
+        let mut pushed_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            mini_trees[..1].to_vec(),
+            mini_tree_1.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+        pushed_mst.extend(mini_trees[1..].to_vec()).unwrap();
+        assert_eq!(pushed_mst.root().hash, aggregation_mst.root().hash);
+    }
+
+    #[test]
+    fn test_aggregation_mst_index_of_username() {
+        let mini_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let mini_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_2.csv").unwrap();
+        let mini_tree_3 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_3.csv").unwrap();
+        let mini_tree_4 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_4.csv").unwrap();
+
+        let mut aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree_1.clone(), mini_tree_2.clone()],
+            mini_tree_1.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+        // The index built by this lookup is kept up to date by the pushes below
+        let username = mini_tree_1.get_entry(3).username();
+        assert_eq!(aggregation_mst.index_of_username(username), Some(3));
+        aggregation_mst
+            .extend([mini_tree_3.clone(), mini_tree_4])
+            .unwrap();
+
+        // The index is global over the mini trees, including the ones pushed later
+        for (index, mini_tree_index, mini_tree, entry_index) in [
+            (3, 0, &mini_tree_1, 3),
+            (19, 1, &mini_tree_2, 3),
+            (37, 2, &mini_tree_3, 5),
+        ] {
+            let username = mini_tree.get_entry(entry_index).username();
+            assert_eq!(aggregation_mst.index_of_username(username), Some(index));
+            assert_eq!(aggregation_mst.get_entry(index).username(), username);
+            assert_eq!(
+                aggregation_mst.locate(index),
+                (mini_tree_index, entry_index)
+            );
+            assert_eq!(
+                aggregation_mst.locate_username(username),
+                Some((mini_tree_index, entry_index))
+            );
+
+            let proof = aggregation_mst
+                .generate_proof_for_username(username)
+                .unwrap();
+            assert!(aggregation_mst.verify_proof(&proof));
+        }
+
+        assert_eq!(aggregation_mst.index_of_username("unknown"), None);
+        assert_eq!(aggregation_mst.locate_username("unknown"), None);
+        assert!(aggregation_mst
+            .generate_proof_for_username("unknown")
+            .is_err());
+    }
+
+    #[test]
+    fn test_aggregation_mst_generate_proofs() {
+        let mut mini_trees = Vec::new();
+        for i in 1..=4 {
+            let mini_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!(
+                "csv/entry_16_{}.csv",
+                i
+            ))
+            .unwrap();
+            mini_trees.push(mini_tree);
+        }
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        // Unordered, repeated and sharing mini trees
+        let indices = [63, 0, 17, 5, 17, 40, 1];
+        let proofs = aggregation_mst.generate_proofs(&indices).unwrap();
+        assert_eq!(proofs.len(), indices.len());
+
+        for (index, proof) in indices.iter().zip(&proofs) {
+            let expected = aggregation_mst.generate_proof(*index).unwrap();
+            assert_eq!(proof.entry.username(), expected.entry.username());
+            assert_eq!(proof.entry.balances(), expected.entry.balances());
+            assert_eq!(proof.root.hash, expected.root.hash);
+            assert_eq!(proof.root.balances, expected.root.balances);
+            assert_eq!(
+                proof.sibling_leaf_node_hash_preimage,
+                expected.sibling_leaf_node_hash_preimage
+            );
+            assert_eq!(
+                proof.sibling_middle_node_hash_preimages,
+                expected.sibling_middle_node_hash_preimages
+            );
+            assert_eq!(proof.path_indices, expected.path_indices);
+            assert!(aggregation_mst.verify_proof(proof));
+        }
+
+        assert!(aggregation_mst.generate_proofs(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_aggregation_mst_save_and_load() {
+        let mut mini_trees = Vec::new();
+        for i in 1..=4 {
+            let mini_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!(
+                "csv/entry_16_{}.csv",
+                i
+            ))
+            .unwrap();
+            mini_trees.push(mini_tree);
+        }
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        let path = std::env::temp_dir().join("summa_aggregation_mst_save_and_load.json");
+        aggregation_mst.save_to_file(&path).unwrap();
+        let loaded_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_mst.root().hash, aggregation_mst.root().hash);
+        assert_eq!(loaded_mst.root().balances, aggregation_mst.root().balances);
+        assert_eq!(loaded_mst.depth(), aggregation_mst.depth());
+        assert_eq!(
+            loaded_mst.cryptocurrencies()[0].name,
+            aggregation_mst.cryptocurrencies()[0].name
+        );
+
+        // Proofs from the loaded tree verify against the original one
+        let index = 37;
+        assert_eq!(
+            loaded_mst.get_entry(index).username(),
+            aggregation_mst.get_entry(index).username()
+        );
+        let proof = loaded_mst.generate_proof(index).unwrap();
+        assert!(aggregation_mst.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_aggregation_mst_write_and_read_json() {
+        let mini_trees = (1..=3)
+            .map(|i| {
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!("csv/entry_16_{}.csv", i))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        let mut json = Vec::new();
+        aggregation_mst.write_json(&mut json).unwrap();
+        let read_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::read_json(&json[..]).unwrap();
+        assert_eq!(read_mst.root().hash, aggregation_mst.root().hash);
+        assert_eq!(read_mst.root().balances, aggregation_mst.root().balances);
+        let proof = read_mst.generate_proof(20).unwrap();
+        assert!(aggregation_mst.verify_proof(&proof));
+
+        // A tampered root or node is rejected
+        let json_tree = JsonAggregationMerkleSumTree::from_tree(&aggregation_mst);
+        let mut tampered_tree = json_tree.clone();
+        tampered_tree.root.hash = tampered_tree.nodes[0][0].hash.clone();
+        let err = tampered_tree
+            .to_verified_aggregation_mst::<N_CURRENCIES, N_BYTES>()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "The root does not match the mini trees");
+        let mut tampered_tree = json_tree;
+        tampered_tree.nodes[1][0] = tampered_tree.nodes[1][1].clone();
+        let err = tampered_tree
+            .to_verified_aggregation_mst::<N_CURRENCIES, N_BYTES>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node 0 at level 1 does not match the mini trees"
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_into_root_only() {
+        let mini_trees = (1..=4)
+            .map(|i| {
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!("csv/entry_16_{}.csv", i))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        let root = aggregation_mst.clone().into_root_only();
+        assert_eq!(root.root().hash, aggregation_mst.root().hash);
+        assert_eq!(root.depth(), *aggregation_mst.depth());
+        assert!(same_cryptocurrencies(
+            root.cryptocurrencies(),
+            aggregation_mst.cryptocurrencies()
+        ));
+        assert_eq!(root.total_balances(), aggregation_mst.total_balances());
+        assert_eq!(
+            root.total_balances_as_big_uint(),
+            aggregation_mst.total_balances_as_big_uint()
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_export_proofs() {
+        let mini_trees = (1..=4)
+            .map(|i| {
+                MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!("csv/entry_16_{}.csv", i))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let aggregation_mst =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(mini_trees, cryptocurrencies)
+                .unwrap();
+
+        let indices = (0..64).rev().collect::<Vec<_>>();
+        let mut output = Vec::new();
+        assert_eq!(
+            aggregation_mst
+                .export_proofs(&indices, 2, &mut output)
+                .unwrap(),
+            64
+        );
+
+        // One proof per line, in the order of the indices
+        let lines = String::from_utf8(output).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), indices.len());
+        for (index, line) in indices.iter().zip(lines) {
+            let json_proof: JsonMerkleProof = serde_json::from_str(line).unwrap();
+            let proof = json_proof.to_proof::<N_CURRENCIES, N_BYTES>().unwrap();
+            assert_eq!(
+                proof.entry.username(),
+                aggregation_mst.get_entry(*index).username()
+            );
+            assert!(aggregation_mst.verify_proof(&proof));
+        }
+
+        assert!(aggregation_mst
+            .export_proofs(&indices, 0, &mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_build_merkle_tree_from_leaves_parallel() {
+        let mini_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16.csv").unwrap();
+
+        let mut nodes = vec![];
+        let root =
+            build_merkle_tree_from_leaves_parallel(mini_tree.leaves(), 4, &mut nodes).unwrap();
+
+        assert_eq!(root.hash, mini_tree.root().hash);
+        assert_eq!(root.balances, mini_tree.root().balances);
+        assert_eq!(nodes.len(), mini_tree.nodes().len());
+        assert!(
+            build_merkle_tree_from_leaves_parallel(&mini_tree.leaves()[..15], 4, &mut nodes)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mst_push_mini_tree() {
+        let mut mini_trees = Vec::new();
+        for i in 1..=4 {
+            let mini_tree = MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv(&format!(
+                "csv/entry_16_{}.csv",
+                i
+            ))
+            .unwrap();
+            mini_trees.push(mini_tree);
+        }
+        let cryptocurrencies = mini_trees[0].cryptocurrencies().to_vec();
+        let expected_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            mini_trees.clone(),
+            cryptocurrencies.clone(),
+        )
+        .unwrap();
+
+        let mut aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            mini_trees[..1].to_vec(),
+            cryptocurrencies,
+        )
+        .unwrap();
+        assert_eq!(*aggregation_mst.depth(), 0);
+
+        // Crossing 2 mini trees grows the depth to 2, leaving the fourth leaf empty
+        aggregation_mst.extend(mini_trees[1..3].to_vec()).unwrap();
+        assert_eq!(*aggregation_mst.depth(), 2);
+        assert_eq!(
+            aggregation_mst.nodes()[0][3].hash,
+            padding_leaf::<N_CURRENCIES>().hash
+        );
+        assert_eq!(
+            aggregation_mst.root().balances,
+            [(556862 * 3).into(), (556862 * 3).into()]
+        );
+
+        aggregation_mst
+            .push_mini_tree(mini_trees[3].clone())
+            .unwrap();
+        assert_eq!(aggregation_mst.root().hash, expected_mst.root().hash);
+        assert_eq!(
+            aggregation_mst.root().balances,
+            expected_mst.root().balances
+        );
+
+        let index = 50;
+        assert_eq!(
+            aggregation_mst.get_entry(index).username(),
+            mini_trees[3].get_entry(index - 48).username()
+        );
+        let proof = aggregation_mst.generate_proof(index).unwrap();
+        assert!(aggregation_mst.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_aggregation_mst_single_mini_tree_proof() {
+        let mini_tree =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_1.csv").unwrap();
+        let aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![mini_tree.clone()],
+            mini_tree.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+        assert_eq!(*aggregation_mst.depth(), 0);
+
+        // The proof is the one of the mini tree, without any sibling above its root
+        let proof = aggregation_mst.generate_proof(5).unwrap();
+        let mini_tree_proof = mini_tree.generate_proof(5).unwrap();
+        assert_eq!(proof.path_indices, mini_tree_proof.path_indices);
+        assert_eq!(
+            proof.sibling_middle_node_hash_preimages,
+            mini_tree_proof.sibling_middle_node_hash_preimages
+        );
+        assert!(aggregation_mst.verify_proof(&proof));
+
+        let username = mini_tree.get_entry(5).username();
+        let proof = aggregation_mst
+            .generate_proof_for_username(username)
+            .unwrap();
+        assert!(aggregation_mst.verify_proof(&proof));
+    }
+
+    #[test]
+    fn test_aggregation_mst_push_mini_tree_overflow() {
+        let merkle_sum_tree_1 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16.csv").unwrap();
+        let merkle_sum_tree_2 =
+            MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_csv("csv/entry_16_no_overflow.csv")
+                .unwrap();
+
+        let mut aggregation_mst = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::new(
+            vec![merkle_sum_tree_1],
+            merkle_sum_tree_2.cryptocurrencies().to_vec(),
+        )
+        .unwrap();
+        let root = aggregation_mst.root().clone();
+
+        assert!(aggregation_mst.push_mini_tree(merkle_sum_tree_2).is_err());
+        assert_eq!(aggregation_mst.mini_trees().len(), 1);
+        assert_eq!(aggregation_mst.root().hash, root.hash);
+    }
+
+    #[test]
+    fn test_estimate_memory() {
+        let small = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::estimate_memory(4, 16);
+        let large = AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::estimate_memory(8, 16);
+        assert!(small > 0);
+        assert!(large > 2 * small - 1);
+
+        // The entries are padded to a power of two
+        assert_eq!(
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::estimate_memory(4, 9),
+            small
+        );
     }
 }
@@ -1,22 +1,86 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 use std::{future::Future, pin::Pin};
 
 use bollard::network::ListNetworksOptions;
-use bollard::service::{ListServicesOptions, UpdateServiceOptions};
+use bollard::service::{ListServicesOptions, ServiceSpec, UpdateServiceOptions};
+use bollard::Docker;
 use tokio::sync::oneshot;
+use tokio::time::Duration;
+use tracing::{error, info, warn};
 
-use crate::executor::utils::get_specs_from_compose;
-use crate::executor::{Executor, ExecutorSpawner};
+use crate::executor::utils::{get_deployment_from_compose, get_specs_from_compose};
+use crate::executor::{
+    Certificate, Executor, ExecutorError, ExecutorSpawner, HeaderMap, RequestTracker,
+    ServiceDeployment, SpawnError,
+};
+
+// Default upper bound for waiting on a newly created service to become ready
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// What `create_service` created rather than found, so that a failed spawn only removes those
+#[derive(Debug, Clone, Copy)]
+struct CreatedResources {
+    network: bool,
+    service: bool,
+}
+
+// What the Executors are built with, cloned into the task creating the service
+#[derive(Clone)]
+struct ExecutorSettings {
+    default_port: i64,
+    root_certificate: Option<Certificate>,
+    headers: HeaderMap,
+    worker_headers: HashMap<String, HeaderMap>,
+    request_tracker: Arc<RequestTracker>,
+}
+
+impl ExecutorSettings {
+    // Builds the Executor for the given worker node URL, with the scheme, port and headers resolved.
+    fn executor_for(&self, node_url: &str) -> Result<Executor, ExecutorError> {
+        // Keep the scheme if the URL already carries one
+        let (scheme, address) = node_url.split_once("://").unwrap_or(("http", node_url));
+
+        // Check if the URL already contains a port
+        let has_port = address.split(':').last().unwrap().parse::<u16>().is_ok();
+
+        // Append the port if it's not there
+        let final_url = if has_port {
+            format!("{}://{}", scheme, address)
+        } else {
+            format!("{}://{}:{}", scheme, address, self.default_port)
+        };
+
+        let mut headers = self.headers.clone();
+        if let Some(worker_headers) = self.worker_headers.get(node_url) {
+            for (name, value) in worker_headers {
+                headers.insert(name, value.clone());
+            }
+        }
+
+        let executor = Executor::new(final_url, None)
+            .with_headers(headers)
+            .with_request_tracker(self.request_tracker.clone());
+        match self.root_certificate.clone() {
+            Some(certificate) => executor.with_root_certificate(certificate),
+            None => Ok(executor),
+        }
+    }
+}
 
 pub struct CloudSpawner {
     service_info: Option<(String, String)>,
+    docker: Option<Docker>,
     worker_counter: Arc<AtomicUsize>,
     worker_node_url: Vec<String>,
-    default_port: i64,
+    executor_settings: ExecutorSettings,
+    ready_timeout: Duration,
+    // The Executor for the endpoint of the service, once it is created
+    service_executor: Arc<Mutex<Option<Executor>>>,
 }
 
 /// CloudSpawner
@@ -31,47 +95,181 @@ pub struct CloudSpawner {
 ///
 /// - With `service_info`, CloudSpawner requires a `docker-compose` file. When provided with `service_info`,
 ///   it manages Docker services and networks, enabling dynamic scaling and orchestration of workers.
+///   The connection to the local Docker daemon is made once in `new`, which fails if the daemon is not reachable.
+///   Without `service_info`, `new` fails if no `worker_node_url` is given.
+///   If the service cannot be created, the network created for it is removed again, so a retry starts from scratch.
+///   After creating the service, the first Executor waits for its worker with `Executor::wait_until_ready`,
+///   for at most the ready timeout. If the worker is not ready by then, the spawn fails and the service and network
+///   are removed again if it created them, while a service that already existed and was only updated is left in place.
+///   The `worker_node_url` may then be left empty: the Executors reach the service at the port it publishes
+///   for `default_port`, on the address of the swarm node the Docker daemon runs on. The swarm routing mesh
+///   spreads their requests over the replicas, so any number of Executors can be spawned.
+///
+/// A worker node URL may carry its own scheme, e.g. `https://10.0.0.1`, otherwise `http://` is used.
+/// For workers behind TLS with a private CA, the CA is passed to every Executor with `with_root_certificate`.
+///
+/// Headers such as `Authorization` can be attached to the requests of all Executors with `with_headers`,
+/// or to the requests of a single worker node with `with_worker_headers`, e.g. when every node has its own token.
 impl CloudSpawner {
     pub fn new(
         service_info: Option<(String, String)>, // If the user want to use docker-compose.yml for docker swarm
         worker_node_url: Vec<String>,
         default_port: i64,
-    ) -> Self {
-        assert!(!worker_node_url.is_empty(), "Worker node url is empty");
-        CloudSpawner {
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if worker_node_url.is_empty() && service_info.is_none() {
+            return Err("Worker node url is empty".into());
+        }
+
+        // Docker is only needed for managing the service
+        let docker = match service_info {
+            Some(_) => Some(Docker::connect_with_local_defaults()?),
+            None => None,
+        };
+
+        Ok(CloudSpawner {
             service_info,
+            docker,
             worker_counter: Arc::new(AtomicUsize::new(0)),
             worker_node_url,
-            default_port,
-        }
+            executor_settings: ExecutorSettings {
+                default_port,
+                root_certificate: None,
+                headers: HeaderMap::new(),
+                worker_headers: HashMap::new(),
+                request_tracker: Arc::default(),
+            },
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+            service_executor: Arc::default(),
+        })
+    }
+
+    /// Sets how long to wait for the worker of a newly created service to become ready.
+    pub fn with_ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    /// Trusts the given CA certificate in every spawned Executor, see `Executor::with_root_certificate`.
+    pub fn with_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.executor_settings.root_certificate = Some(certificate);
+        self
     }
 
-    async fn create_service(service_name: &str, compose_path: &str) -> Result<(), Box<dyn Error>> {
-        let docker = bollard::Docker::connect_with_local_defaults().unwrap();
+    /// Attaches the given headers to the requests of every spawned Executor.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.executor_settings.headers = headers;
+        self
+    }
+
+    /// Attaches the given headers to the requests of the Executor for `worker_node_url`,
+    /// which has to match an entry of `worker_node_url` passed to `new`.
+    ///
+    /// These take precedence over headers of the same name set with `with_headers`.
+    pub fn with_worker_headers(mut self, worker_node_url: String, headers: HeaderMap) -> Self {
+        self.executor_settings
+            .worker_headers
+            .insert(worker_node_url, headers);
+        self
+    }
 
+    /// Returns the replicas and placement constraints of the service in the `docker-compose` file of `service_info`,
+    /// or `None` without `service_info`.
+    pub fn service_deployment(
+        &self,
+    ) -> Result<Option<ServiceDeployment>, Box<dyn Error + Send + Sync>> {
+        match &self.service_info {
+            Some((service_name, compose_path)) => {
+                get_deployment_from_compose(service_name, compose_path).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn create_service(
+        docker: &Docker,
+        service_name: &str,
+        compose_path: &str,
+    ) -> Result<CreatedResources, Box<dyn Error + Send + Sync>> {
         // Retrieve network options and service spec from docker-compose.yml
-        let (network_options, service_spec) =
-            get_specs_from_compose(service_name, compose_path).unwrap();
+        let (network_options, service_spec) = get_specs_from_compose(service_name, compose_path)?;
 
-        // Check network exist then create if not exist
+        // Check network exist then create if not exist, networks without a name are skipped
         let list_network = docker
             .list_networks(None::<ListNetworksOptions<String>>)
             .await?;
 
-        let mut found_target_network = false;
-        list_network.iter().for_each(|network| {
-            if service_name == *network.name.as_ref().unwrap() {
-                found_target_network = true;
-            }
-        });
+        let found_target_network = list_network
+            .iter()
+            .any(|network| network.name.as_deref() == Some(service_name));
 
+        let mut created_network = false;
         if !found_target_network {
             match docker.create_network(network_options).await {
-                Ok(result) => println!("Network created: {:?}", result),
-                Err(error) => eprintln!("Error creating network: {}", error),
+                Ok(result) => {
+                    info!(network = ?result, "network created");
+                    created_network = true;
+                }
+                Err(error) => error!(%error, "error creating network"),
             }
         }
 
+        // Roll back the network created above, so a retry does not find it without a service
+        let result =
+            CloudSpawner::create_or_update_service(docker, service_name, service_spec).await;
+        if result.is_err() && created_network {
+            if let Err(error) = docker.remove_network(service_name).await {
+                error!(network = service_name, %error, "error removing network");
+            }
+        }
+        Ok(CreatedResources {
+            network: created_network,
+            service: result?,
+        })
+    }
+
+    // Removes the service and its network, either may already be gone
+    async fn remove_service(docker: &Docker, service_name: &str) {
+        CloudSpawner::delete_service(docker, service_name).await;
+        CloudSpawner::delete_network(docker, service_name).await;
+    }
+
+    // Removes only what `create_service` created, leaving a service or network that existed before in place
+    async fn roll_back_service(docker: &Docker, service_name: &str, created: CreatedResources) {
+        if created.service {
+            CloudSpawner::delete_service(docker, service_name).await;
+        }
+        if created.network {
+            CloudSpawner::delete_network(docker, service_name).await;
+        }
+    }
+
+    async fn delete_service(docker: &Docker, service_name: &str) {
+        match docker.delete_service(service_name).await {
+            Ok(()) => {}
+            Err(error) if is_not_found(&error) => {
+                info!(service = service_name, "service already removed")
+            }
+            Err(error) => error!(service = service_name, %error, "error removing service"),
+        }
+    }
+
+    // The network is gone if it was rolled back by a failed `create_service`
+    async fn delete_network(docker: &Docker, service_name: &str) {
+        match docker.remove_network(service_name).await {
+            Ok(()) => {}
+            Err(error) if is_not_found(&error) => {
+                info!(network = service_name, "network already removed")
+            }
+            Err(error) => error!(network = service_name, %error, "error removing network"),
+        }
+    }
+
+    // Returns whether the service was created, rather than updated
+    async fn create_or_update_service(
+        docker: &Docker,
+        service_name: &str,
+        service_spec: ServiceSpec,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
         // Checking service exist then create if not exist
         let services = docker
             .list_services(None::<ListServicesOptions<String>>)
@@ -80,17 +278,15 @@ impl CloudSpawner {
         let mut found_exist_service = false;
         let mut service_version = 0;
 
-        services.iter().for_each(|service| {
+        for service in services.iter() {
             let retrieved_service_spec = service
                 .spec
                 .as_ref()
-                .ok_or::<Box<dyn Error>>("No spec in service on Docker".into())
-                .unwrap();
+                .ok_or("No spec in service on Docker")?;
             let retrieved_service_name = retrieved_service_spec
                 .name
                 .as_ref()
-                .ok_or::<Box<dyn Error>>("No name in service.spec on Docker".into())
-                .unwrap();
+                .ok_or("No name in service.spec on Docker")?;
 
             if service_name == *retrieved_service_name {
                 found_exist_service = true;
@@ -99,21 +295,20 @@ impl CloudSpawner {
                 let retrieved_service_version = service
                     .version
                     .as_ref()
-                    .ok_or::<Box<dyn Error>>("No version in service on Docker".into())
-                    .unwrap();
+                    .ok_or("No version in service on Docker")?;
                 if let Some(exist_version) = retrieved_service_version.index {
                     service_version = exist_version + 1;
                 }
             }
-        });
+        }
 
         if !found_exist_service {
             docker.create_service(service_spec, None).await?;
-            println!("Service {:?} created", service_name);
+            info!(service = service_name, "service created");
         } else {
-            println!(
-                "Service {:?} already exists, Will use the service",
-                service_name
+            info!(
+                service = service_name,
+                "service already exists, will use the service"
             );
             let update_service_options = UpdateServiceOptions {
                 version: service_version,
@@ -123,65 +318,196 @@ impl CloudSpawner {
                 .update_service(service_name, service_spec, update_service_options, None)
                 .await?;
             update_response.warnings.iter().for_each(|warning| {
-                println!("warning: {:?}", warning);
+                warn!(
+                    service = service_name,
+                    ?warning,
+                    "warning while updating service"
+                );
             });
         };
-        Ok(())
+        Ok(!found_exist_service)
+    }
+
+    // Resolves the address the service publishes the worker port on, reachable on every swarm node through the routing mesh
+    async fn service_url(
+        docker: &Docker,
+        service_name: &str,
+        worker_port: i64,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let node_addr = docker
+            .info()
+            .await?
+            .swarm
+            .and_then(|swarm| swarm.node_addr)
+            .filter(|node_addr| !node_addr.is_empty())
+            .ok_or("The Docker daemon is not a node of a swarm")?;
+
+        let endpoint = docker
+            .inspect_service(service_name, None)
+            .await?
+            .endpoint
+            .unwrap_or_default();
+        // The ports are listed in the spec until the swarm has published them
+        let published_port = endpoint
+            .ports
+            .into_iter()
+            .chain(endpoint.spec.and_then(|spec| spec.ports))
+            .flatten()
+            .find(|port| port.target_port == Some(worker_port))
+            .and_then(|port| port.published_port)
+            .ok_or_else(|| {
+                format!(
+                    "Service {} does not publish the worker port {}",
+                    service_name, worker_port
+                )
+            })?;
+
+        Ok(format!("{}:{}", node_addr, published_port))
     }
 }
 
-impl ExecutorSpawner for CloudSpawner {
-    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Executor> + Send>> {
-        let (tx, rx) = oneshot::channel();
+// Whether Docker reports the service or network as missing
+fn is_not_found(error: &bollard::errors::Error) -> bool {
+    matches!(
+        error,
+        bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            ..
+        }
+    )
+}
 
+impl ExecutorSpawner for CloudSpawner {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
         let current_worker_counter = self.worker_counter.load(Ordering::SeqCst);
 
+        // The traffic is routed to the service by the swarm manager.
+        // So, All executor can use the same exposed endpoint for distributing task to multiple workers.
+        // Without worker node URLs, that endpoint is resolved once the service is created.
+        let executor = if self.worker_node_url.is_empty() {
+            None
+        } else {
+            let Some(node_url) = self.worker_node_url.get(current_worker_counter) else {
+                let error = SpawnError::Exhausted {
+                    requested: current_worker_counter + 1,
+                    available: self.worker_node_url.len(),
+                };
+                return Box::pin(async move { Err(error) });
+            };
+
+            match self.executor_settings.executor_for(node_url) {
+                Ok(executor) => Some(executor),
+                Err(e) => return Box::pin(async move { Err(SpawnError::Executor(e)) }),
+            }
+        };
+
         // Create service if the worker counter is 0, which means no executor is spawned.
-        if current_worker_counter == 0 && self.service_info.is_some() {
-            let (service_name, compose_path) = self.service_info.clone().unwrap();
+        let mut service_created = None;
+        if let (0, Some((service_name, compose_path)), Some(docker)) = (
+            current_worker_counter,
+            self.service_info.clone(),
+            self.docker.clone(),
+        ) {
+            let ready_timeout = self.ready_timeout;
+            let executor_settings = self.executor_settings.clone();
+            let first_executor = executor.clone();
+            let (tx, rx) = oneshot::channel::<Result<Executor, Box<dyn Error + Send + Sync>>>();
+            service_created = Some(rx);
             tokio::spawn(async move {
-                if let Err(e) = CloudSpawner::create_service(&service_name, &compose_path).await {
-                    eprintln!("Error creating service: {}", e);
-                } else {
-                    // Sleep for 5 seconds to wait for the service to be ready
-                    std::thread::sleep(std::time::Duration::from_secs(5));
-                    let _ = tx.send(service_name.clone());
-                    println!("Service {} created", service_name);
+                let created: Result<Executor, Box<dyn Error + Send + Sync>> = async {
+                    let created_resources =
+                        CloudSpawner::create_service(&docker, &service_name, &compose_path).await?;
+                    let ready: Result<Executor, Box<dyn Error + Send + Sync>> = async {
+                        let first_executor = match first_executor {
+                            Some(executor) => executor,
+                            None => {
+                                let service_url = CloudSpawner::service_url(
+                                    &docker,
+                                    &service_name,
+                                    executor_settings.default_port,
+                                )
+                                .await?;
+                                info!(
+                                    service = service_name,
+                                    url = service_url,
+                                    "service endpoint resolved"
+                                );
+                                executor_settings.executor_for(&service_url)?
+                            }
+                        };
+                        info!(service = service_name, "service created");
+                        first_executor
+                            .wait_until_ready(ready_timeout)
+                            .await
+                            .map_err(|e| format!("Service {} is not ready: {}", service_name, e))?;
+                        Ok(first_executor)
+                    }
+                    .await;
+                    // Do not leave a service behind that no Executor is returned for
+                    if ready.is_err() {
+                        CloudSpawner::roll_back_service(&docker, &service_name, created_resources)
+                            .await;
+                    }
+                    ready
                 }
+                .await;
+                let _ = tx.send(created);
             });
         }
 
-        // The traffic is routed to the service by the swarm manager.
-        // So, All executor can use the same exposed endpoint for distributing task to multiple workers.
-        let port = self.default_port;
-        let node_url = self.worker_node_url[current_worker_counter].clone();
         let worker_counter = self.worker_counter.clone();
+        let service_executor = self.service_executor.clone();
         Box::pin(async move {
-            if worker_counter.load(Ordering::SeqCst) == 0 {
-                let _ = rx.await;
+            if let Some(rx) = service_created {
+                let first_executor = rx
+                    .await
+                    .map_err(|_| SpawnError::Worker("Failed to receive service status".into()))?
+                    .map_err(SpawnError::Worker)?;
+                *service_executor.lock().unwrap() = Some(first_executor);
             }
-            // Check if the URL already contains a port
-            let has_port = node_url.split(':').last().unwrap().parse::<u16>().is_ok();
-
-            // Append the port if it's not there
-            let final_url = if has_port {
-                node_url.clone()
-            } else {
-                format!("{}:{}", node_url, port)
-            };
+            let executor =
+                match executor {
+                    Some(executor) => executor,
+                    None => service_executor.lock().unwrap().clone().ok_or_else(|| {
+                        SpawnError::Worker("The service has not been created".into())
+                    })?,
+                };
             worker_counter.fetch_add(1, Ordering::SeqCst);
-            Executor::new(format!("http://{}", final_url), None)
+            Ok(executor)
         })
     }
 
+    // Unbounded if all Executors share the endpoint of the service
+    fn max_executors(&self) -> Option<usize> {
+        if self.worker_node_url.is_empty() {
+            None
+        } else {
+            Some(self.worker_node_url.len())
+        }
+    }
+
+    // The replicas of the service if it is managed, one executor per worker node URL otherwise
+    fn default_executors(&self) -> Option<usize> {
+        match self.service_deployment() {
+            Ok(Some(deployment)) => Some(deployment.replicas),
+            Ok(None) => Some(self.worker_node_url.len()),
+            Err(e) => {
+                warn!(error = %e, "cannot read the replicas of the service");
+                None
+            }
+        }
+    }
+
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        Some(self.executor_settings.request_tracker.clone())
+    }
+
     fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let service_info = self.service_info.clone();
+        let docker = self.docker.clone();
         Box::pin(async move {
-            if let Some((service_name, _)) = service_info.clone() {
-                let docker = bollard::Docker::connect_with_local_defaults().unwrap();
-
-                docker.delete_service(&service_name).await.unwrap();
-                docker.remove_network(&service_name).await.unwrap();
+            if let (Some((service_name, _)), Some(docker)) = (service_info, docker) {
+                CloudSpawner::remove_service(&docker, &service_name).await;
             }
         })
     }
@@ -0,0 +1,36 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::executor::{Executor, ExecutorSpawner, SpawnError};
+
+/// InProcessSpawner
+///
+/// Spawns Executors that build the mini-trees in this process, see `Executor::in_process`, without any Worker or HTTP.
+/// Meant for fast and deterministic tests of the Orchestrator, which then depend neither on free ports nor on the
+/// timing of a server.
+#[derive(Default)]
+pub struct InProcessSpawner {
+    executor_counter: AtomicUsize,
+}
+
+impl InProcessSpawner {
+    pub fn new() -> Self {
+        InProcessSpawner::default()
+    }
+}
+
+impl ExecutorSpawner for InProcessSpawner {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
+        let id = self.executor_counter.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move { Ok(Executor::in_process(Some(format!("in-process-{}", id)))) })
+    }
+
+    fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            // Nothing to terminate, the trees were built by the executors themselves
+        })
+    }
+}
@@ -0,0 +1,229 @@
+use std::error::Error;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::{future::Future, pin::Pin};
+
+use k8s_openapi::api::{apps::v1::Deployment, core::v1::Service};
+use kube::{
+    api::{DeleteParams, Patch, PatchParams},
+    Api, Client,
+};
+use serde_json::json;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout, Duration};
+use tracing::{error, info};
+
+use crate::executor::{Executor, ExecutorSpawner, RequestTracker, SpawnError};
+
+// Name of the Deployment, its pods and the Service in front of them
+const APP_NAME: &str = "mini-tree";
+const WORKER_PORT: i32 = 4000;
+const FIELD_MANAGER: &str = "summa-aggregation";
+// Default upper bound for waiting on all replicas to become ready
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(120);
+const READY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// K8sSpawner
+///
+/// The counterpart of CloudSpawner for managed Kubernetes clusters, where Docker Swarm services are not available.
+/// On the first `spawn_executor`, it applies a Deployment of `replicas` mini-tree pods and a ClusterIP Service
+/// in front of them to the given namespace, then waits until the pods pass their readiness probe on `/readyz`,
+/// for up to 2 minutes by default, see `with_ready_timeout`.
+///
+/// All Executors point to the Service, so their requests are balanced over the pods by kube-proxy,
+/// just like CloudSpawner relies on the Swarm manager. The Service URL is resolved by the cluster DNS,
+/// so the Orchestrator has to run inside the cluster.
+///
+/// The Kubernetes client is configured from the environment, i.e. the local kubeconfig or the in-cluster service account.
+/// `new` fails if `replicas` is not positive.
+pub struct K8sSpawner {
+    namespace: String,
+    image_name: String,
+    replicas: i32,
+    ready_timeout: Duration,
+    worker_counter: AtomicUsize,
+    request_tracker: Arc<RequestTracker>,
+}
+
+impl K8sSpawner {
+    pub fn new(
+        namespace: String,
+        image_name: String,
+        replicas: i32,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if replicas <= 0 {
+            return Err(format!("Replicas must be greater than 0, got {}", replicas).into());
+        }
+        Ok(K8sSpawner {
+            namespace,
+            image_name,
+            replicas,
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+            worker_counter: AtomicUsize::new(0),
+            request_tracker: Arc::default(),
+        })
+    }
+
+    /// Sets how long to wait for all replicas of the Deployment to become ready.
+    pub fn with_ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    // The URL of the Service in front of the pods, resolved by the cluster DNS
+    pub(crate) fn worker_url(&self) -> String {
+        format!(
+            "http://{}.{}.svc.cluster.local:{}",
+            APP_NAME, self.namespace, WORKER_PORT
+        )
+    }
+
+    async fn create_deployment(
+        namespace: &str,
+        image_name: &str,
+        replicas: i32,
+        ready_timeout: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let client = Client::try_default().await?;
+        let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+        let services: Api<Service> = Api::namespaced(client, namespace);
+
+        let deployment: Deployment = serde_json::from_value(json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": APP_NAME },
+            "spec": {
+                "replicas": replicas,
+                "selector": { "matchLabels": { "app": APP_NAME } },
+                "template": {
+                    "metadata": { "labels": { "app": APP_NAME } },
+                    "spec": {
+                        "containers": [{
+                            "name": APP_NAME,
+                            "image": image_name,
+                            "ports": [{ "containerPort": WORKER_PORT }],
+                            "livenessProbe": {
+                                "httpGet": { "path": "/healthz", "port": WORKER_PORT }
+                            },
+                            "readinessProbe": {
+                                "httpGet": { "path": "/readyz", "port": WORKER_PORT }
+                            }
+                        }]
+                    }
+                }
+            }
+        }))?;
+
+        let service: Service = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": APP_NAME },
+            "spec": {
+                "selector": { "app": APP_NAME },
+                "ports": [{ "port": WORKER_PORT, "targetPort": WORKER_PORT }]
+            }
+        }))?;
+
+        // Server-side apply creates both resources, or updates them if they already exist, e.g. scales the Deployment
+        let params = PatchParams::apply(FIELD_MANAGER);
+        deployments
+            .patch(APP_NAME, &params, &Patch::Apply(&deployment))
+            .await?;
+        services
+            .patch(APP_NAME, &params, &Patch::Apply(&service))
+            .await?;
+        info!(deployment = APP_NAME, "deployment applied");
+
+        // The deadline also bounds the requests to the API server, not only the polls
+        let ready = timeout(ready_timeout, async {
+            loop {
+                let ready_replicas = deployments
+                    .get(APP_NAME)
+                    .await?
+                    .status
+                    .and_then(|status| status.ready_replicas)
+                    .unwrap_or(0);
+                if ready_replicas >= replicas {
+                    return Ok::<_, kube::Error>(());
+                }
+                sleep(READY_POLL_INTERVAL).await;
+            }
+        })
+        .await;
+        match ready {
+            Ok(ready) => Ok(ready?),
+            Err(_) => Err(format!(
+                "Deployment {:?} is not ready after {:?}",
+                APP_NAME, ready_timeout
+            )
+            .into()),
+        }
+    }
+}
+
+impl ExecutorSpawner for K8sSpawner {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
+        let (tx, rx) = oneshot::channel();
+
+        let current_worker_counter = self.worker_counter.fetch_add(1, Ordering::SeqCst);
+
+        // Apply the Deployment for the first executor, the following ones share it.
+        if current_worker_counter == 0 {
+            let namespace = self.namespace.clone();
+            let image_name = self.image_name.clone();
+            let replicas = self.replicas;
+            let ready_timeout = self.ready_timeout;
+            tokio::spawn(async move {
+                let created =
+                    K8sSpawner::create_deployment(&namespace, &image_name, replicas, ready_timeout)
+                        .await;
+                let _ = tx.send(created);
+            });
+        }
+
+        let worker_url = self.worker_url();
+        let request_tracker = self.request_tracker.clone();
+        Box::pin(async move {
+            if current_worker_counter == 0 {
+                rx.await
+                    .map_err(|_| SpawnError::Worker("Failed to receive deployment status".into()))?
+                    .map_err(SpawnError::Worker)?;
+            }
+            Ok(Executor::new(worker_url, None).with_request_tracker(request_tracker))
+        })
+    }
+
+    // One executor per pod of the Deployment
+    fn default_executors(&self) -> Option<usize> {
+        Some(self.replicas as usize)
+    }
+
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        Some(self.request_tracker.clone())
+    }
+
+    fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let namespace = self.namespace.clone();
+        Box::pin(async move {
+            let client = match Client::try_default().await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!(error = %e, "error connecting to Kubernetes");
+                    return;
+                }
+            };
+
+            let deployments: Api<Deployment> = Api::namespaced(client.clone(), &namespace);
+            if let Err(e) = deployments.delete(APP_NAME, &DeleteParams::default()).await {
+                error!(error = %e, "error removing deployment");
+            }
+
+            let services: Api<Service> = Api::namespaced(client, &namespace);
+            if let Err(e) = services.delete(APP_NAME, &DeleteParams::default()).await {
+                error!(error = %e, "error removing service");
+            }
+        })
+    }
+}
@@ -10,25 +10,79 @@ use std::{
     env,
     error::Error,
     future::Future,
-    net::{SocketAddr, TcpListener, IpAddr},
+    net::IpAddr,
     pin::Pin,
-    sync::atomic::{AtomicUsize, Ordering}, str::FromStr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use tokio;
 use tokio::sync::oneshot;
+use tokio::time::Duration;
+use tracing::error;
 
-use crate::executor::{Executor, ExecutorSpawner};
+use crate::executor::{Executor, ExecutorSpawner, RequestTracker, SpawnError};
+
+// The port `mini-tree-server` listens on unless `MINI_TREE_PORT` is set
+const DEFAULT_CONTAINER_PORT: u16 = 4000;
+// Default upper bound for waiting on a started container to serve requests
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resource limits applied to each worker container, unlimited if not set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContainerLimits {
+    /// Memory limit in bytes.
+    pub memory: Option<i64>,
+    /// CPU quota in units of 10^-9 CPUs, e.g. `1_500_000_000` for 1.5 CPUs.
+    pub nano_cpus: Option<i64>,
+}
+
+/// Settings shared by all worker containers of a LocalSpawner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerSettings {
+    pub limits: ContainerLimits,
+    /// Environment variables in the `KEY=value` form.
+    pub env: Vec<String>,
+    /// The port the worker listens on inside the container.
+    pub container_port: u16,
+}
+
+impl Default for ContainerSettings {
+    fn default() -> Self {
+        ContainerSettings {
+            limits: ContainerLimits::default(),
+            env: Vec::new(),
+            container_port: DEFAULT_CONTAINER_PORT,
+        }
+    }
+}
 
 /// LocalSpawner
 ///
 /// The LocalSpawner is to use cases closer to actual deployment. It enables the initialization of Executors
 /// and Workers within a local Docker environment. This spawner is ideal for development and testing phases,
 /// where simplicity and direct control over the containers are beneficial.
+///
+/// With `with_limits`, the memory and CPU usage of each worker container can be capped,
+/// so that a worker running out of memory is killed on its own instead of exhausting the host.
+/// Environment variables for the worker containers, in the `KEY=value` form, are passed with `with_env`.
+///
+/// The workers are expected to listen on port 4000 inside the container. For images listening on another port,
+/// set it with `with_container_port`, which also passes it to the workers as `MINI_TREE_PORT`.
+/// The port on the host is chosen by Docker when the container starts, so executors spawned in parallel never race for it.
+///
+/// A spawned Executor is only returned once its worker serves requests, see `Executor::wait_until_ready`.
+/// The spawn fails if the worker is not ready within the ready timeout, 30 seconds by default.
 pub struct LocalSpawner {
     docker: Docker,
     worker_counter: AtomicUsize,
     image_name: String,
     container_name: String,
+    settings: ContainerSettings,
+    ready_timeout: Duration,
+    request_tracker: Arc<RequestTracker>,
 }
 
 impl LocalSpawner {
@@ -46,40 +100,85 @@ impl LocalSpawner {
             worker_counter: AtomicUsize::new(0),
             image_name,
             container_name,
+            settings: ContainerSettings::default(),
+            ready_timeout: DEFAULT_READY_TIMEOUT,
+            request_tracker: Arc::default(),
         }
     }
 
-    fn find_unused_port() -> Result<u16, std::io::Error> {
-        // Bind to address with port 0.
-        // The OS will assign an available ephemeral port.
-        let listener = TcpListener::bind("127.0.0.1:0")?;
+    /// Applies the given resource limits to every spawned worker container.
+    pub fn with_limits(mut self, limits: ContainerLimits) -> Self {
+        self.settings.limits = limits;
+        self
+    }
 
-        // Retrieve the assigned port.
-        match listener.local_addr() {
-            Ok(SocketAddr::V4(addr)) => Ok(addr.port()),
-            Ok(SocketAddr::V6(addr)) => Ok(addr.port()),
-            Err(e) => Err(e),
-        }
+    /// Sets environment variables, e.g. `RUST_BACKTRACE=1`, for every spawned worker container.
+    ///
+    /// The `mini-tree-server` in the image builds trees of the shape given by `N_CURRENCIES` and `N_BYTES`
+    /// for requests that do not name a shape, e.g. `N_CURRENCIES=1`, see `WorkerInfo::from_env`.
+    pub fn with_env(mut self, env: Vec<String>) -> Self {
+        self.settings.env = env;
+        self
+    }
+
+    /// Sets the port the worker listens on inside the container, 4000 by default.
+    pub fn with_container_port(mut self, container_port: u16) -> Self {
+        self.settings.container_port = container_port;
+        self
+    }
+
+    /// Sets how long to wait for the worker of a started container to serve requests.
+    pub fn with_ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    // Reads the host port Docker bound to `exposed_port` of the started container
+    fn host_port(
+        container_info: &ContainerInspectResponse,
+        exposed_port: &str,
+    ) -> Result<u16, Box<dyn Error + Send + Sync>> {
+        container_info
+            .network_settings
+            .as_ref()
+            .and_then(|network_settings| network_settings.ports.as_ref())
+            .and_then(|ports| ports.get(exposed_port))
+            .and_then(|bindings| bindings.as_ref())
+            .and_then(|bindings| {
+                bindings
+                    .iter()
+                    .find_map(|binding| binding.host_port.as_ref())
+            })
+            .ok_or_else(|| format!("No host port is bound to {} of the container", exposed_port))?
+            .parse()
+            .map_err(|e| format!("Invalid host port bound to {}: {}", exposed_port, e).into())
     }
 
     // Create a Docker instance connected to the local Docker daemon.
+    //
+    // Returns the inspected container along with the host port Docker bound to the container port.
     pub async fn create_container(
         docker: Docker,
         image_name: String,
         container_name: String,
         id: usize,
-        desirable_port: u16,
-    ) -> Result<ContainerInspectResponse, Box<dyn Error>> {
+        settings: ContainerSettings,
+    ) -> Result<(ContainerInspectResponse, u16), Box<dyn Error + Send + Sync>> {
         let container_name = format!("{}_{}", container_name, id);
+        let exposed_port = format!("{}/tcp", settings.container_port);
+
+        // Keep `mini-tree-server` listening on the mapped port
+        let mut env = settings.env;
+        env.push(format!("MINI_TREE_PORT={}", settings.container_port));
 
         // Define port mapping (container_port -> host_port)
         let port_bindings = {
             let mut port_bindings = HashMap::new();
             port_bindings.insert(
-                "4000/tcp".to_string(), // Container port
+                exposed_port.clone(), // Container port
                 Some(vec![PortBinding {
                     host_ip: Some(IpAddr::from_str("127.0.0.1").unwrap().to_string()), // Host IP
-                    host_port: Some(desirable_port.to_string()),                       // Host port
+                    host_port: None, // Chosen by Docker on start, read back from the inspected container
                 }]),
             );
             port_bindings
@@ -87,9 +186,15 @@ impl LocalSpawner {
 
         let config = Config {
             image: Some(image_name),
-            exposed_ports: Some(HashMap::from([("4000/tcp".to_string(), HashMap::<(), ()>::new())])), // Expose the container port
+            env: Some(env),
+            exposed_ports: Some(HashMap::from([(
+                exposed_port.clone(),
+                HashMap::<(), ()>::new(),
+            )])), // Expose the container port
             host_config: Some(HostConfig {
                 port_bindings: Some(port_bindings),
+                memory: settings.limits.memory,
+                nano_cpus: settings.limits.nano_cpus,
                 ..Default::default()
             }),
             ..Default::default()
@@ -114,13 +219,14 @@ impl LocalSpawner {
 
         let container_info: ContainerInspectResponse =
             docker.inspect_container(&container_name, None).await?;
+        let host_port = LocalSpawner::host_port(&container_info, &exposed_port)?;
 
-        Ok(container_info)
+        Ok((container_info, host_port))
     }
 }
 
 impl ExecutorSpawner for LocalSpawner {
-    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Executor> + Send>> {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
         // Using channel that onetime use, `oneshot`, to send container information
         let (tx, rx) = oneshot::channel();
 
@@ -128,40 +234,54 @@ impl ExecutorSpawner for LocalSpawner {
         let docker_clone = self.docker.clone();
         let image_name = self.image_name.clone();
         let container_name = self.container_name.clone();
+        let settings = self.settings.clone();
+        let request_tracker = self.request_tracker.clone();
+        let ready_timeout = self.ready_timeout;
         let id = self.worker_counter.fetch_add(1, Ordering::SeqCst);
         tokio::spawn(async move {
-            let desirable_port = LocalSpawner::find_unused_port().unwrap_or_default();
             let res = LocalSpawner::create_container(
                 docker_clone,
                 image_name,
                 container_name,
                 id,
-                desirable_port,
+                settings,
             )
             .await;
-            match res {
-                Ok(container_info) => {
-                    // the desirable_port is the port that is exposed to the host
-                    let _ = tx.send((desirable_port, container_info));
-                }
-                Err(e) => {
-                    eprintln!("Error creating container: {}", e);
-                }
-            }
+            let _ = tx.send(res);
         });
 
         // Return a Future that resolves to Executor
         Box::pin(async move {
-            // the container_info also has exposed port as 'host_port` field but it looks ugly to use it 
-            let (exposed_port, container_info) = rx.await.expect("Failed to receive worker URL");
+            // the exposed_port is the host port Docker bound to the container port
+            let (container_info, exposed_port) = rx
+                .await
+                .map_err(|_| SpawnError::Worker("Failed to receive worker URL".into()))?
+                .map_err(SpawnError::Worker)?;
             let worker_url = format!(
                 "http://127.0.0.1:{}", // This port is exposed to the host
                 exposed_port
             );
-            Executor::new(worker_url, container_info.name)
+            let executor = Executor::new(worker_url, container_info.name)
+                .with_request_tracker(request_tracker);
+            executor
+                .wait_until_ready(ready_timeout)
+                .await
+                .map_err(|e| {
+                    SpawnError::Worker(
+                        format!("Worker on port {} is not ready: {}", exposed_port, e).into(),
+                    )
+                })?;
+            match container_info.id {
+                Some(container_id) => Ok(executor.with_container(container_id, exposed_port)),
+                None => Ok(executor),
+            }
         })
     }
 
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        Some(self.request_tracker.clone())
+    }
+
     fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let docker_clone = self.docker.clone();
 
@@ -180,18 +300,60 @@ impl ExecutorSpawner for LocalSpawner {
                     .remove_container(&container_name_with_id, Some(remove_options))
                     .await
                 {
-                    eprintln!("Error removing container: {}", e);
+                    error!(container = container_name_with_id, error = %e, "error removing container");
                 }
             }
         })
     }
 }
 
-#[cfg(feature = "docker")]
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "docker")]
+    use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
+    use bollard::models::NetworkSettings;
+
+    fn inspect_response(bindings: Option<Vec<PortBinding>>) -> ContainerInspectResponse {
+        ContainerInspectResponse {
+            network_settings: Some(NetworkSettings {
+                ports: Some(HashMap::from([("4000/tcp".to_string(), bindings)])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_host_port() {
+        // The port Docker bound is used, whatever port was asked for
+        let container_info = inspect_response(Some(vec![PortBinding {
+            host_ip: Some("0.0.0.0".to_string()),
+            host_port: Some("49153".to_string()),
+        }]));
+        assert_eq!(
+            LocalSpawner::host_port(&container_info, "4000/tcp").unwrap(),
+            49153
+        );
+
+        let unpublished = LocalSpawner::host_port(&inspect_response(None), "4000/tcp").unwrap_err();
+        assert_eq!(
+            unpublished.to_string(),
+            "No host port is bound to 4000/tcp of the container"
+        );
+        assert!(LocalSpawner::host_port(&container_info, "5000/tcp").is_err());
 
+        let invalid = inspect_response(Some(vec![PortBinding {
+            host_ip: None,
+            host_port: Some("port".to_string()),
+        }]));
+        assert!(LocalSpawner::host_port(&invalid, "4000/tcp")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid host port bound to 4000/tcp"));
+    }
+
+    #[cfg(feature = "docker")]
     #[tokio::test]
     async fn test_executor_spawner() {
         let spawner = LocalSpawner::new(
@@ -200,13 +362,109 @@ mod tests {
         );
 
         // Spawn 2 executors
-        let executor_1 = spawner.spawn_executor().await;
-        let executor_2 = spawner.spawn_executor().await;
+        let executor_1 = spawner.spawn_executor().await.unwrap();
+        let executor_2 = spawner.spawn_executor().await.unwrap();
+
+        assert!(executor_1.get_container_id().is_some());
+        assert!(executor_2.get_container_id().is_some());
+        assert_ne!(executor_1.get_port(), executor_2.get_port());
+
+        // The workers serve requests as soon as their executors are spawned
+        for executor in [&executor_1, &executor_2] {
+            let port = executor.get_port().unwrap();
+            assert!(executor.get_url().ends_with(&format!(":{}", port)));
+            assert!(executor.health_check().await.is_ok());
+        }
+
+        // Teardown
+        spawner.terminate_executors().await;
+    }
+
+    #[cfg(feature = "docker")]
+    #[tokio::test]
+    async fn test_concurrent_spawns() {
+        let spawner = LocalSpawner::new(
+            "summadev/summa-aggregation-mini-tree:latest".to_string(),
+            "executor_concurrent_test".to_string(),
+        );
+
+        // Docker binds a distinct host port to each container, however many are started at once
+        let executors = futures::future::join_all((0..4).map(|_| spawner.spawn_executor())).await;
+        let mut ports = executors
+            .into_iter()
+            .map(|executor| executor.unwrap().get_port().unwrap())
+            .collect::<Vec<_>>();
+        ports.sort();
+        ports.dedup();
+        assert_eq!(ports.len(), 4);
+
+        // Teardown
+        spawner.terminate_executors().await;
+    }
+
+    #[cfg(feature = "docker")]
+    #[tokio::test]
+    async fn test_container_limits() {
+        let limits = ContainerLimits {
+            memory: Some(512 * 1024 * 1024),
+            nano_cpus: Some(1_000_000_000),
+        };
+        let spawner = LocalSpawner::new(
+            "summadev/summa-aggregation-mini-tree:latest".to_string(),
+            "executor_limits_test".to_string(),
+        )
+        .with_limits(limits.clone());
+
+        let _executor = spawner.spawn_executor().await.unwrap();
+
+        let container_info = spawner
+            .docker
+            .inspect_container("executor_limits_test_0", None)
+            .await
+            .unwrap();
+        let host_config = container_info.host_config.unwrap();
+        assert_eq!(host_config.memory, limits.memory);
+        assert_eq!(host_config.nano_cpus, limits.nano_cpus);
+
+        // Teardown
+        spawner.terminate_executors().await;
+    }
+
+    #[cfg(feature = "docker")]
+    #[tokio::test]
+    async fn test_container_env() {
+        let spawner = LocalSpawner::new(
+            "summadev/summa-aggregation-mini-tree:latest".to_string(),
+            "executor_env_test".to_string(),
+        )
+        .with_env(vec![
+            "RUST_BACKTRACE=1".to_string(),
+            "N_CURRENCIES=1".to_string(),
+        ]);
+
+        let executor = spawner.spawn_executor().await.unwrap();
+
+        let container_info = spawner
+            .docker
+            .inspect_container("executor_env_test_0", None)
+            .await
+            .unwrap();
+        let env = container_info.config.unwrap().env.unwrap();
+        assert!(env.contains(&"RUST_BACKTRACE=1".to_string()));
 
-        // Sleep 2 seconds for the container to be ready
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-        assert!(!executor_1.get_url().is_empty());
-        assert!(!executor_2.get_url().is_empty());
+        // The worker builds trees of one currency for requests without a shape
+        assert_eq!(executor.worker_info().await.unwrap().n_currencies, 1);
+        let json_entries = vec![JsonEntry::new("alice".to_string(), vec!["10".to_string()])];
+        let json_tree = reqwest::Client::new()
+            .post(executor.get_url())
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert_eq!(json_tree.root.balances.len(), 1);
 
         // Teardown
         spawner.terminate_executors().await;
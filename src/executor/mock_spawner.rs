@@ -1,24 +1,31 @@
-use axum::{routing::post, Router};
 use std::{
     future::Future,
-    net::SocketAddr,
+    net::{SocketAddr, TcpListener},
     pin::Pin,
     str::FromStr,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
 };
 use tokio;
 use tokio::sync::oneshot;
 
-use crate::executor::{Executor, ExecutorSpawner};
-use crate::mini_tree_generator::create_mst;
+use crate::executor::{Executor, ExecutorSpawner, SpawnError};
+use crate::mini_tree_generator::mini_tree_router;
 
 /// MockSpawner
 ///
 /// Primarily used for testing purposes, the MockSpawner initializes Executors suitable for various test scenarios,
 /// including negative test cases. It runs the `mini-tree-server` locally, allowing for a controlled testing environment.
+///
+/// By default, every Executor gets a new server. A spawner created with `pooled` instead starts a fixed number of servers
+/// on the first spawn and distributes all Executors over them round-robin, simulating many Executors sharing few Workers.
 pub struct MockSpawner {
     urls: Option<Vec<String>>,
     worker_counter: AtomicUsize,
+    pool_size: usize,
+    pool: OnceLock<Vec<SocketAddr>>,
 }
 
 impl MockSpawner {
@@ -26,31 +33,68 @@ impl MockSpawner {
         MockSpawner {
             urls,
             worker_counter: AtomicUsize::new(0),
+            pool_size: 0,
+            pool: OnceLock::new(),
+        }
+    }
+
+    /// Creates a spawner that shares `pool_size` servers among all Executors.
+    pub fn pooled(pool_size: usize) -> Self {
+        assert!(pool_size > 0, "Pool size must be greater than 0");
+        MockSpawner {
+            pool_size,
+            ..MockSpawner::new(None)
         }
     }
+
+    fn start_server() -> SocketAddr {
+        // Bind synchronously, so the address is known before the server runs
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(mini_tree_router().into_make_service())
+                .await
+                .unwrap();
+        });
+        addr
+    }
 }
 
 impl ExecutorSpawner for MockSpawner {
-    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Executor> + Send>> {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
         let (tx, rx) = oneshot::channel();
 
         let id = self.worker_counter.fetch_add(1, Ordering::SeqCst);
 
+        if self.pool_size > 0 {
+            let pool = self.pool.get_or_init(|| {
+                (0..self.pool_size)
+                    .map(|_| MockSpawner::start_server())
+                    .collect()
+            });
+            let worker_url = format!("http://{}", pool[id % pool.len()]);
+            return Box::pin(async move { Ok(Executor::new(worker_url, None)) });
+        }
+
         // If urls is not None, use the urls to spawn executors
         if self.urls.is_some() && self.urls.as_ref().unwrap().len() > id {
             let url = self.urls.as_ref().unwrap()[id].clone();
-            let _ = tx.send(SocketAddr::from_str(&url).unwrap());
+            let addr = SocketAddr::from_str(&url);
 
             return Box::pin(async move {
-                let url = rx.await.expect("Failed to receive worker URL");
+                let url = addr.map_err(|e| SpawnError::Worker(e.into()))?;
                 let worker_url = format!("http://{}", url);
-                Executor::new(worker_url, None)
+                Ok(Executor::new(worker_url, None))
             });
         }
 
         // if there is no url or already used all urls, spawn a new executor
         tokio::spawn(async move {
-            let app = Router::new().route("/", post(create_mst));
+            let app = mini_tree_router();
 
             // Bind to port 0 to let the OS choose a random port
             let addr = SocketAddr::from(([127, 0, 0, 1], 0));
@@ -67,9 +111,11 @@ impl ExecutorSpawner for MockSpawner {
         // Return a Future that resolves to Executor
         Box::pin(async move {
             // load currnet worker counter
-            let url = rx.await.expect("Failed to receive worker URL");
+            let url = rx
+                .await
+                .map_err(|_| SpawnError::Worker("Failed to receive worker URL".into()))?;
             let worker_url = format!("http://{}", url);
-            Executor::new(worker_url, None)
+            Ok(Executor::new(worker_url, None))
         })
     }
 
@@ -89,8 +135,8 @@ mod tests {
         let spawner = MockSpawner::new(None);
 
         // Spawn 2 executors
-        let executor_1 = spawner.spawn_executor().await;
-        let executor_2 = spawner.spawn_executor().await;
+        let executor_1 = spawner.spawn_executor().await.unwrap();
+        let executor_2 = spawner.spawn_executor().await.unwrap();
 
         // Sleep 2 seconds for the container to be ready
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -104,11 +150,30 @@ mod tests {
         let spawner = MockSpawner::new(Some(urls));
 
         // Spawn 2 executors
-        let executor_1 = spawner.spawn_executor().await;
-        let executor_2 = spawner.spawn_executor().await;
+        let executor_1 = spawner.spawn_executor().await.unwrap();
+        let executor_2 = spawner.spawn_executor().await.unwrap();
 
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         assert_eq!(executor_1.get_url(), "http://192.168.0.1:65535");
         assert_ne!(executor_2.get_url(), "http://192.168.0.1:65535");
     }
+
+    #[tokio::test]
+    async fn test_pooled() {
+        let spawner = MockSpawner::pooled(2);
+
+        let mut urls = Vec::new();
+        for _ in 0..5 {
+            urls.push(spawner.spawn_executor().await.unwrap().get_url());
+        }
+
+        // Executors are distributed over the two servers round-robin
+        assert_ne!(urls[0], urls[1]);
+        assert_eq!(urls[0], urls[2]);
+        assert_eq!(urls[1], urls[3]);
+        assert_eq!(urls[0], urls[4]);
+
+        let executor = spawner.spawn_executor().await.unwrap();
+        assert!(executor.health_check().await.is_ok());
+    }
 }
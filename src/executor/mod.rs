@@ -1,21 +1,50 @@
 mod cloud_spawner;
+mod in_process_spawner;
+#[cfg(feature = "kubernetes")]
+mod k8s_spawner;
 mod local_spawner;
 mod mock_spawner;
+mod request_tracker;
+mod retry_policy;
 mod spawner;
+mod static_spawner;
+#[cfg(test)]
 mod test;
 mod utils;
 
 pub use cloud_spawner::CloudSpawner;
-pub use local_spawner::LocalSpawner;
+pub use in_process_spawner::InProcessSpawner;
+#[cfg(feature = "kubernetes")]
+pub use k8s_spawner::K8sSpawner;
+pub use local_spawner::{ContainerLimits, ContainerSettings, LocalSpawner};
 pub use mock_spawner::MockSpawner;
-pub use spawner::ExecutorSpawner;
+pub use request_tracker::RequestTracker;
+pub use retry_policy::RetryPolicy;
+pub use spawner::{ExecutorSpawner, SpawnError};
+pub use static_spawner::StaticSpawner;
+pub use utils::ServiceDeployment;
 
-use reqwest::Client;
-use std::error::Error;
-use tokio::time::{sleep, Duration};
+pub use reqwest::{header::HeaderMap, Certificate};
 
-use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
-use summa_backend::merkle_sum_tree::MerkleSumTree;
+use flate2::{write::GzEncoder, Compression};
+use futures::{future, stream, Future, Stream, StreamExt};
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{error::Error, fmt, sync::Arc};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::warn;
+
+use crate::json_mst::{JsonCryptocurrency, JsonEntry, JsonMerkleSumTree};
+use crate::mini_tree_generator::{
+    build_mst, BatchItem, TreeQuery, TreeRequest, WorkerInfo, N_BYTES_HEADER, N_CURRENCIES_HEADER,
+};
+use summa_backend::merkle_sum_tree::{Cryptocurrency, MerkleSumTree};
+
+// Interval between two polls of the readiness route while waiting for a Worker
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Executor role and functionality.
 /// Acts as an intermediary between the Orchestrator and Workers, facilitating the data processing workflow.
@@ -27,20 +56,340 @@ use summa_backend::merkle_sum_tree::MerkleSumTree;
 /// - **Communication Bridge**: They facilitate communication within the data pipeline, relaying 'mini-tree' from Workers to the Orchestrator.
 ///
 /// Executors are dynamically spawned and connected to Workers for task execution.
+///
+/// Every request to the Worker is bounded by a request timeout, 60 seconds by default.
+/// A request that times out is retried like any other failed request, so the worst-case latency of
+/// `generate_tree` is roughly `max_attempts * request_timeout` plus the retry delays.
+///
+/// Responses are requested gzip-compressed and transparently decompressed, which mostly pays off for deep trees.
+/// Optionally, the entries can be sent gzip-compressed as well. This requires a Worker that decompresses request bodies,
+/// which `mini_tree_router` does, so it is disabled by default to keep older Workers reachable.
+///
+/// Connections to the Worker are kept alive and reused by the following requests, see `with_pool_idle_timeout`
+/// and `with_pool_max_idle`. By default, an idle connection is closed after 90 seconds and all of them are kept.
+///
+/// Workers behind TLS are reached with an `https://` URL. If their certificate is signed by a private CA,
+/// that CA has to be trusted with `with_root_certificate`.
+///
+/// Every tree from the Worker is verified against its entries before it is returned, see `with_tree_verification`.
+/// The trees can also be requested without their nodes with `with_omitted_nodes`, the Executor then rebuilds them.
+///
+/// The Worker names the cryptocurrencies of the trees "DUMMY", unless they are sent along with the entries,
+/// see `with_cryptocurrencies`.
+///
+/// Static headers, e.g. the `Authorization` header required by an API gateway in front of the Worker,
+/// can be attached to every request with `with_headers`.
+///
+/// An Executor created with `in_process` has no Worker, it builds the trees on a blocking thread of its own process
+/// with the code of `mini_tree_generator`, e.g. for tests that should not depend on free ports or HTTP timing.
+///
+/// Requests are counted by a `RequestTracker`, which spawners share over their Executors to drain them before termination.
+/// A draining Executor refuses new requests.
 #[derive(Clone)]
 pub struct Executor {
     client: Client,
     url: String,
     id: Option<String>,
+    retry_policy: RetryPolicy,
+    request_timeout: Duration,
+    fallback_warmup: Duration,
+    compress_requests: bool,
+    omit_nodes: bool,
+    sort_entries: bool,
+    verify_trees: bool,
+    root_certificate: Option<Certificate>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle: usize,
+    headers: HeaderMap,
+    container: Option<(String, u16)>,
+    request_tracker: Arc<RequestTracker>,
+    cryptocurrencies: Option<Vec<JsonCryptocurrency>>,
+    in_process: bool,
 }
 
+/// Errors that can occur while an Executor works with its Worker.
+///
+/// All variants are `Send`, so they can be passed through the channels of the Orchestrator.
+#[derive(Debug)]
+pub enum ExecutorError {
+    /// The Worker could not be reached.
+    Connection(reqwest::Error),
+    /// The Worker did not respond within the request timeout.
+    Timeout(reqwest::Error),
+    /// The Worker responded with a body that is not a valid `JsonMerkleSumTree`.
+    DeserializeResponse(reqwest::Error),
+    /// The Worker responded with a non-success status code, the body usually explains why.
+    WorkerStatus {
+        url: String,
+        status: u16,
+        body: String,
+    },
+    /// The `JsonMerkleSumTree` from the Worker could not be converted into a `MerkleSumTree`.
+    TreeReconstruction(String),
+    /// The entries could not be compressed before sending them to the Worker.
+    Compression(std::io::Error),
+    /// The HTTP client could not be built with the given configuration, e.g. the root certificate.
+    ClientConfiguration(reqwest::Error),
+    /// The Executor is being drained before termination and does not accept new requests.
+    Draining,
+    /// The Worker cannot build trees of the shape of the Orchestrator, see `Executor::check_tree_shape`.
+    UnsupportedShape {
+        url: String,
+        n_currencies: usize,
+        n_bytes: usize,
+        worker_info: WorkerInfo,
+    },
+}
+
+impl From<reqwest::Error> for ExecutorError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            ExecutorError::Timeout(err)
+        } else if err.is_decode() {
+            ExecutorError::DeserializeResponse(err)
+        } else {
+            ExecutorError::Connection(err)
+        }
+    }
+}
+
+impl fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutorError::Connection(err) => write!(f, "Failed to connect to worker: {}", err),
+            ExecutorError::Timeout(err) => write!(f, "Worker request timed out: {}", err),
+            ExecutorError::DeserializeResponse(err) => {
+                write!(f, "Failed to deserialize worker response: {}", err)
+            }
+            ExecutorError::WorkerStatus { url, status, body } => {
+                write!(
+                    f,
+                    "Worker {} responded with status {}: {}",
+                    url, status, body
+                )
+            }
+            ExecutorError::TreeReconstruction(reason) => {
+                write!(
+                    f,
+                    "Failed to reconstruct tree from worker response: {}",
+                    reason
+                )
+            }
+            ExecutorError::Compression(err) => write!(f, "Failed to compress entries: {}", err),
+            ExecutorError::ClientConfiguration(err) => {
+                write!(f, "Failed to configure HTTP client: {}", err)
+            }
+            ExecutorError::Draining => write!(f, "Executor is draining, no new requests accepted"),
+            ExecutorError::UnsupportedShape {
+                url,
+                n_currencies,
+                n_bytes,
+                worker_info,
+            } => write!(
+                f,
+                "Worker {} cannot build trees of {} currencies with {} bytes, it builds {} currencies with {} bytes \
+                 and supports {:?} currencies with {:?} bytes",
+                url,
+                n_currencies,
+                n_bytes,
+                worker_info.n_currencies,
+                worker_info.n_bytes,
+                worker_info.supported_n_currencies,
+                worker_info.supported_n_bytes
+            ),
+        }
+    }
+}
+
+impl Error for ExecutorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExecutorError::Connection(err)
+            | ExecutorError::Timeout(err)
+            | ExecutorError::DeserializeResponse(err)
+            | ExecutorError::ClientConfiguration(err) => Some(err),
+            ExecutorError::Compression(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// The URL of every Executor created with `Executor::in_process`, naming it in logs and errors.
+pub const IN_PROCESS_URL: &str = "in-process";
+/// How long a connection to the Worker is kept open without requests, reqwest's default.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// How many idle connections to the Worker are kept open, all of them like reqwest does by default.
+pub const DEFAULT_POOL_MAX_IDLE: usize = usize::MAX;
+/// How long `wait_until_ready` waits for a Worker that has neither a readiness nor a health route.
+pub const DEFAULT_FALLBACK_WARMUP: Duration = Duration::from_secs(5);
+
 impl Executor {
+    /// Creates an Executor with the default `RetryPolicy`, 5 attempts with a fixed 1 second delay.
     pub fn new(url: String, id: Option<String>) -> Self {
-        Executor {
+        Executor::with_retry_policy(url, id, RetryPolicy::default())
+    }
+
+    pub fn with_retry_policy(url: String, id: Option<String>, retry_policy: RetryPolicy) -> Self {
+        let mut executor = Executor {
             client: Client::new(),
             url,
             id,
+            retry_policy,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            fallback_warmup: DEFAULT_FALLBACK_WARMUP,
+            compress_requests: false,
+            omit_nodes: false,
+            sort_entries: false,
+            verify_trees: true,
+            root_certificate: None,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            pool_max_idle: DEFAULT_POOL_MAX_IDLE,
+            headers: HeaderMap::new(),
+            container: None,
+            request_tracker: Arc::default(),
+            cryptocurrencies: None,
+            in_process: false,
+        };
+        // Like `Client::new`, this only fails if the TLS backend cannot be initialized
+        executor.client = executor
+            .build_client()
+            .expect("Failed to build HTTP client");
+        executor
+    }
+
+    /// Creates an Executor that builds the trees in this process instead of requesting a Worker.
+    ///
+    /// It is always healthy and ready, and supports the tree shapes of `WorkerInfo::local`.
+    /// The settings that only concern the requests, e.g. the retry policy or the compression, have no effect.
+    pub fn in_process(id: Option<String>) -> Self {
+        let mut executor = Executor::new(IN_PROCESS_URL.to_string(), id);
+        executor.in_process = true;
+        executor
+    }
+
+    fn build_client(&self) -> Result<Client, reqwest::Error> {
+        let mut builder = Client::builder()
+            .gzip(true)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle);
+        if let Some(certificate) = &self.root_certificate {
+            builder = builder.add_root_certificate(certificate.clone());
         }
+        builder.build()
+    }
+
+    /// Trusts the given CA certificate, in addition to the system roots, when connecting to an `https://` Worker.
+    ///
+    /// The certificate can be loaded from PEM with `Certificate::from_pem`.
+    pub fn with_root_certificate(
+        mut self,
+        certificate: Certificate,
+    ) -> Result<Self, ExecutorError> {
+        self.root_certificate = Some(certificate);
+        self.client = self
+            .build_client()
+            .map_err(ExecutorError::ClientConfiguration)?;
+        Ok(self)
+    }
+
+    /// Closes connections to the Worker that were idle for `pool_idle_timeout`, never if `None`.
+    ///
+    /// A timeout longer than the gap between two chunks sent to the Worker spares a new TCP and TLS handshake per chunk,
+    /// but it should stay below the idle timeout of any proxy in front of the Worker, which would close them first.
+    pub fn with_pool_idle_timeout(
+        mut self,
+        pool_idle_timeout: Option<Duration>,
+    ) -> Result<Self, ExecutorError> {
+        self.pool_idle_timeout = pool_idle_timeout;
+        self.client = self
+            .build_client()
+            .map_err(ExecutorError::ClientConfiguration)?;
+        Ok(self)
+    }
+
+    /// Keeps at most `pool_max_idle` idle connections to the Worker open, 0 opens a connection per request.
+    pub fn with_pool_max_idle(mut self, pool_max_idle: usize) -> Result<Self, ExecutorError> {
+        self.pool_max_idle = pool_max_idle;
+        self.client = self
+            .build_client()
+            .map_err(ExecutorError::ClientConfiguration)?;
+        Ok(self)
+    }
+
+    /// Sets the timeout applied to each request sent to the Worker.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Sets the fixed warmup `wait_until_ready` gives a Worker that has neither a readiness nor a health route.
+    pub fn with_fallback_warmup(mut self, fallback_warmup: Duration) -> Self {
+        self.fallback_warmup = fallback_warmup;
+        self
+    }
+
+    /// Sends the entries gzip-compressed with `Content-Encoding: gzip`.
+    pub fn with_request_compression(mut self, compress_requests: bool) -> Self {
+        self.compress_requests = compress_requests;
+        self
+    }
+
+    /// Requests the trees without their `nodes` and rebuilds them from the entries, checking them against the root.
+    ///
+    /// This shrinks the responses of deep trees at the cost of hashing on the Executor's side.
+    /// Requires a Worker that supports the `omit_nodes` query parameter, which `mini_tree_router` does.
+    pub fn with_omitted_nodes(mut self, omit_nodes: bool) -> Self {
+        self.omit_nodes = omit_nodes;
+        self
+    }
+
+    /// Requests the trees with their entries sorted by username, as some proof schemes require.
+    ///
+    /// Requires a Worker that supports the `sorted` query parameter, which `mini_tree_router` does.
+    pub fn with_sorted_entries(mut self, sort_entries: bool) -> Self {
+        self.sort_entries = sort_entries;
+        self
+    }
+
+    /// Verifies that the nodes and root of every tree from the Worker match its entries, enabled by default.
+    ///
+    /// Disabling it saves recomputing the tree, but trusts the Worker to send a consistent tree.
+    pub fn with_tree_verification(mut self, verify_trees: bool) -> Self {
+        self.verify_trees = verify_trees;
+        self
+    }
+
+    /// Attaches the given headers to every request sent to the Worker.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Records the container running the Worker and the host port it is published on.
+    pub fn with_container(mut self, container_id: String, port: u16) -> Self {
+        self.container = Some((container_id, port));
+        self
+    }
+
+    /// Counts the requests of this Executor with the given tracker, usually shared with other Executors.
+    pub fn with_request_tracker(mut self, request_tracker: Arc<RequestTracker>) -> Self {
+        self.request_tracker = request_tracker;
+        self
+    }
+
+    /// Sends the cryptocurrencies of the balances along with the entries, so the Worker builds the trees with them.
+    ///
+    /// Requires a Worker that accepts the entries with their cryptocurrencies, see `TreeRequest`,
+    /// which `mini_tree_router` does.
+    pub fn with_cryptocurrencies(mut self, cryptocurrencies: &[Cryptocurrency]) -> Self {
+        self.cryptocurrencies = Some(
+            cryptocurrencies
+                .iter()
+                .map(JsonCryptocurrency::from_cryptocurrency)
+                .collect(),
+        );
+        self
     }
 
     pub fn get_url(&self) -> String {
@@ -51,37 +400,516 @@ impl Executor {
         self.id.clone()
     }
 
+    /// Returns the id of the Worker container, if the Executor was spawned with one, e.g. by `LocalSpawner`.
+    pub fn get_container_id(&self) -> Option<String> {
+        self.container
+            .as_ref()
+            .map(|(container_id, _)| container_id.clone())
+    }
+
+    /// Returns the host port of the Worker container, if the Executor was spawned with one, e.g. by `LocalSpawner`.
+    pub fn get_port(&self) -> Option<u16> {
+        self.container.as_ref().map(|(_, port)| *port)
+    }
+
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn get_request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// Checks that the Worker is up by requesting its `/health` route.
+    pub async fn health_check(&self) -> Result<(), ExecutorError> {
+        self.get_route("health").await.map(|_| ())
+    }
+
+    /// Checks that the Worker can start a build at once by requesting its `/readyz` route.
+    pub async fn ready_check(&self) -> Result<(), ExecutorError> {
+        self.get_route("readyz").await.map(|_| ())
+    }
+
+    // Requests the `path` route of the Worker, failing unless it responds with a success status.
+    // An in-process Executor has no Worker to request, so it gets no response.
+    async fn get_route(&self, path: &str) -> Result<Option<reqwest::Response>, ExecutorError> {
+        if self.in_process {
+            return Ok(None);
+        }
+        let response = self
+            .client
+            .get(format!("{}/{}", self.url.trim_end_matches('/'), path))
+            .headers(self.headers.clone())
+            .timeout(self.request_timeout)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ExecutorError::WorkerStatus {
+                url: self.url.clone(),
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(Some(response))
+    }
+
+    /// Polls the `/readyz` route of the Worker until it responds with `200 OK`,
+    /// failing with the last error once `ready_timeout` elapses.
+    ///
+    /// A Worker that predates the readiness route is polled on `/health` instead.
+    /// A Worker without either route is up but cannot tell when it is ready,
+    /// it is given the fixed warmup of `with_fallback_warmup` instead, 5 seconds by default.
+    pub async fn wait_until_ready(&self, ready_timeout: Duration) -> Result<(), ExecutorError> {
+        // Each poll must not outlive the deadline
+        let executor = self.clone().with_request_timeout(READY_POLL_INTERVAL);
+        let deadline = Instant::now() + ready_timeout;
+        loop {
+            let ready = match executor.ready_check().await {
+                Err(ExecutorError::WorkerStatus { status: 404, .. }) => {
+                    executor.health_check().await
+                }
+                ready => ready,
+            };
+            match ready {
+                Ok(()) => return Ok(()),
+                Err(ExecutorError::WorkerStatus { status: 404, .. }) => {
+                    sleep(self.fallback_warmup).await;
+                    return Ok(());
+                }
+                Err(err) if Instant::now() >= deadline => return Err(err),
+                Err(_) => sleep(READY_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Requests the tree shapes the Worker builds from its `/info` route.
+    pub async fn worker_info(&self) -> Result<WorkerInfo, ExecutorError> {
+        match self.get_route("info").await? {
+            Some(response) => Ok(response.json().await?),
+            None => Ok(WorkerInfo::local()),
+        }
+    }
+
+    /// Checks that the Worker builds trees of N_CURRENCIES currencies with N_BYTES bytes, see `WorkerInfo::supports`.
+    ///
+    /// A Worker built for another shape would otherwise build trees that fail to verify, or silently drop balances.
+    /// Workers that predate the `/info` route cannot be checked and pass with a warning.
+    pub async fn check_tree_shape<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+    ) -> Result<(), ExecutorError> {
+        let worker_info = match self.worker_info().await {
+            Ok(worker_info) => worker_info,
+            Err(ExecutorError::WorkerStatus { status: 404, .. }) => {
+                warn!(url = %self.url, "worker has no info route, its tree shape is not checked");
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        if !worker_info.supports(N_CURRENCIES, N_BYTES) {
+            return Err(ExecutorError::UnsupportedShape {
+                url: self.url.clone(),
+                n_currencies: N_CURRENCIES,
+                n_bytes: N_BYTES,
+                worker_info,
+            });
+        }
+        Ok(())
+    }
+
     pub async fn generate_tree<const N_CURRENCIES: usize, const N_BYTES: usize>(
         &self,
         json_entries: Vec<JsonEntry>,
-    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error + Send>>
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>
     where
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        const MAX_RETRIES: u32 = 5;
-        const RETRY_DELAY: Duration = Duration::from_secs(1);
+        self.generate_tree_with_build_time(json_entries)
+            .await
+            .map(|(tree, _)| tree)
+    }
+
+    /// Like `generate_tree`, but also returns the time the Worker took to build the tree,
+    /// or `None` if the Worker does not report it, see `JsonMerkleSumTree::build_ms`.
+    #[tracing::instrument(name = "generate_tree", skip_all, fields(url = %self.url, entries = json_entries.len()))]
+    pub async fn generate_tree_with_build_time<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+        json_entries: Vec<JsonEntry>,
+    ) -> Result<(MerkleSumTree<N_CURRENCIES, N_BYTES>, Option<Duration>), ExecutorError>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if self.in_process {
+            let item = self
+                .build_in_process(move |query, cryptocurrencies| {
+                    build_batch_item(
+                        &query,
+                        &cryptocurrencies,
+                        &json_entries,
+                        N_CURRENCIES,
+                        N_BYTES,
+                    )
+                })
+                .await?;
+            let build_time = match &item {
+                BatchItem::Tree(json_tree) => json_tree.build_ms.map(Duration::from_millis),
+                BatchItem::Error(_) => None,
+            };
+            return batch_item_to_tree(item, &self.url, self.verify_trees)
+                .map(|tree| (tree, build_time));
+        }
+        let json_tree: JsonMerkleSumTree = self
+            .post_with_retries(
+                &self.url,
+                &self.tree_request(&json_entries),
+                N_CURRENCIES,
+                N_BYTES,
+            )
+            .await?;
+        let build_time = json_tree.build_ms.map(Duration::from_millis);
+        json_tree_to_mst(json_tree, self.verify_trees).map(|tree| (tree, build_time))
+    }
+
+    /// Builds a tree from each of the batches with a single request to the `/batch` route of the Worker,
+    /// saving the per-request overhead for many small batches.
+    ///
+    /// The trees are returned in the order of the batches. A batch rejected by the Worker fails with
+    /// `WorkerStatus`, just like it would with `generate_tree`, without failing the other batches.
+    pub async fn generate_trees<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+        batches: Vec<Vec<JsonEntry>>,
+    ) -> Result<Vec<Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>>, ExecutorError>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if self.in_process {
+            let items = self
+                .build_in_process(move |query, cryptocurrencies| {
+                    batches
+                        .iter()
+                        .map(|json_entries| {
+                            build_batch_item(
+                                &query,
+                                &cryptocurrencies,
+                                json_entries,
+                                N_CURRENCIES,
+                                N_BYTES,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await?;
+            return Ok(items
+                .into_iter()
+                .map(|item| batch_item_to_tree(item, &self.url, self.verify_trees))
+                .collect());
+        }
+        let url = format!("{}/batch", self.url.trim_end_matches('/'));
+        let items: Vec<BatchItem> = self
+            .post_with_retries(&url, &self.tree_request(&batches), N_CURRENCIES, N_BYTES)
+            .await?;
+
+        let trees = items
+            .into_iter()
+            .map(|item| batch_item_to_tree(item, &url, self.verify_trees))
+            .collect();
+        Ok(trees)
+    }
+
+    /// Like `generate_trees`, but requests the `/batch/stream` route of the Worker and yields each tree as soon as
+    /// the Worker has built it, so the first trees can be processed while the Worker is still building the others.
+    ///
+    /// Only sending the request is retried, if the connection fails midway the stream ends with the error.
+    pub async fn generate_trees_stream<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+        batches: Vec<Vec<JsonEntry>>,
+    ) -> Result<
+        impl Stream<Item = Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>> + Send,
+        ExecutorError,
+    >
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        // Built at once, the trees are then yielded one by one
+        if self.in_process {
+            let trees = self.generate_trees(batches).await?;
+            return Ok(stream::iter(trees).left_stream());
+        }
+
+        // Moved into the stream, so draining waits until the last tree is read
+        let request_guard = self
+            .request_tracker
+            .start()
+            .ok_or(ExecutorError::Draining)?;
+
+        let url = format!("{}/batch/stream", self.url.trim_end_matches('/'));
+        // The body is read by the stream, so nothing is left to retry once the Worker responds
+        let response = self
+            .send_with_retries(
+                &url,
+                &self.tree_request(&batches),
+                N_CURRENCIES,
+                N_BYTES,
+                |response| future::ready(Ok(response)),
+            )
+            .await?;
+
+        // Each line of the response is a `BatchItem`, the state is `None` once the stream has ended
+        let verify_trees = self.verify_trees;
+        let state = Some((response, Vec::new(), request_guard));
+        Ok(stream::unfold(state, move |state| {
+            let url = url.clone();
+            async move {
+                let (mut response, mut buffer, request_guard) = state?;
+                loop {
+                    if let Some(position) = buffer.iter().position(|byte| *byte == b'\n') {
+                        let line = buffer.drain(..=position).collect::<Vec<u8>>();
+                        if line.iter().all(u8::is_ascii_whitespace) {
+                            continue;
+                        }
+                        let tree = parse_batch_line(&line, &url, verify_trees);
+                        return Some((tree, Some((response, buffer, request_guard))));
+                    }
+
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                        // The last line may lack the trailing newline
+                        Ok(None) if !buffer.iter().all(u8::is_ascii_whitespace) => {
+                            return Some((parse_batch_line(&buffer, &url, verify_trees), None));
+                        }
+                        Ok(None) => return None,
+                        Err(err) => return Some((Err(err.into()), None)),
+                    }
+                }
+            }
+        })
+        .right_stream())
+    }
+
+    // Runs `build` with the query and cryptocurrencies a Worker would receive, on a blocking thread like a Worker does
+    async fn build_in_process<R: Send + 'static>(
+        &self,
+        build: impl FnOnce(TreeQuery, Vec<JsonCryptocurrency>) -> R + Send + 'static,
+    ) -> Result<R, ExecutorError> {
+        let _request_guard = self
+            .request_tracker
+            .start()
+            .ok_or(ExecutorError::Draining)?;
+        let query = TreeQuery {
+            omit_nodes: self.omit_nodes,
+            sorted: self.sort_entries,
+        };
+        let cryptocurrencies = self.cryptocurrencies.clone().unwrap_or_default();
+        tokio::task::spawn_blocking(move || build(query, cryptocurrencies))
+            .await
+            .map_err(|err| {
+                ExecutorError::TreeReconstruction(format!("In-process build failed: {}", err))
+            })
+    }
+
+    // The body of the tree building routes, with the cryptocurrencies if they were set
+    fn tree_request<'a, T>(&self, entries: &'a T) -> TreeRequest<&'a T> {
+        match &self.cryptocurrencies {
+            Some(cryptocurrencies) => TreeRequest::WithCryptocurrencies {
+                cryptocurrencies: cryptocurrencies.clone(),
+                entries,
+            },
+            None => TreeRequest::Entries(entries),
+        }
+    }
+
+    // Builds a request posting `body` to `url`, or `compressed_body` instead if given
+    fn post_request<T: Serialize>(
+        &self,
+        url: &str,
+        body: &T,
+        compressed_body: Option<&Vec<u8>>,
+        n_currencies: usize,
+        n_bytes: usize,
+    ) -> RequestBuilder {
+        let mut request = self
+            .client
+            .post(url)
+            .headers(self.headers.clone())
+            // Lets the Worker build trees of the requested shape, regardless of its defaults
+            .header(N_CURRENCIES_HEADER, n_currencies)
+            .header(N_BYTES_HEADER, n_bytes)
+            .timeout(self.request_timeout);
+        if self.omit_nodes {
+            request = request.query(&[("omit_nodes", "true")]);
+        }
+        if self.sort_entries {
+            request = request.query(&[("sorted", "true")]);
+        }
+        match compressed_body {
+            Some(compressed_body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .header(CONTENT_ENCODING, "gzip")
+                .body(compressed_body.clone()),
+            None => request.json(body),
+        }
+    }
+
+    // Posts `body` to `url`, retrying according to the retry policy, and deserializes the response.
+    async fn post_with_retries<T: Serialize, R: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &T,
+        n_currencies: usize,
+        n_bytes: usize,
+    ) -> Result<R, ExecutorError> {
+        // Held until the response is read, so draining waits for the whole request including retries
+        let _request_guard = self
+            .request_tracker
+            .start()
+            .ok_or(ExecutorError::Draining)?;
+
+        self.send_with_retries(url, body, n_currencies, n_bytes, |response| {
+            response.json::<R>()
+        })
+        .await
+    }
+
+    // Posts `body` to `url` and reads the successful response with `read`, retrying according to the retry policy.
+    // A failure of `read` is retried too, unless the response is malformed.
+    async fn send_with_retries<T: Serialize, R, F: Future<Output = reqwest::Result<R>>>(
+        &self,
+        url: &str,
+        body: &T,
+        n_currencies: usize,
+        n_bytes: usize,
+        read: impl Fn(Response) -> F,
+    ) -> Result<R, ExecutorError> {
+        // Compress once, the same body is reused by every attempt
+        let compressed_body = if self.compress_requests {
+            Some(gzip_json(body).map_err(ExecutorError::Compression)?)
+        } else {
+            None
+        };
 
         let mut attempts = 0;
         loop {
             attempts += 1;
-            let response = self.client.post(&self.url).json(&json_entries).send().await;
+            let can_retry = attempts < self.retry_policy.max_attempts;
+
+            let request =
+                self.post_request(url, body, compressed_body.as_ref(), n_currencies, n_bytes);
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(err) if can_retry => {
+                    warn!(attempt = attempts, error = %err, "request to worker failed, retrying");
+                    sleep(self.retry_policy.delay_for_attempt(attempts)).await;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
 
-            match response {
-                Ok(response) => {
-                    let json_tree = response
-                        .json::<JsonMerkleSumTree>()
-                        .await
-                        .map_err(|err| Box::new(err) as Box<dyn Error + Send>)?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
 
-                    let tree = json_tree.to_mst().unwrap();
-                    return Ok(tree);
+                // A server error may be transient, a client error means the Worker rejected the entries.
+                if status.is_server_error() && can_retry {
+                    warn!(
+                        attempt = attempts,
+                        status = status.as_u16(),
+                        "worker responded with a server error, retrying"
+                    );
+                    sleep(self.retry_policy.delay_for_attempt(attempts)).await;
+                    continue;
                 }
-                Err(_err) if attempts < MAX_RETRIES => {
-                    sleep(RETRY_DELAY).await;
+                return Err(ExecutorError::WorkerStatus {
+                    url: url.to_string(),
+                    status: status.as_u16(),
+                    body,
+                });
+            }
+
+            match read(response).await {
+                Ok(response) => return Ok(response),
+                // Only a malformed response fails immediately, a timeout while reading the body is retried.
+                Err(err) if !err.is_decode() && can_retry => {
+                    warn!(attempt = attempts, error = %err, "reading the response of the worker failed, retrying");
+                    sleep(self.retry_policy.delay_for_attempt(attempts)).await;
                 }
-                Err(err) => return Err(Box::new(err) as Box<dyn Error + Send>),
+                Err(err) => return Err(err.into()),
             }
         }
     }
 }
+
+fn json_tree_to_mst<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    json_tree: JsonMerkleSumTree,
+    verify: bool,
+) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let tree = if verify {
+        json_tree.to_mst()
+    } else {
+        json_tree.to_mst_unverified()
+    };
+    tree.map_err(|err| ExecutorError::TreeReconstruction(err.to_string()))
+}
+
+// The item a Worker would respond with for the entries of a batch
+fn build_batch_item(
+    query: &TreeQuery,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+    n_currencies: usize,
+    n_bytes: usize,
+) -> BatchItem {
+    match build_mst(n_currencies, n_bytes, query, cryptocurrencies, json_entries) {
+        Ok(json_tree) => BatchItem::Tree(json_tree),
+        Err(error) => BatchItem::Error(error),
+    }
+}
+
+fn batch_item_to_tree<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    item: BatchItem,
+    url: &str,
+    verify: bool,
+) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    match item {
+        BatchItem::Tree(json_tree) => json_tree_to_mst(json_tree, verify),
+        // Reported like a rejected `generate_tree` request
+        BatchItem::Error(error) => Err(ExecutorError::WorkerStatus {
+            url: url.to_string(),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+            body: serde_json::to_string(&error).unwrap_or(error.message),
+        }),
+    }
+}
+
+fn parse_batch_line<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    line: &[u8],
+    url: &str,
+    verify: bool,
+) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, ExecutorError>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    let item = serde_json::from_slice::<BatchItem>(line).map_err(|err| {
+        ExecutorError::TreeReconstruction(format!("Invalid line in worker response: {}", err))
+    })?;
+    batch_item_to_tree(item, url, verify)
+}
+
+fn gzip_json<T: Serialize>(value: &T) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, value)?;
+    encoder.finish()
+}
@@ -0,0 +1,102 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::sync::Notify;
+use tokio::time::{timeout_at, Duration, Instant};
+
+/// RequestTracker
+///
+/// Counts the requests in flight over all Executors sharing it, typically all Executors of one spawner.
+/// Draining the tracker makes the Executors refuse new requests and waits for the outstanding ones,
+/// so the Workers can be removed without cutting off a response.
+#[derive(Debug, Default)]
+pub struct RequestTracker {
+    in_flight: AtomicUsize,
+    draining: AtomicBool,
+    idle: Notify,
+}
+
+/// Marks a request as in flight until dropped.
+pub(crate) struct RequestGuard(Arc<RequestTracker>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl RequestTracker {
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Registers a new request, or returns `None` if the tracker is draining.
+    pub(crate) fn start(self: &Arc<Self>) -> Option<RequestGuard> {
+        // Count the request first, so a concurrent `drain` either waits for it or it sees the draining flag
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = RequestGuard(self.clone());
+        if self.is_draining() {
+            return None;
+        }
+        Some(guard)
+    }
+
+    /// Stops accepting new requests and waits until all requests in flight are completed, for at most `timeout`.
+    ///
+    /// Returns whether all requests were completed in time.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Created before checking the counter, so a notification in between is not missed
+            let idle = self.idle.notified();
+            if self.in_flight() == 0 {
+                return true;
+            }
+            if timeout_at(deadline, idle).await.is_err() {
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_requests_in_flight() {
+        let tracker = Arc::new(RequestTracker::default());
+        let guard = tracker.start().unwrap();
+        assert_eq!(tracker.in_flight(), 1);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(guard);
+        });
+
+        assert!(tracker.drain(Duration::from_secs(5)).await);
+        assert_eq!(tracker.in_flight(), 0);
+
+        // No new requests are accepted after draining
+        assert!(tracker.start().is_none());
+        assert_eq!(tracker.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let tracker = Arc::new(RequestTracker::default());
+        let _guard = tracker.start().unwrap();
+
+        assert!(!tracker.drain(Duration::from_millis(100)).await);
+        assert_eq!(tracker.in_flight(), 1);
+    }
+}
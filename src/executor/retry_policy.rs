@@ -0,0 +1,165 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cmp::min;
+use tokio::time::Duration;
+
+/// RetryPolicy
+///
+/// Controls how often an Executor retries a request to its Worker and how long it waits between attempts.
+///
+/// The delay after the n-th failed attempt is `base_delay * backoff_multiplier^(n - 1)`, capped by `max_delay` if set.
+/// Without a `backoff_multiplier` the delay stays constant at `base_delay`.
+/// With a `jitter`, the delay is then scaled by a random factor in `1 ± jitter`, so that Executors retrying
+/// on a recovering Worker spread their attempts instead of all waking at once.
+///
+/// The default policy makes 5 attempts with a fixed 1 second delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub backoff_multiplier: Option<f64>,
+    pub max_delay: Option<Duration>,
+    pub jitter: Option<f64>,
+    pub jitter_seed: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            backoff_multiplier: None,
+            max_delay: None,
+            jitter: None,
+            jitter_seed: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            base_delay,
+            ..Default::default()
+        }
+    }
+
+    /// Multiplies the delay by `multiplier` after every failed attempt.
+    pub fn with_exponential_backoff(mut self, multiplier: f64) -> Self {
+        self.backoff_multiplier = Some(multiplier);
+        self
+    }
+
+    /// Upper bound for the delay between two attempts.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Scales every delay by a random factor between `1 - jitter` and `1 + jitter`, e.g. 0.5 for ±50%.
+    ///
+    /// The jitter is applied after the `max_delay` cap, so a delay may exceed the cap by up to the jitter.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "Jitter must be between 0 and 1"
+        );
+        self.jitter = Some(jitter);
+        self
+    }
+
+    /// Draws the jitter from a generator seeded with `seed` and the attempt, making the delays reproducible.
+    ///
+    /// Meant for tests: Executors sharing a seed retry in lockstep, which is what the jitter is there to avoid.
+    pub fn with_jitter_seed(mut self, seed: u64) -> Self {
+        self.jitter_seed = Some(seed);
+        self
+    }
+
+    /// Returns how long to wait after the given failed attempt, counting from 1.
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let factor = self
+            .backoff_multiplier
+            .unwrap_or(1.0)
+            .powi(attempt.saturating_sub(1) as i32);
+
+        // An overflowing backoff saturates instead of panicking, the `max_delay` cap still applies.
+        let delay = Duration::try_from_secs_f64(self.base_delay.as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX);
+
+        let delay = match self.max_delay {
+            Some(max_delay) => min(delay, max_delay),
+            None => delay,
+        };
+
+        match self.jitter {
+            Some(jitter) if jitter > 0.0 => {
+                let factor = match self.jitter_seed {
+                    Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(attempt as u64))
+                        .gen_range(-jitter..=jitter),
+                    None => rand::thread_rng().gen_range(-jitter..=jitter),
+                };
+                Duration::try_from_secs_f64(delay.as_secs_f64() * (1.0 + factor))
+                    .unwrap_or(Duration::MAX)
+            }
+            _ => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_has_fixed_delay() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.max_attempts, 5);
+        assert_eq!(policy.delay_for_attempt(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_for_attempt(4), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_exponential_backoff_with_max_delay() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(100))
+            .with_exponential_backoff(2.0)
+            .with_max_delay(Duration::from_millis(500));
+
+        assert_eq!(policy.delay_for_attempt(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_for_attempt(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_for_attempt(3), Duration::from_millis(400));
+        assert_eq!(policy.delay_for_attempt(4), Duration::from_millis(500));
+        assert_eq!(policy.delay_for_attempt(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(1000))
+            .with_exponential_backoff(2.0)
+            .with_jitter(0.5);
+        for attempt in 1..=5 {
+            let backoff = 1000 * 2_u64.pow(attempt - 1);
+            let delay = policy.delay_for_attempt(attempt);
+            assert!(delay >= Duration::from_millis(backoff / 2));
+            assert!(delay <= Duration::from_millis(backoff * 3 / 2));
+        }
+
+        // A seeded jitter is reproducible, while differing between attempts
+        let seeded_policy = policy.with_jitter_seed(7);
+        let delays = (1..=5)
+            .map(|attempt| seeded_policy.delay_for_attempt(attempt))
+            .collect::<Vec<_>>();
+        let replayed_delays = (1..=5)
+            .map(|attempt| seeded_policy.clone().delay_for_attempt(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, replayed_delays);
+        let constant_policy = RetryPolicy::new(10, Duration::from_millis(1000))
+            .with_jitter(0.5)
+            .with_jitter_seed(7);
+        assert_ne!(
+            constant_policy.delay_for_attempt(1),
+            constant_policy.delay_for_attempt(2)
+        );
+    }
+}
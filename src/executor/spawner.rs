@@ -1,6 +1,46 @@
-use std::{future::Future, pin::Pin};
+use std::{error::Error, fmt, future::Future, pin::Pin, sync::Arc};
+use tokio::time::Duration;
+use tracing::warn;
 
-use crate::executor::Executor;
+use crate::executor::{Executor, ExecutorError, RequestTracker};
+
+/// Errors that can occur while spawning an Executor and its Worker.
+#[derive(Debug)]
+pub enum SpawnError {
+    /// The Worker could not be started, e.g. the Docker image is missing or the daemon is not running.
+    Worker(Box<dyn Error + Send + Sync>),
+    /// More executors were requested than the spawner has workers for.
+    Exhausted { requested: usize, available: usize },
+    /// The Executor could not be configured to connect to the Worker.
+    Executor(ExecutorError),
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::Worker(err) => write!(f, "Failed to spawn worker: {}", err),
+            SpawnError::Exhausted {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Requested {} executors but only {} worker URLs are configured",
+                requested, available
+            ),
+            SpawnError::Executor(err) => write!(f, "Failed to configure executor: {}", err),
+        }
+    }
+}
+
+impl Error for SpawnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpawnError::Worker(err) => Some(err.as_ref()),
+            SpawnError::Executor(err) => Some(err),
+            SpawnError::Exhausted { .. } => None,
+        }
+    }
+}
 
 /// ExecutorSpawner responsibility and types.
 ///
@@ -8,15 +48,19 @@ use crate::executor::Executor;
 ///
 /// Types include:
 /// - MockSpawner: For testing, runs `mini-tree-server` locally.
+/// - InProcessSpawner: For testing, builds the mini-trees in the process itself, without any server.
 /// - LocalSpawner: Initializes Executors and Workers in local Docker environments.
 /// - CloudSpawner: Optimized for cloud resources and Docker Swarm, manages containers as services for scalability.
+/// - K8sSpawner: Manages a Deployment of Workers on Kubernetes, available with the `kubernetes` feature.
+/// - StaticSpawner: Hands out Executors created beforehand, for Workers that are managed out of band and outlive the round.
 ///
 /// Note: ExecutorSpawner is a trait with key methods `spawn_executor` and `terminate_executor`.
 ///
 pub trait ExecutorSpawner {
     /// Spawns an executor asynchronously.
     //
-    /// This method initializes an Executor and returns a Future that resolves to the Executor.
+    /// This method initializes an Executor and returns a Future that resolves to the Executor,
+    /// or to a `SpawnError` if its Worker could not be started.
     ///
     /// To achieve this asynchronously (outside of an async trait function), we use a one-time channel ('oneshot`) to deliver the variables to the Future.
     ///
@@ -33,25 +77,40 @@ pub trait ExecutorSpawner {
     /// 3. Spawns an asynchronous task (`tokio::spawn`) that asynchronously creates a worker and sends back its information.
     ///    ```ignore
     ///     tokio::spawn(async move {
-    ///     if let Ok(worker_info) =
-    ///         Spawner::create_worker(url).await
-    ///     {
+    ///         let worker_info = Spawner::create_worker(url).await;
     ///         let _ = tx.send(worker_info);
-    ///     }
     ///    });
     ///     Note that, the "create_worker" is typically declared in the "Spawner" struct that has "ExecutorSpawner"trait.
     /// 4. Returns a Future that, upon completion, provides an Executor connected to the newly spawned worker.
     ///    ```ignore
     ///    Box::pin(async move {
-    ///             let url = rx.await.expect("Failed to receive worker URL");
-    ///             Executor::new(url, None);
+    ///             let url = rx
+    ///                 .await
+    ///                 .map_err(|_| SpawnError::Worker("Failed to receive worker URL".into()))?
+    ///                 .map_err(SpawnError::Worker)?;
+    ///             Ok(Executor::new(url, None))
     ///    });
     ///   ```
     ///
     ///
     // Returns:
-    // - "Pin<Box<dyn Future<Output = ()> + Send>>": A Future that, when awaited, yields an Executor instance and spawns a worker.
-    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Executor> + Send>>;
+    // - "Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>>": A Future that, when awaited, yields an Executor instance and spawns a worker.
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>>;
+
+    /// Returns the maximum number of executors this spawner can provide, or `None` if it is unbounded.
+    ///
+    /// The Orchestrator checks the requested executor count against it before spawning any executor.
+    fn max_executors(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the number of executors the spawner is set up for, e.g. the replicas of the service it deploys.
+    ///
+    /// The Orchestrator spawns this many executors if no count is given to `create_aggregation_mst`,
+    /// and warns if a given count differs from it.
+    fn default_executors(&self) -> Option<usize> {
+        None
+    }
 
     /// Terminates all spawned executors (and/or workers) asynchronously.
     ///
@@ -67,4 +126,33 @@ pub trait ExecutorSpawner {
     // Returns:
     // - "Pin<Box<dyn Future<Output = ()> + Send>>": A Future that, when awaited, indicates that all executors (and/or workers) have been terminated.
     fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// Returns the `RequestTracker` shared by all spawned executors, if the spawner tracks their requests.
+    fn request_tracker(&self) -> Option<Arc<RequestTracker>> {
+        None
+    }
+
+    /// Terminates all spawned executors (and/or workers) after draining them.
+    ///
+    /// The executors stop accepting new requests first, then the requests in flight are awaited for at most `timeout`
+    /// before `terminate_executors` removes the workers. Without a `request_tracker`, this is `terminate_executors`.
+    fn terminate_executors_gracefully(
+        &self,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let request_tracker = self.request_tracker();
+        let terminate = self.terminate_executors();
+        Box::pin(async move {
+            if let Some(request_tracker) = request_tracker {
+                if !request_tracker.drain(timeout).await {
+                    warn!(
+                        in_flight = request_tracker.in_flight(),
+                        ?timeout,
+                        "requests still in flight after the timeout, terminating anyway"
+                    );
+                }
+            }
+            terminate.await;
+        })
+    }
 }
@@ -0,0 +1,87 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::executor::{Executor, ExecutorSpawner, SpawnError};
+
+/// StaticSpawner
+///
+/// Hands out Executors that were created beforehand, for a pool of Workers that is managed out of band
+/// and reused over many rounds, instead of spawning and removing Workers for every round.
+///
+/// The Executors are handed out in the given order, at most once each per round.
+/// Terminating the executors leaves their Workers running and lets the next round take them again.
+pub struct StaticSpawner {
+    executors: Vec<Executor>,
+    executor_counter: AtomicUsize,
+}
+
+impl StaticSpawner {
+    pub fn new(executors: Vec<Executor>) -> Self {
+        StaticSpawner {
+            executors,
+            executor_counter: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl ExecutorSpawner for StaticSpawner {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
+        let id = self.executor_counter.fetch_add(1, Ordering::SeqCst);
+        let executor = self.executors.get(id).cloned();
+        let available = self.executors.len();
+        Box::pin(async move {
+            executor.ok_or(SpawnError::Exhausted {
+                requested: id + 1,
+                available,
+            })
+        })
+    }
+
+    fn max_executors(&self) -> Option<usize> {
+        Some(self.executors.len())
+    }
+
+    fn default_executors(&self) -> Option<usize> {
+        Some(self.executors.len())
+    }
+
+    fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        // The Workers outlive the round, only the executors are released
+        self.executor_counter.store(0, Ordering::SeqCst);
+        Box::pin(async move {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_spawner() {
+        let spawner = StaticSpawner::new(vec![
+            Executor::new("http://127.0.0.1:4001".to_string(), None),
+            Executor::new("http://127.0.0.1:4002".to_string(), None),
+        ]);
+        assert_eq!(spawner.max_executors(), Some(2));
+
+        let executor_1 = spawner.spawn_executor().await.unwrap();
+        let executor_2 = spawner.spawn_executor().await.unwrap();
+        assert_eq!(executor_1.get_url(), "http://127.0.0.1:4001");
+        assert_eq!(executor_2.get_url(), "http://127.0.0.1:4002");
+        assert!(matches!(
+            spawner.spawn_executor().await,
+            Err(SpawnError::Exhausted {
+                requested: 3,
+                available: 2
+            })
+        ));
+
+        // The executors are handed out again after the round
+        spawner.terminate_executors().await;
+        let executor_1 = spawner.spawn_executor().await.unwrap();
+        assert_eq!(executor_1.get_url(), "http://127.0.0.1:4001");
+    }
+}
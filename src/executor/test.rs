@@ -1,12 +1,32 @@
 #![allow(unused_imports)]
-use futures::future;
+use axum::{
+    extract::ConnectInfo,
+    http::{header::AUTHORIZATION, HeaderMap as AxumHeaderMap, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use futures::{future, StreamExt};
 use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use tokio::time::{Duration, Instant};
 
 use bollard::models::TaskSpecContainerSpec;
 
-use crate::executor::{spawner::ExecutorSpawner, utils::get_specs_from_compose, MockSpawner};
-use crate::json_mst::JsonEntry;
-use summa_backend::merkle_sum_tree::utils::parse_csv_to_entries;
+#[cfg(feature = "kubernetes")]
+use crate::executor::K8sSpawner;
+use crate::executor::{
+    spawner::ExecutorSpawner,
+    utils::{get_deployment_from_compose, get_specs_from_compose},
+    CloudSpawner, Executor, ExecutorError, HeaderMap, MockSpawner, RequestTracker, RetryPolicy,
+    ServiceDeployment, SpawnError, IN_PROCESS_URL,
+};
+use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
+use crate::test_utils::{load_json_entries, spawn_test_server};
+use summa_backend::merkle_sum_tree::{utils::parse_csv_to_entries, Tree};
 
 #[test]
 fn test_util_get_specs_from_compose() {
@@ -30,10 +50,59 @@ fn test_util_get_specs_from_compose() {
     );
 }
 
+#[test]
+fn test_util_get_deployment_from_compose() {
+    let deployment = get_deployment_from_compose("mini_tree", "docker-compose.yml").unwrap();
+    assert_eq!(
+        deployment,
+        ServiceDeployment {
+            replicas: 2,
+            constraints: vec!["node.role == worker".to_string()],
+        }
+    );
+
+    // Without a service, the spawner is set up for one executor per worker node URL
+    let spawner = CloudSpawner::new(
+        None,
+        vec!["127.0.0.1:4000".to_string(), "127.0.0.1:4001".to_string()],
+        4000,
+    )
+    .unwrap();
+    assert!(spawner.service_deployment().unwrap().is_none());
+    assert_eq!(spawner.default_executors(), Some(2));
+}
+
+#[test]
+fn test_util_compose_errors() {
+    let error = get_specs_from_compose("mini_tree", "missing-compose.yml").unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("Unable to read \"missing-compose.yml\""));
+
+    let path = std::env::temp_dir().join("summa_aggregation_compose_without_networks.yml");
+    std::fs::write(
+        &path,
+        "version: '3.8'\nservices:\n  mini_tree:\n    image: summadev/summa-aggregation-mini-tree:latest\n",
+    )
+    .unwrap();
+    let error = get_deployment_from_compose("mini_tree", path.to_str().unwrap()).unwrap_err();
+    assert_eq!(error.to_string(), "There is no network configuration");
+
+    // Without a readable compose file, the number of executors is left to the caller
+    let spawner = CloudSpawner::new(
+        Some(("mini_tree".to_string(), "missing-compose.yml".to_string())),
+        vec![],
+        4000,
+    )
+    .unwrap();
+    assert!(spawner.service_deployment().is_err());
+    assert_eq!(spawner.default_executors(), None);
+}
+
 #[tokio::test]
 async fn test_executor() -> Result<(), Box<dyn Error>> {
     let spawner = MockSpawner::new(None);
-    let executor = spawner.spawn_executor().await;
+    let executor = spawner.spawn_executor().await.unwrap();
 
     let (_, entries) = parse_csv_to_entries::<_, 2, 14>("csv/entry_16.csv").unwrap();
     let json_entries = entries
@@ -48,10 +117,128 @@ async fn test_executor() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_executor_tree_shape() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    // The worker defaults to 2 currencies, but builds the single currency tree requested by the executor
+    let json_entries = vec![
+        JsonEntry::new("alice".to_string(), vec!["100".to_string()]),
+        JsonEntry::new("bob".to_string(), vec!["200".to_string()]),
+    ];
+    let merkle_sum_tree = executor.generate_tree::<1, 14>(json_entries).await.unwrap();
+
+    assert_eq!(merkle_sum_tree.entries().len(), 2);
+    assert_eq!(merkle_sum_tree.cryptocurrencies().len(), 1);
+}
+
+#[tokio::test]
+async fn test_executor_generate_trees() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+
+    // The invalid batch in the middle fails on its own
+    let batches = vec![
+        json_entries[..8].to_vec(),
+        vec![JsonEntry::new(
+            "alice".to_string(),
+            vec!["100".to_string(), "1O0".to_string()],
+        )],
+        json_entries[8..].to_vec(),
+    ];
+    let trees = executor.generate_trees::<2, 14>(batches).await.unwrap();
+
+    spawner.terminate_executors().await;
+
+    assert_eq!(trees.len(), 3);
+    let first_tree = trees[0].as_ref().unwrap();
+    assert_eq!(first_tree.index_of_username("dxGaEAii").unwrap(), 0);
+    match &trees[1] {
+        Err(ExecutorError::WorkerStatus { status, body, .. }) => {
+            assert_eq!(*status, 400);
+            assert!(body.contains("invalid_entry"));
+        }
+        _ => panic!("Expected the invalid batch to be rejected"),
+    }
+    assert_eq!(trees[2].as_ref().unwrap().entries().len(), 8);
+}
+
+#[tokio::test]
+async fn test_executor_generate_trees_stream() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+    let batches = json_entries
+        .chunks(4)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<Vec<JsonEntry>>>();
+
+    let expected_trees = executor
+        .generate_trees::<2, 14>(batches.clone())
+        .await
+        .unwrap();
+    let streamed_trees = executor
+        .generate_trees_stream::<2, 14>(batches)
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+
+    spawner.terminate_executors().await;
+
+    assert_eq!(streamed_trees.len(), 4);
+    for (streamed_tree, expected_tree) in streamed_trees.iter().zip(expected_trees.iter()) {
+        let streamed_root = streamed_tree.as_ref().unwrap().root();
+        let expected_root = expected_tree.as_ref().unwrap().root();
+        assert_eq!(streamed_root.hash, expected_root.hash);
+        assert_eq!(streamed_root.balances, expected_root.balances);
+    }
+}
+
+#[tokio::test]
+async fn test_executor_omitted_nodes() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+
+    // The worker leaves out the nodes when asked to
+    let json_tree = reqwest::Client::new()
+        .post(format!("{}?omit_nodes=true", executor.get_url()))
+        .json(&json_entries)
+        .send()
+        .await
+        .unwrap()
+        .json::<JsonMerkleSumTree>()
+        .await
+        .unwrap();
+    assert!(json_tree.nodes.is_empty());
+
+    let full_tree = executor
+        .generate_tree::<2, 14>(json_entries.clone())
+        .await
+        .unwrap();
+    let rebuilt_tree = executor
+        .clone()
+        .with_omitted_nodes(true)
+        .generate_tree::<2, 14>(json_entries)
+        .await
+        .unwrap();
+
+    spawner.terminate_executors().await;
+
+    assert_eq!(rebuilt_tree.root().hash, full_tree.root().hash);
+    assert_eq!(rebuilt_tree.nodes().len(), full_tree.nodes().len());
+}
+
 #[tokio::test]
 async fn test_executor_block() -> Result<(), Box<dyn Error>> {
     let spawner = MockSpawner::new(None);
-    let executor = spawner.spawn_executor().await;
+    let executor = spawner.spawn_executor().await.unwrap();
 
     // Parse two csv files
     let (_, entries_1) = parse_csv_to_entries::<_, 2, 14>("csv/entry_16.csv").unwrap();
@@ -78,3 +265,375 @@ async fn test_executor_block() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_executor_request_timeout() {
+    // A worker that never answers
+    let app = Router::new().route(
+        "/",
+        post(|| async {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        }),
+    );
+    let worker_url = spawn_test_server(app);
+
+    let executor = Executor::with_retry_policy(
+        worker_url,
+        None,
+        RetryPolicy::new(2, Duration::from_millis(10)),
+    )
+    .with_request_timeout(Duration::from_millis(100));
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+    let result = executor.generate_tree::<2, 14>(json_entries).await;
+
+    assert!(matches!(result, Err(ExecutorError::Timeout(_))));
+}
+
+// Serves a worker that always answers with the given status, returns its url and a request counter
+#[cfg(test)]
+fn spawn_failing_worker(status: StatusCode) -> (String, Arc<AtomicUsize>) {
+    let request_count = Arc::new(AtomicUsize::new(0));
+    let counter = request_count.clone();
+    let app = Router::new().route(
+        "/",
+        post(move || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            (status, "balance is not a number")
+        }),
+    );
+    let worker_url = spawn_test_server(app);
+
+    (worker_url, request_count)
+}
+
+#[tokio::test]
+async fn test_executor_worker_error_status() {
+    let retry_policy = RetryPolicy::new(3, Duration::from_millis(10));
+    let json_entries = load_json_entries("csv/entry_16.csv");
+
+    // A client error is returned immediately along with the response body
+    let (worker_url, request_count) = spawn_failing_worker(StatusCode::BAD_REQUEST);
+    let executor = Executor::with_retry_policy(worker_url.clone(), None, retry_policy.clone());
+    match executor.generate_tree::<2, 14>(json_entries.clone()).await {
+        Err(ExecutorError::WorkerStatus { url, status, body }) => {
+            assert_eq!(status, 400);
+            assert_eq!(url, worker_url);
+            assert_eq!(body, "balance is not a number");
+        }
+        _ => panic!("Expected a worker status error"),
+    }
+    assert_eq!(request_count.load(Ordering::SeqCst), 1);
+
+    // A server error is retried until the retry policy gives up
+    let (worker_url, request_count) = spawn_failing_worker(StatusCode::INTERNAL_SERVER_ERROR);
+    let executor = Executor::with_retry_policy(worker_url, None, retry_policy);
+    assert!(matches!(
+        executor.generate_tree::<2, 14>(json_entries).await,
+        Err(ExecutorError::WorkerStatus { status: 500, .. })
+    ));
+    assert_eq!(request_count.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_executor_health_check() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+    assert!(executor.health_check().await.is_ok());
+
+    // Nothing is listening on this port
+    let executor = Executor::new("http://127.0.0.1:40".to_string(), None);
+    assert!(matches!(
+        executor.health_check().await,
+        Err(ExecutorError::Connection(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_executor_wait_until_ready() {
+    // The worker becomes ready on the third poll
+    let polls = Arc::new(AtomicUsize::new(0));
+    let polls_clone = polls.clone();
+    let app = Router::new().route(
+        "/readyz",
+        get(move || async move {
+            if polls_clone.fetch_add(1, Ordering::SeqCst) < 2 {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            }
+        }),
+    );
+    let executor = Executor::new(spawn_test_server(app), None);
+
+    executor
+        .wait_until_ready(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(polls.load(Ordering::SeqCst), 3);
+
+    // A worker without the readiness route is polled on its health route
+    let app = Router::new().route("/health", get(|| async { StatusCode::OK }));
+    let executor = Executor::new(spawn_test_server(app), None);
+    let start = Instant::now();
+    executor
+        .wait_until_ready(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // A worker without either route is given the fixed warmup, however long the ready timeout
+    let executor = Executor::new(spawn_test_server(Router::new()), None)
+        .with_fallback_warmup(Duration::from_millis(200));
+    let start = Instant::now();
+    executor
+        .wait_until_ready(Duration::from_secs(10))
+        .await
+        .unwrap();
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_secs(2));
+
+    // Nothing is listening on this port
+    let executor = Executor::new("http://127.0.0.1:1".to_string(), None);
+    assert!(executor
+        .wait_until_ready(Duration::from_secs(1))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_executor_connection_pool() {
+    // Records the client address of every request, which stays the same while a connection is reused
+    let client_addrs = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let client_addrs_clone = client_addrs.clone();
+    let app = Router::new().route(
+        "/health",
+        get(
+            move |ConnectInfo(addr): ConnectInfo<SocketAddr>| async move {
+                client_addrs_clone.lock().unwrap().push(addr);
+                StatusCode::OK
+            },
+        ),
+    );
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    let executor = Executor::new(url.clone(), None);
+    executor.health_check().await.unwrap();
+    executor.health_check().await.unwrap();
+    let pooled_addrs = std::mem::take(&mut *client_addrs.lock().unwrap());
+    assert_eq!(pooled_addrs[0], pooled_addrs[1]);
+
+    let executor = Executor::new(url, None).with_pool_max_idle(0).unwrap();
+    executor.health_check().await.unwrap();
+    executor.health_check().await.unwrap();
+    let unpooled_addrs = client_addrs.lock().unwrap().clone();
+    assert_ne!(unpooled_addrs[0], unpooled_addrs[1]);
+}
+
+#[tokio::test]
+async fn test_executor_in_process() {
+    let spawner = MockSpawner::new(None);
+    let http_executor = spawner.spawn_executor().await.unwrap();
+    let executor = Executor::in_process(None);
+    assert!(executor.health_check().await.is_ok());
+    assert!(executor.check_tree_shape::<2, 14>().await.is_ok());
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+    let tree = executor
+        .generate_tree::<2, 14>(json_entries.clone())
+        .await
+        .unwrap();
+    let http_tree = http_executor
+        .generate_tree::<2, 14>(json_entries.clone())
+        .await
+        .unwrap();
+    assert_eq!(tree.root().hash, http_tree.root().hash);
+
+    let trees = executor
+        .generate_trees::<2, 14>(vec![json_entries[..8].to_vec(), json_entries[8..].to_vec()])
+        .await
+        .unwrap();
+    assert_eq!(trees.len(), 2);
+    assert!(trees.iter().all(Result::is_ok));
+
+    // Invalid entries are rejected like by a Worker
+    let invalid_entries = vec![JsonEntry::new("alice".to_string(), vec!["100".to_string()])];
+    match executor.generate_tree::<2, 14>(invalid_entries).await {
+        Err(ExecutorError::WorkerStatus { status, url, .. }) => {
+            assert_eq!(status, 400);
+            assert_eq!(url, IN_PROCESS_URL);
+        }
+        _ => panic!("expected the entries to be rejected"),
+    }
+}
+
+#[tokio::test]
+async fn test_executor_check_tree_shape() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    let worker_info = executor.worker_info().await.unwrap();
+    assert_eq!((worker_info.n_currencies, worker_info.n_bytes), (2, 14));
+    assert!(executor.check_tree_shape::<2, 14>().await.is_ok());
+    // Other shapes can be requested from the Worker
+    assert!(executor.check_tree_shape::<4, 16>().await.is_ok());
+
+    match executor.check_tree_shape::<5, 14>().await {
+        Err(ExecutorError::UnsupportedShape {
+            n_currencies,
+            n_bytes,
+            ..
+        }) => assert_eq!((n_currencies, n_bytes), (5, 14)),
+        _ => panic!("Expected an unsupported shape error"),
+    }
+
+    // A Worker without the info route cannot be checked
+    let (worker_url, _) = spawn_failing_worker(StatusCode::BAD_REQUEST);
+    let executor = Executor::new(worker_url, None);
+    assert!(executor.check_tree_shape::<5, 14>().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_executor_request_compression() {
+    let spawner = MockSpawner::new(None);
+    let executor = spawner.spawn_executor().await.unwrap();
+    let compressing_executor = executor.clone().with_request_compression(true);
+
+    let json_entries = load_json_entries("csv/entry_16.csv");
+
+    let tree = executor
+        .generate_tree::<2, 14>(json_entries.clone())
+        .await
+        .unwrap();
+    let tree_from_compressed = compressing_executor
+        .generate_tree::<2, 14>(json_entries)
+        .await
+        .unwrap();
+
+    assert_eq!(tree.root().hash, tree_from_compressed.root().hash);
+    assert_eq!(tree.root().balances, tree_from_compressed.root().balances);
+}
+
+#[tokio::test]
+async fn test_cloud_spawner_worker_url_scheme() {
+    let spawner = CloudSpawner::new(
+        None,
+        vec![
+            "10.0.0.1".to_string(),
+            "https://10.0.0.2".to_string(),
+            "https://10.0.0.3:4443".to_string(),
+        ],
+        4000,
+    )
+    .unwrap();
+
+    assert_eq!(
+        spawner.spawn_executor().await.unwrap().get_url(),
+        "http://10.0.0.1:4000"
+    );
+    assert_eq!(
+        spawner.spawn_executor().await.unwrap().get_url(),
+        "https://10.0.0.2:4000"
+    );
+    assert_eq!(
+        spawner.spawn_executor().await.unwrap().get_url(),
+        "https://10.0.0.3:4443"
+    );
+}
+
+#[tokio::test]
+async fn test_cloud_spawner_worker_headers() {
+    // A worker that only accepts requests carrying the expected bearer token
+    let app = Router::new().route(
+        "/health",
+        get(|headers: AxumHeaderMap| async move {
+            match headers.get(AUTHORIZATION) {
+                Some(value) if value == "Bearer secret" => StatusCode::OK,
+                _ => StatusCode::UNAUTHORIZED,
+            }
+        }),
+    );
+    let url = spawn_test_server(app);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+
+    // Both worker nodes point to the same server, but only the first one carries the token
+    let authorized_url = url.trim_start_matches("http://").to_string();
+    let unauthorized_url = authorized_url.replace("127.0.0.1", "localhost");
+    let spawner = CloudSpawner::new(None, vec![authorized_url.clone(), unauthorized_url], 4000)
+        .unwrap()
+        .with_worker_headers(authorized_url, headers);
+
+    let authorized_executor = spawner.spawn_executor().await.unwrap();
+    let unauthorized_executor = spawner.spawn_executor().await.unwrap();
+
+    assert!(authorized_executor.health_check().await.is_ok());
+    match unauthorized_executor.health_check().await {
+        Err(ExecutorError::WorkerStatus { status, .. }) => assert_eq!(status, 401),
+        other => panic!("Expected unauthorized worker status, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_cloud_spawner_exhausted() {
+    let spawner = CloudSpawner::new(None, vec!["10.0.0.1".to_string()], 4000).unwrap();
+
+    assert!(spawner.spawn_executor().await.is_ok());
+    match spawner.spawn_executor().await {
+        Err(SpawnError::Exhausted {
+            requested,
+            available,
+        }) => {
+            assert_eq!(requested, 2);
+            assert_eq!(available, 1);
+        }
+        Err(e) => panic!("Expected an exhausted spawner, got {}", e),
+        Ok(_) => panic!("Expected an error"),
+    }
+}
+
+#[test]
+fn test_cloud_spawner_without_workers() {
+    let error = CloudSpawner::new(None, vec![], 4000).err().unwrap();
+    assert_eq!(error.to_string(), "Worker node url is empty");
+}
+
+#[tokio::test]
+async fn test_cloud_spawner_graceful_termination() {
+    let spawner = CloudSpawner::new(None, vec!["10.0.0.1".to_string()], 4000).unwrap();
+    let executor = spawner.spawn_executor().await.unwrap();
+
+    spawner
+        .terminate_executors_gracefully(Duration::from_secs(1))
+        .await;
+
+    // The drained executor refuses new work instead of reaching out to its worker
+    let json_entries = load_json_entries("csv/entry_16.csv");
+    let result = executor.generate_tree::<2, 14>(json_entries).await;
+    assert!(matches!(result, Err(ExecutorError::Draining)));
+}
+
+#[cfg(feature = "kubernetes")]
+#[test]
+fn test_k8s_spawner() {
+    let image_name = "summadev/summa-aggregation-mini-tree:latest".to_string();
+    let error = K8sSpawner::new("summa".to_string(), image_name.clone(), 0)
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "Replicas must be greater than 0, got 0");
+
+    // One executor per pod, all of them reaching the pods through the Service
+    let spawner = K8sSpawner::new("summa".to_string(), image_name, 3).unwrap();
+    assert_eq!(spawner.default_executors(), Some(3));
+    assert_eq!(spawner.max_executors(), None);
+    assert_eq!(
+        spawner.worker_url(),
+        "http://mini-tree.summa.svc.cluster.local:4000"
+    );
+    assert!(spawner.request_tracker().is_some());
+}
@@ -48,12 +48,47 @@ pub struct Network {
     driver: Option<String>,
 }
 
+/// ServiceDeployment
+///
+/// How a service of `docker-compose.yml` is deployed on the swarm, see `CloudSpawner::service_deployment`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDeployment {
+    /// The number of workers, from `deploy.replicas`.
+    pub replicas: usize,
+    /// The nodes the workers may be placed on, from `deploy.placement.constraints`, e.g. `node.role == worker`.
+    pub constraints: Vec<String>,
+}
+
+// This helper function return the `ServiceDeployment` of a service from `docker-compose.yml`
+pub fn get_deployment_from_compose(
+    service_name: &str,
+    file_path: &str,
+) -> Result<ServiceDeployment, Box<dyn Error + Send + Sync>> {
+    let (_, service_spec) = get_specs_from_compose(service_name, file_path)?;
+    let replicas = service_spec
+        .mode
+        .and_then(|mode| mode.replicated)
+        .and_then(|replicated| replicated.replicas)
+        .ok_or("There is no 'replicas' under 'deploy' field")?;
+    let constraints = service_spec
+        .task_template
+        .and_then(|task_template| task_template.placement)
+        .and_then(|placement| placement.constraints)
+        .unwrap_or_default();
+    Ok(ServiceDeployment {
+        replicas: usize::try_from(replicas)
+            .map_err(|_| format!("Invalid number of replicas: {}", replicas))?,
+        constraints,
+    })
+}
+
 // This helper function return `CreateNetworkOptions` and `ServiceSpec` from `docker-compose.yml`
 pub fn get_specs_from_compose(
     service_name: &str,
     file_path: &str,
-) -> Result<(CreateNetworkOptions<String>, ServiceSpec), Box<dyn Error>> {
-    let file_content = std::fs::read_to_string(file_path).expect("Unable to read file");
+) -> Result<(CreateNetworkOptions<String>, ServiceSpec), Box<dyn Error + Send + Sync>> {
+    let file_content = std::fs::read_to_string(file_path)
+        .map_err(|e| format!("Unable to read {:?}: {}", file_path, e))?;
     let compose = serde_yaml::from_str::<DockerCompose>(&file_content)?;
 
     // Declare docker client & default labels
@@ -67,8 +102,7 @@ pub fn get_specs_from_compose(
     let mut network_options = CreateNetworkOptions::<String>::default();
     compose
         .networks
-        .ok_or("There is no network configuration")
-        .unwrap()
+        .ok_or("There is no network configuration")?
         .iter()
         .for_each(|(network_name, network)| {
             if network_name == service_name {
@@ -4,7 +4,11 @@ use std::error::Error;
 
 use halo2_proofs::halo2curves::{bn256::Fr as Fp, group::ff::PrimeField};
 
-use summa_backend::merkle_sum_tree::{Cryptocurrency, Entry, MerkleSumTree, Node, Tree};
+use crate::aggregation_merkle_sum_tree::AggregationMerkleSumTree;
+use summa_backend::merkle_sum_tree::{
+    utils::build_merkle_tree_from_leaves, Cryptocurrency, Entry, MerkleProof, MerkleSumTree, Node,
+    Tree,
+};
 
 /// JsonNode
 /// Represents a entry in the Merkle Sum Tree in JSON format.
@@ -27,6 +31,11 @@ pub struct JsonNode {
 /// JsonMerkleSumTree
 /// Represents the entire Merkle Sum Tree in JSON format.
 /// It is used for transmitting tree data between the executor and mini-tree-server.
+/// The `nodes` may be left empty to shrink the payload, `to_mst` then rebuilds them from the entries.
+/// The `cryptocurrencies` are empty if the tree was sent by a mini-tree-server that does not know them,
+/// `to_mst` then names them "Dummy".
+/// The `source` names the entry source the tree was built from, if it was written to disk by the Orchestrator.
+/// The `build_ms` is the time in milliseconds the mini-tree-server took to build the tree, if it reports it.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonMerkleSumTree {
     pub root: JsonNode,
@@ -34,6 +43,46 @@ pub struct JsonMerkleSumTree {
     pub depth: usize,
     pub entries: Vec<JsonEntry>,
     pub is_sorted: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cryptocurrencies: Vec<JsonCryptocurrency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_ms: Option<u64>,
+}
+
+/// JsonCryptocurrency
+/// Represents a cryptocurrency of a tree in JSON format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonCryptocurrency {
+    pub name: String,
+    pub chain: String,
+}
+
+/// JsonAggregationMerkleSumTree
+/// Represents an entire Aggregation Merkle Sum Tree in JSON format, including its mini trees.
+/// It is used for persisting the tree, so that proofs can be generated without rebuilding it from the entries,
+/// and for sending the aggregated tree to other services, which check it with `to_verified_aggregation_mst`.
+/// The mini trees are embedded with their entries, so the loaded tree can generate the proofs of all users.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonAggregationMerkleSumTree {
+    pub root: JsonNode,
+    pub nodes: Vec<Vec<JsonNode>>,
+    pub depth: usize,
+    pub cryptocurrencies: Vec<JsonCryptocurrency>,
+    pub mini_trees: Vec<JsonMerkleSumTree>,
+}
+
+/// JsonMerkleProof
+/// Represents a proof of inclusion in JSON format, with the field elements as hex strings like in `JsonNode`.
+/// It is written by `AggregationMerkleSumTree::export_proofs`, one proof per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonMerkleProof {
+    pub entry: JsonEntry,
+    pub root: JsonNode,
+    pub sibling_leaf_node_hash_preimage: Vec<String>,
+    pub sibling_middle_node_hash_preimages: Vec<Vec<String>>,
+    pub path_indices: Vec<String>,
 }
 
 pub fn convert_node_to_json<const N_CURRENCIES: usize>(node: &Node<N_CURRENCIES>) -> JsonNode {
@@ -43,9 +92,55 @@ pub fn convert_node_to_json<const N_CURRENCIES: usize>(node: &Node<N_CURRENCIES>
     }
 }
 
-fn parse_fp_from_hex(hex_str: &str) -> Fp {
-    let bigint = BigUint::parse_bytes(hex_str[2..].as_bytes(), 16).unwrap();
-    Fp::from_str_vartime(&bigint.to_str_radix(10)).unwrap()
+// The cryptocurrencies of a tree sent without them, only their number is known
+fn dummy_cryptocurrencies(n_currencies: usize) -> Vec<Cryptocurrency> {
+    vec![
+        Cryptocurrency {
+            name: "Dummy".to_string(),
+            chain: "ETH".to_string(),
+        };
+        n_currencies
+    ]
+}
+
+// Parses a field element from hex as formatted by `{:?}`, the `0x` prefix is optional
+fn parse_fp_from_hex(hex_str: &str) -> Result<Fp, Box<dyn Error>> {
+    let digits = hex_str
+        .strip_prefix("0x")
+        .or_else(|| hex_str.strip_prefix("0X"))
+        .unwrap_or(hex_str);
+    if digits.is_empty() {
+        return Err(format!("Invalid hex {:?}: no digits", hex_str).into());
+    }
+
+    let bytes = BigUint::parse_bytes(digits.as_bytes(), 16)
+        .ok_or_else(|| format!("Invalid hex {:?}: not a hex number", hex_str))?
+        .to_bytes_le();
+
+    // Unlike `from_str_vartime`, which reduces modulo the field modulus, `from_repr` rejects values above it
+    let mut repr = <Fp as PrimeField>::Repr::default();
+    if bytes.len() > repr.as_ref().len() {
+        return Err(format!("Invalid hex {:?}: not a field element", hex_str).into());
+    }
+    repr.as_mut()[..bytes.len()].copy_from_slice(&bytes);
+    Option::from(Fp::from_repr(repr))
+        .ok_or_else(|| format!("Invalid hex {:?}: not a field element", hex_str).into())
+}
+
+impl JsonCryptocurrency {
+    pub fn from_cryptocurrency(cryptocurrency: &Cryptocurrency) -> Self {
+        JsonCryptocurrency {
+            name: cryptocurrency.name.clone(),
+            chain: cryptocurrency.chain.clone(),
+        }
+    }
+
+    pub fn to_cryptocurrency(&self) -> Cryptocurrency {
+        Cryptocurrency {
+            name: self.name.clone(),
+            chain: self.chain.clone(),
+        }
+    }
 }
 
 impl JsonEntry {
@@ -72,29 +167,47 @@ impl JsonEntry {
     ///
     /// This method is utilized by the mini-tree-server when processing data received from the executor in JSON format.
     /// It converts `JsonEntry` objects back to the `Entry` struct, facilitating the construction of the Merkle Sum Tree.
-    pub fn to_entry<const N_CURRENCIES: usize>(&self) -> Entry<N_CURRENCIES> {
+    ///
+    /// Fails if the number of balances differs from `N_CURRENCIES` or a balance is not a decimal number.
+    pub fn to_entry<const N_CURRENCIES: usize>(
+        &self,
+    ) -> Result<Entry<N_CURRENCIES>, Box<dyn Error>> {
+        if self.balances.len() != N_CURRENCIES {
+            return Err(format!(
+                "Expected {} balances, got {}",
+                N_CURRENCIES,
+                self.balances.len()
+            )
+            .into());
+        }
+
         let mut balances: [BigUint; N_CURRENCIES] = std::array::from_fn(|_| BigUint::from(0u32));
-        self.balances.iter().enumerate().for_each(|(i, balance)| {
-            balances[i] = balance.parse::<BigUint>().unwrap();
-        });
+        for (i, balance) in self.balances.iter().enumerate() {
+            balances[i] = balance
+                .parse::<BigUint>()
+                .map_err(|err| format!("Invalid balance {:?}: {}", balance, err))?;
+        }
 
-        Entry::<N_CURRENCIES>::new(self.username.clone(), balances).unwrap()
+        Entry::<N_CURRENCIES>::new(self.username.clone(), balances)
     }
 }
 
 /// Converts a `JsonNode` back to a `Node` for reconstructing the Merkle Sum Tree from JSON data.
 impl JsonNode {
-    pub fn to_node<const N_CURRENCIES: usize>(&self) -> Node<N_CURRENCIES> {
-        let hash = parse_fp_from_hex(&self.hash);
+    /// Fails if the number of balances differs from `N_CURRENCIES` or the hash or a balance is not a valid field element.
+    pub fn to_node<const N_CURRENCIES: usize>(&self) -> Result<Node<N_CURRENCIES>, Box<dyn Error>> {
+        let hash = parse_fp_from_hex(&self.hash)?;
         let balances = self
             .balances
             .iter()
             .map(|balance| parse_fp_from_hex(balance))
-            .collect::<Vec<_>>()
+            .collect::<Result<Vec<_>, _>>()?;
+        let balances_len = balances.len();
+        let balances = balances
             .try_into()
-            .expect("Incorrect number of balances");
+            .map_err(|_| format!("Expected {} balances, got {}", N_CURRENCIES, balances_len))?;
 
-        Node { hash, balances }
+        Ok(Node { hash, balances })
     }
 }
 
@@ -104,7 +217,7 @@ impl JsonMerkleSumTree {
     /// This function is essential for the mini-tree-server to send the Merkle Sum Tree results back to the executor in JSON format,
     /// facilitating the translation of the tree structure into a universally readable JSON form.
     pub fn from_tree<const N_CURRENCIES: usize, const N_BYTES: usize>(
-        tree: MerkleSumTree<N_CURRENCIES, N_BYTES>,
+        tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>,
     ) -> Self {
         let root = convert_node_to_json(tree.root());
         let nodes = tree
@@ -129,14 +242,44 @@ impl JsonMerkleSumTree {
             depth: *tree.depth(),
             entries,
             is_sorted: false,
+            cryptocurrencies: tree
+                .cryptocurrencies()
+                .iter()
+                .map(JsonCryptocurrency::from_cryptocurrency)
+                .collect(),
+            source: None,
+            build_ms: None,
         }
     }
 
+    /// Records whether the entries of the tree were sorted by username when it was built,
+    /// which `from_tree` cannot read from the tree itself.
+    pub fn with_sorted(mut self, is_sorted: bool) -> Self {
+        self.is_sorted = is_sorted;
+        self
+    }
+
+    /// Names the entry source the tree was built from, e.g. the path of its CSV file.
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Records the time in milliseconds it took to build the tree, reported by the mini-tree-server.
+    pub fn with_build_ms(mut self, build_ms: u64) -> Self {
+        self.build_ms = Some(build_ms);
+        self
+    }
+
     /// Converts a JsonMerkleSumTree back to a MerkleSumTree.
     ///
     /// This function is crucial when handling data received in JSON format from the mini-tree-server.
     /// It rebuilds the MerkleSumTree on the main machine using the `from_params` method.
     /// This method is preferred over `from_entries` as the nodes are pre-computed by the mini-tree-server, thus the tree doesn't need to be recomputed from scratch.
+    ///
+    /// The tree is verified before it is trusted: the nodes are recomputed from the leaves of the entries,
+    /// and both the recomputed root and nodes have to match the ones reported by the mini-tree-server.
+    /// If the `nodes` were omitted, the recomputed ones are used instead.
     pub fn to_mst<const N_CURRENCIES: usize, const N_BYTES: usize>(
         &self,
     ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
@@ -144,25 +287,71 @@ impl JsonMerkleSumTree {
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let root: Node<N_CURRENCIES> = self.root.to_node::<N_CURRENCIES>();
-        let nodes = self
-            .nodes
+        self.to_mst_with_verification(self.cryptocurrencies_or_dummy::<N_CURRENCIES>()?, true)
+    }
+
+    /// Like `to_mst`, but trusts the `root` and `nodes` reported by the mini-tree-server, skipping the hashing.
+    ///
+    /// Omitted `nodes` are still recomputed and checked against the `root`.
+    pub fn to_mst_unverified<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        self.to_mst_with_verification(self.cryptocurrencies_or_dummy::<N_CURRENCIES>()?, false)
+    }
+
+    // The cryptocurrencies sent with the tree, or dummy ones if it was sent without them
+    fn cryptocurrencies_or_dummy<const N_CURRENCIES: usize>(
+        &self,
+    ) -> Result<Vec<Cryptocurrency>, Box<dyn Error>> {
+        if self.cryptocurrencies.is_empty() {
+            return Ok(dummy_cryptocurrencies(N_CURRENCIES));
+        }
+        if self.cryptocurrencies.len() != N_CURRENCIES {
+            return Err(format!(
+                "Expected {} cryptocurrencies, got {}",
+                N_CURRENCIES,
+                self.cryptocurrencies.len()
+            )
+            .into());
+        }
+        Ok(self
+            .cryptocurrencies
             .iter()
-            .map(|node| node.iter().map(|n| n.to_node()).collect())
-            .collect();
+            .map(JsonCryptocurrency::to_cryptocurrency)
+            .collect())
+    }
+
+    fn to_mst_with_verification<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+        cryptocurrencies: Vec<Cryptocurrency>,
+        verify: bool,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let root: Node<N_CURRENCIES> = self.root.to_node::<N_CURRENCIES>()?;
         let entries = self
             .entries
             .iter()
             .map(|entry| entry.to_entry::<N_CURRENCIES>())
-            .collect();
-        let cryptocurrencies = vec![
-            Cryptocurrency {
-                name: "Dummy".to_string(),
-                chain: "ETH".to_string(),
-            };
-            N_CURRENCIES
-        ];
-
+            .collect::<Result<Vec<_>, _>>()?;
+        let nodes = if verify || self.nodes.is_empty() {
+            let nodes = self.rebuild_nodes(&root, &entries)?;
+            if !self.nodes.is_empty() {
+                verify_nodes(&self.nodes, &nodes, "the entries")?;
+            }
+            nodes
+        } else {
+            self.nodes
+                .iter()
+                .map(|node| node.iter().map(|n| n.to_node()).collect())
+                .collect::<Result<_, _>>()?
+        };
         MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_params(
             root,
             nodes,
@@ -172,4 +361,484 @@ impl JsonMerkleSumTree {
             self.is_sorted,
         )
     }
+
+    /// Returns the tree without its `nodes`, leaving only the entries and the root.
+    pub fn without_nodes(mut self) -> Self {
+        self.nodes.clear();
+        self
+    }
+
+    // Rebuilds the nodes from the entries, which include the padding up to `2^depth` leaves
+    fn rebuild_nodes<const N_CURRENCIES: usize>(
+        &self,
+        root: &Node<N_CURRENCIES>,
+        entries: &[Entry<N_CURRENCIES>],
+    ) -> Result<Vec<Vec<Node<N_CURRENCIES>>>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        if entries.len() != 1 << self.depth {
+            return Err(format!(
+                "Expected {} entries for a tree of depth {}, got {}",
+                1usize << self.depth,
+                self.depth,
+                entries.len()
+            )
+            .into());
+        }
+
+        let leaves = entries
+            .iter()
+            .map(|entry| entry.compute_leaf())
+            .collect::<Vec<_>>();
+        let mut nodes = vec![];
+        let rebuilt_root = build_merkle_tree_from_leaves(&leaves, self.depth, &mut nodes)?;
+        if rebuilt_root.hash != root.hash || rebuilt_root.balances != root.balances {
+            return Err("Rebuilt root does not match the root of the tree".into());
+        }
+        Ok(nodes)
+    }
+}
+
+impl JsonMerkleProof {
+    /// Converts a MerkleProof to its JSON representation.
+    pub fn from_proof<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        proof: &MerkleProof<N_CURRENCIES, N_BYTES>,
+    ) -> Self
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let to_hex = |elements: &[Fp]| {
+            elements
+                .iter()
+                .map(|element| format!("{:?}", element))
+                .collect::<Vec<_>>()
+        };
+        JsonMerkleProof {
+            entry: JsonEntry::from_entry(&proof.entry),
+            root: convert_node_to_json(&proof.root),
+            sibling_leaf_node_hash_preimage: to_hex(&proof.sibling_leaf_node_hash_preimage),
+            sibling_middle_node_hash_preimages: proof
+                .sibling_middle_node_hash_preimages
+                .iter()
+                .map(|preimage| to_hex(preimage))
+                .collect(),
+            path_indices: to_hex(&proof.path_indices),
+        }
+    }
+
+    /// Converts a JsonMerkleProof back to a MerkleProof, e.g. to verify an exported proof.
+    ///
+    /// Fails if a field element is invalid or a preimage does not hold the number of elements given by `N_CURRENCIES`.
+    pub fn to_proof<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+    ) -> Result<MerkleProof<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        Ok(MerkleProof {
+            entry: self.entry.to_entry()?,
+            root: self.root.to_node()?,
+            sibling_leaf_node_hash_preimage: parse_preimage(&self.sibling_leaf_node_hash_preimage)?,
+            sibling_middle_node_hash_preimages: self
+                .sibling_middle_node_hash_preimages
+                .iter()
+                .map(|preimage| parse_preimage(preimage))
+                .collect::<Result<_, _>>()?,
+            path_indices: self
+                .path_indices
+                .iter()
+                .map(|index| parse_fp_from_hex(index))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// Checks the reported nodes against the ones rebuilt from `rebuilt_from`, e.g. "the entries", naming it in the errors
+fn verify_nodes<const N_CURRENCIES: usize>(
+    nodes: &[Vec<JsonNode>],
+    rebuilt_nodes: &[Vec<Node<N_CURRENCIES>>],
+    rebuilt_from: &str,
+) -> Result<(), Box<dyn Error>> {
+    if nodes.len() != rebuilt_nodes.len() {
+        return Err(format!(
+            "Expected {} levels of nodes, got {}",
+            rebuilt_nodes.len(),
+            nodes.len()
+        )
+        .into());
+    }
+
+    for (level, (nodes, rebuilt_nodes)) in nodes.iter().zip(rebuilt_nodes).enumerate() {
+        if nodes.len() != rebuilt_nodes.len() {
+            return Err(format!(
+                "Expected {} nodes at level {}, got {}",
+                rebuilt_nodes.len(),
+                level,
+                nodes.len()
+            )
+            .into());
+        }
+        for (index, (node, rebuilt_node)) in nodes.iter().zip(rebuilt_nodes).enumerate() {
+            let node = node.to_node::<N_CURRENCIES>()?;
+            if node.hash != rebuilt_node.hash || node.balances != rebuilt_node.balances {
+                return Err(format!(
+                    "Node {} at level {} does not match {}",
+                    index, level, rebuilt_from
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Parses a hash preimage of exactly `N` field elements
+fn parse_preimage<const N: usize>(preimage: &[String]) -> Result<[Fp; N], Box<dyn Error>> {
+    let elements = preimage
+        .iter()
+        .map(|element| parse_fp_from_hex(element))
+        .collect::<Result<Vec<_>, _>>()?;
+    let elements_len = elements.len();
+    elements.try_into().map_err(|_| {
+        format!(
+            "Expected a preimage of {} elements, got {}",
+            N, elements_len
+        )
+        .into()
+    })
+}
+
+impl JsonAggregationMerkleSumTree {
+    /// Converts an AggregationMerkleSumTree to its JSON representation.
+    pub fn from_tree<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        tree: &AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>,
+    ) -> Self
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        JsonAggregationMerkleSumTree {
+            root: convert_node_to_json(tree.root()),
+            nodes: tree
+                .nodes()
+                .iter()
+                .map(|node| node.iter().map(convert_node_to_json).collect())
+                .collect(),
+            depth: *tree.depth(),
+            cryptocurrencies: tree
+                .cryptocurrencies()
+                .iter()
+                .map(JsonCryptocurrency::from_cryptocurrency)
+                .collect(),
+            mini_trees: tree
+                .mini_trees()
+                .iter()
+                .map(JsonMerkleSumTree::from_tree)
+                .collect(),
+        }
+    }
+
+    /// Converts a JsonAggregationMerkleSumTree back to an AggregationMerkleSumTree.
+    ///
+    /// The nodes are taken as they are, without hashing, as the JSON is expected to come from a trusted source,
+    /// e.g. a file written by `AggregationMerkleSumTree::save_to_file`.
+    pub fn to_aggregation_mst<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+    ) -> Result<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let cryptocurrencies = self
+            .cryptocurrencies
+            .iter()
+            .map(JsonCryptocurrency::to_cryptocurrency)
+            .collect::<Vec<_>>();
+        let mini_trees = self
+            .mini_trees
+            .iter()
+            .map(|mini_tree| mini_tree.to_mst_with_verification(cryptocurrencies.clone(), false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| node.iter().map(|n| n.to_node()).collect())
+            .collect::<Result<_, _>>()?;
+
+        AggregationMerkleSumTree::from_params(
+            self.root.to_node()?,
+            nodes,
+            self.depth,
+            cryptocurrencies,
+            mini_trees,
+        )
+    }
+
+    /// Like `to_aggregation_mst`, but checks the tree before trusting it, e.g. when it was received from another service.
+    ///
+    /// Each mini tree is verified like in `JsonMerkleSumTree::to_mst`, and the aggregation tree is rebuilt from them:
+    /// its depth, nodes and root have to match the ones in the JSON.
+    pub fn to_verified_aggregation_mst<const N_CURRENCIES: usize, const N_BYTES: usize>(
+        &self,
+    ) -> Result<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let cryptocurrencies = self
+            .cryptocurrencies
+            .iter()
+            .map(JsonCryptocurrency::to_cryptocurrency)
+            .collect::<Vec<_>>();
+        let mini_trees = self
+            .mini_trees
+            .iter()
+            .map(|mini_tree| mini_tree.to_mst_with_verification(cryptocurrencies.clone(), true))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tree = AggregationMerkleSumTree::new(mini_trees, cryptocurrencies)?;
+
+        if self.depth != *tree.depth() {
+            return Err(format!("Expected depth {}, got {}", tree.depth(), self.depth).into());
+        }
+        let root = self.root.to_node::<N_CURRENCIES>()?;
+        if root.hash != tree.root().hash || root.balances != tree.root().balances {
+            return Err("The root does not match the mini trees".into());
+        }
+        verify_nodes(&self.nodes, tree.nodes(), "the mini trees")?;
+
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_to_entry() {
+        let json_entry = JsonEntry::new(
+            "alice".to_string(),
+            vec!["100".to_string(), "200".to_string()],
+        );
+        let entry = json_entry.to_entry::<2>().unwrap();
+
+        assert_eq!(entry.username(), "alice");
+        assert_eq!(entry.balances()[1], BigUint::from(200u32));
+    }
+
+    #[test]
+    fn test_to_entry_invalid_balances() {
+        let json_entry = JsonEntry::new(
+            "alice".to_string(),
+            vec!["100".to_string(), "2OO".to_string()],
+        );
+        let err = json_entry.to_entry::<2>().unwrap_err();
+        assert!(err.to_string().contains("Invalid balance \"2OO\""));
+
+        // Too many and too few balances are both rejected
+        let err = json_entry.to_entry::<1>().unwrap_err();
+        assert_eq!(err.to_string(), "Expected 1 balances, got 2");
+        let err = json_entry.to_entry::<3>().unwrap_err();
+        assert_eq!(err.to_string(), "Expected 3 balances, got 2");
+    }
+
+    #[test]
+    fn test_to_mst_without_nodes() {
+        let tree = MerkleSumTree::<2, 14>::from_csv("csv/entry_16.csv").unwrap();
+        let json_tree = JsonMerkleSumTree::from_tree(&tree).without_nodes();
+        assert!(json_tree.nodes.is_empty());
+
+        let rebuilt_tree = json_tree.to_mst::<2, 14>().unwrap();
+        assert_eq!(rebuilt_tree.root().hash, tree.root().hash);
+        assert_eq!(rebuilt_tree.nodes().len(), tree.nodes().len());
+        for (rebuilt_level, level) in rebuilt_tree.nodes().iter().zip(tree.nodes()) {
+            for (rebuilt_node, node) in rebuilt_level.iter().zip(level) {
+                assert_eq!(rebuilt_node.hash, node.hash);
+                assert_eq!(rebuilt_node.balances, node.balances);
+            }
+        }
+
+        // A root that does not match the entries is rejected
+        let mut tampered_tree = json_tree;
+        tampered_tree.root.balances[0] =
+            convert_node_to_json(&tree.nodes()[0][0]).balances[0].clone();
+        assert!(tampered_tree.to_mst::<2, 14>().is_err());
+    }
+
+    #[test]
+    fn test_to_mst_verification() {
+        let tree = MerkleSumTree::<2, 14>::from_csv("csv/entry_16.csv").unwrap();
+        let json_tree = JsonMerkleSumTree::from_tree(&tree);
+        assert_eq!(
+            json_tree.to_mst::<2, 14>().unwrap().root().hash,
+            tree.root().hash
+        );
+
+        // A reported node that does not match the entries is only caught by the verification
+        let mut tampered_tree = json_tree.clone();
+        tampered_tree.nodes[1][0] = tampered_tree.nodes[1][1].clone();
+        let err = tampered_tree.to_mst::<2, 14>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node 0 at level 1 does not match the entries"
+        );
+        assert!(tampered_tree.to_mst_unverified::<2, 14>().is_ok());
+
+        // Same for a reported root that does not match
+        let mut tampered_tree = json_tree;
+        tampered_tree.root = tampered_tree.nodes[0][0].clone();
+        assert!(tampered_tree.to_mst::<2, 14>().is_err());
+        assert!(tampered_tree.to_mst_unverified::<2, 14>().is_ok());
+    }
+
+    #[test]
+    fn test_to_mst_cryptocurrencies() {
+        let tree = MerkleSumTree::<2, 14>::from_csv("csv/entry_16.csv").unwrap();
+        let json_tree = JsonMerkleSumTree::from_tree(&tree);
+        let rebuilt_tree = json_tree.to_mst::<2, 14>().unwrap();
+        assert_eq!(rebuilt_tree.cryptocurrencies()[1].name, "USDT");
+        assert_eq!(rebuilt_tree.cryptocurrencies()[1].chain, "ETH");
+
+        // A tree sent without its cryptocurrencies gets dummy ones
+        let mut anonymous_tree = json_tree.clone();
+        anonymous_tree.cryptocurrencies.clear();
+        let rebuilt_tree = anonymous_tree.to_mst::<2, 14>().unwrap();
+        assert_eq!(rebuilt_tree.cryptocurrencies()[1].name, "Dummy");
+
+        let mut truncated_tree = json_tree;
+        truncated_tree.cryptocurrencies.pop();
+        let err = truncated_tree.to_mst_unverified::<2, 14>().unwrap_err();
+        assert_eq!(err.to_string(), "Expected 2 cryptocurrencies, got 1");
+    }
+
+    #[test]
+    fn test_parse_fp_from_hex() {
+        assert_eq!(parse_fp_from_hex("0x0a").unwrap(), Fp::from(10));
+        assert_eq!(parse_fp_from_hex("0A").unwrap(), Fp::from(10));
+        assert_eq!(parse_fp_from_hex("0xabc").unwrap(), Fp::from(0xabc));
+
+        assert!(parse_fp_from_hex("").is_err());
+        assert!(parse_fp_from_hex("0x").is_err());
+        assert!(parse_fp_from_hex("0xzz").is_err());
+        // Exceeds the field modulus
+        assert!(parse_fp_from_hex(&format!("0x{}", "f".repeat(64))).is_err());
+        assert!(parse_fp_from_hex(&format!("0x1{}", "0".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn test_to_node_invalid_hex() {
+        let json_node = JsonNode {
+            hash: "0x01".to_string(),
+            balances: vec!["0x02".to_string(), "0xno".to_string()],
+        };
+        let err = json_node.to_node::<2>().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid hex \"0xno\": not a hex number");
+
+        let json_node = JsonNode {
+            hash: "0x01".to_string(),
+            balances: vec!["0x02".to_string()],
+        };
+        let err = json_node.to_node::<2>().unwrap_err();
+        assert_eq!(err.to_string(), "Expected 2 balances, got 1");
+    }
+
+    // Field elements of all sizes, including the ones right below the field modulus
+    fn fp_strategy() -> impl Strategy<Value = Fp> {
+        prop_oneof![
+            any::<u64>().prop_map(Fp::from),
+            any::<[u64; 4]>().prop_map(Fp::from_raw),
+            (0..1024u64).prop_map(|k| -Fp::from(k)),
+        ]
+    }
+
+    fn node_strategy() -> impl Strategy<Value = Node<2>> {
+        (fp_strategy(), [fp_strategy(), fp_strategy()])
+            .prop_map(|(hash, balances)| Node { hash, balances })
+    }
+
+    // Balances below 2^108, so that the sums of 16 entries stay within the 14 bytes of the tree
+    fn balance_strategy() -> impl Strategy<Value = BigUint> {
+        prop_oneof![
+            (0..1000u64).prop_map(BigUint::from),
+            any::<u128>().prop_map(|b| BigUint::from(b >> 20)),
+        ]
+    }
+
+    fn entry_strategy() -> impl Strategy<Value = Entry<2>> {
+        ("[a-z0-9]{1,16}", [balance_strategy(), balance_strategy()])
+            .prop_map(|(username, balances)| Entry::new(username, balances).unwrap())
+    }
+
+    proptest! {
+        #[test]
+        fn test_node_json_round_trip(node in node_strategy()) {
+            let json_node = convert_node_to_json(&node);
+            prop_assert_eq!(json_node.to_node::<2>().unwrap(), node);
+        }
+
+        #[test]
+        fn test_parse_fp_from_hex_leading_zeros(fp in fp_strategy(), zeros in 0..8usize) {
+            let hex = format!("{:?}", fp);
+            // Zero keeps a single digit
+            let digits = match hex.trim_start_matches("0x").trim_start_matches('0') {
+                "" => "0",
+                digits => digits,
+            };
+            // Without the zero padding of `{:?}`, or with more of it
+            prop_assert_eq!(parse_fp_from_hex(&format!("0x{}", digits)).unwrap(), fp);
+            let padded = format!("{}{}", "0".repeat(zeros), digits);
+            prop_assert_eq!(parse_fp_from_hex(&padded).unwrap(), fp);
+        }
+
+        #[test]
+        fn test_entry_json_round_trip(entry in entry_strategy(), zeros in 0..4usize) {
+            let json_entry = JsonEntry::from_entry(&entry);
+            let rebuilt_entry = json_entry.to_entry::<2>().unwrap();
+            prop_assert_eq!(rebuilt_entry.username(), entry.username());
+            prop_assert_eq!(rebuilt_entry.balances(), entry.balances());
+
+            // Decimal balances with leading zeros are the same balances
+            let padded_entry = JsonEntry::new(
+                json_entry.username.clone(),
+                json_entry
+                    .balances
+                    .iter()
+                    .map(|b| format!("{}{}", "0".repeat(zeros), b))
+                    .collect(),
+            );
+            let rebuilt_entry = padded_entry.to_entry::<2>().unwrap();
+            prop_assert_eq!(rebuilt_entry.balances(), entry.balances());
+        }
+    }
+
+    proptest! {
+        // Every case builds and verifies a tree
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_tree_json_round_trip(
+            mut entries in prop::collection::vec(entry_strategy(), 1..=16),
+            without_nodes in any::<bool>(),
+        ) {
+            entries.resize(16, Entry::init_empty());
+            let tree =
+                MerkleSumTree::<2, 14>::from_entries(entries, dummy_cryptocurrencies(2), false)
+                    .unwrap();
+            let mut json_tree = JsonMerkleSumTree::from_tree(&tree);
+            if without_nodes {
+                json_tree = json_tree.without_nodes();
+            }
+
+            let json = serde_json::to_string(&json_tree).unwrap();
+            let json_tree: JsonMerkleSumTree = serde_json::from_str(&json).unwrap();
+            let rebuilt_tree = json_tree.to_mst::<2, 14>().unwrap();
+            prop_assert_eq!(rebuilt_tree.root(), tree.root());
+            prop_assert_eq!(rebuilt_tree.nodes(), tree.nodes());
+        }
+    }
 }
@@ -2,5 +2,9 @@
 pub mod aggregation_merkle_sum_tree;
 pub mod executor;
 pub mod json_mst;
+pub mod logging;
+pub mod metrics;
 pub mod mini_tree_generator;
 pub mod orchestrator;
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,24 @@
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable selecting the log format, `json` for one JSON object per line, e.g. for a log aggregator.
+pub const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+
+/// Installs a `tracing` subscriber printing the logs of the Orchestrator, Executors and spawners to stderr.
+///
+/// The verbosity is set with `RUST_LOG`, e.g. `RUST_LOG=summa_aggregation=debug`, and defaults to `info`.
+/// With `LOG_FORMAT=json`, the logs are printed as JSON, including the fields of their spans such as the executor index.
+///
+/// Does nothing if a subscriber is already installed, so it can be called by binaries and tests alike.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    let json = std::env::var(LOG_FORMAT_ENV).map_or(false, |format| format == "json");
+    let _ = if json {
+        builder.json().try_init()
+    } else {
+        builder.try_init()
+    };
+}
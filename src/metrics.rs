@@ -0,0 +1,161 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Upper bounds of the build time histogram buckets, in seconds
+const BUILD_TIME_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// The metrics of the mini-tree server process, shared by all of its requests.
+pub static TREE_METRICS: TreeMetrics = TreeMetrics::new();
+
+/// TreeMetrics
+///
+/// Counts the trees built by a mini-tree server, the entries processed and the rejected requests,
+/// and keeps a histogram of the build times. The counters are atomic, so concurrent requests accumulate correctly.
+///
+/// `render` formats the metrics in the Prometheus text format, which the `/metrics` route of the server responds with.
+#[derive(Debug)]
+pub struct TreeMetrics {
+    trees_built: AtomicU64,
+    entries_processed: AtomicU64,
+    errors: AtomicU64,
+    // Non-cumulative count per bucket, the last one counting the builds above the largest bound
+    build_time_buckets: [AtomicU64; BUILD_TIME_BUCKETS.len() + 1],
+    build_time_sum_micros: AtomicU64,
+}
+
+impl Default for TreeMetrics {
+    fn default() -> Self {
+        TreeMetrics::new()
+    }
+}
+
+impl TreeMetrics {
+    pub const fn new() -> Self {
+        // Only used to initialize the array, each bucket is a distinct atomic
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        TreeMetrics {
+            trees_built: AtomicU64::new(0),
+            entries_processed: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            build_time_buckets: [ZERO; BUILD_TIME_BUCKETS.len() + 1],
+            build_time_sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a tree built from `entries` entries in `build_time`.
+    pub fn record_tree(&self, entries: usize, build_time: Duration) {
+        self.trees_built.fetch_add(1, Ordering::Relaxed);
+        self.entries_processed
+            .fetch_add(entries as u64, Ordering::Relaxed);
+
+        let seconds = build_time.as_secs_f64();
+        let bucket = BUILD_TIME_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUILD_TIME_BUCKETS.len());
+        self.build_time_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.build_time_sum_micros
+            .fetch_add(build_time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Records a request rejected with an error.
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn trees_built(&self) -> u64 {
+        self.trees_built.load(Ordering::Relaxed)
+    }
+
+    pub fn entries_processed(&self) -> u64 {
+        self.entries_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Formats the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        // Writing to a `String` cannot fail
+        let _ = writeln!(
+            output,
+            "# HELP mini_tree_trees_built_total Number of trees built.\n\
+             # TYPE mini_tree_trees_built_total counter\n\
+             mini_tree_trees_built_total {}",
+            self.trees_built()
+        );
+        let _ = writeln!(
+            output,
+            "# HELP mini_tree_entries_processed_total Number of entries in the trees built.\n\
+             # TYPE mini_tree_entries_processed_total counter\n\
+             mini_tree_entries_processed_total {}",
+            self.entries_processed()
+        );
+        let _ = writeln!(
+            output,
+            "# HELP mini_tree_errors_total Number of requests rejected with an error.\n\
+             # TYPE mini_tree_errors_total counter\n\
+             mini_tree_errors_total {}",
+            self.errors()
+        );
+
+        let _ = writeln!(
+            output,
+            "# HELP mini_tree_build_seconds Time to build a tree from its entries.\n\
+             # TYPE mini_tree_build_seconds histogram"
+        );
+        let mut cumulative = 0;
+        for (bound, count) in BUILD_TIME_BUCKETS.iter().zip(&self.build_time_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(
+                output,
+                "mini_tree_build_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        cumulative += self.build_time_buckets[BUILD_TIME_BUCKETS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(
+            output,
+            "mini_tree_build_seconds_bucket{{le=\"+Inf\"}} {}\n\
+             mini_tree_build_seconds_sum {}\n\
+             mini_tree_build_seconds_count {}",
+            cumulative,
+            self.build_time_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            cumulative
+        );
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = TreeMetrics::new();
+        metrics.record_tree(16, Duration::from_millis(20));
+        metrics.record_tree(4, Duration::from_millis(3));
+        metrics.record_tree(1024, Duration::from_secs(10));
+        metrics.record_error();
+
+        let output = metrics.render();
+        assert!(output.contains("mini_tree_trees_built_total 3\n"));
+        assert!(output.contains("mini_tree_entries_processed_total 1044\n"));
+        assert!(output.contains("mini_tree_errors_total 1\n"));
+
+        // Buckets are cumulative, the 10 second build only counts in `+Inf`
+        assert!(output.contains("mini_tree_build_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(output.contains("mini_tree_build_seconds_bucket{le=\"0.025\"} 2\n"));
+        assert!(output.contains("mini_tree_build_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(output.contains("mini_tree_build_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(output.contains("mini_tree_build_seconds_sum 10.023\n"));
+        assert!(output.contains("mini_tree_build_seconds_count 3\n"));
+    }
+}
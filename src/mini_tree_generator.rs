@@ -1,61 +1,1389 @@
-use axum::{extract::Json, http::StatusCode, response::IntoResponse};
+use axum::{
+    body::{boxed, Body, HttpBody, StreamBody},
+    extract::{DefaultBodyLimit, Json, Query, State},
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderMap, HeaderValue, Request, StatusCode,
+    },
+    middleware::{from_fn, map_request_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Router,
+};
 use const_env::from_env;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::env;
+use std::io::{Read, Write};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::Instant;
 
-use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
-use summa_backend::merkle_sum_tree::{Cryptocurrency, Entry, MerkleSumTree};
+use crate::aggregation_merkle_sum_tree::AggregationMerkleSumTree;
+use crate::json_mst::{JsonCryptocurrency, JsonEntry, JsonMerkleSumTree};
+use crate::metrics::TREE_METRICS;
+use summa_backend::merkle_sum_tree::{Cryptocurrency, MerkleSumTree};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 
 /// Mini Tree Generator is designed to create Merkle Sum Tree using the Axum web framework.
 /// It primarily handles HTTP requests to generate tree based on provided JSON entries.
 ///
 /// Constants:
-/// - `N_CURRENCIES`: The number of cryptocurrencies involved. Set via environment variables.
-/// - `N_BYTES`: The byte size for each entry. Set via environment variables.
+/// - `N_CURRENCIES`: The default number of cryptocurrencies involved. Set via environment variables at compile time.
+/// - `N_BYTES`: The default byte size for each entry. Set via environment variables at compile time.
+///   The router serves another default shape if the same variables are set when it is built, see `WorkerInfo::from_env`.
+/// - `N_CURRENCIES_HEADER`, `N_BYTES_HEADER`: Request headers overriding the defaults above for a single request.
+///   Besides the defaults, the shapes combining `SUPPORTED_N_CURRENCIES` with `SUPPORTED_N_BYTES` can be requested.
 ///
 /// Functions:
 /// - `create_mst`: An asynchronous function that processes incoming JSON requests to generate a Merkle Sum Tree.
 ///   It converts `JsonEntry` objects into `Entry<N_CURRENCIES>` instances and then constructs the `MerkleSumTree`.
 ///   The function handles the conversion of the `MerkleSumTree` into a JSON format (`JsonMerkleSumTree`) for the response.
+///   The entries may be sent with the cryptocurrencies of their balances, see `TreeRequest`.
+///   Invalid requests, e.g. an unsupported shape in the headers or a malformed balance, are rejected with
+///   `400 Bad Request` and an `ErrorResponse` body.
+///   With the `omit_nodes=true` query parameter, the tree is sent without its `nodes` to shrink the response,
+///   this applies to the batch routes below as well.
+///   With the `sorted=true` query parameter, the entries are sorted by username before the tree is built,
+///   and the tree is sent with `is_sorted` set.
+///   The time it took to build the tree is sent in `build_ms`, which the Orchestrator collects in its `RoundStats`.
+/// - `create_mst_batch`: Builds a tree from each of the posted batches of entries, like `create_mst` does for one batch,
+///   and responds with a `BatchItem` per batch in the same order. A rejected batch is reported in its item,
+///   while the other batches are still built.
+/// - `create_mst_batch_stream`: Like `create_mst_batch`, but streams the items as newline-delimited JSON
+///   (`application/x-ndjson`), sending each item as soon as its tree is built.
+/// - `health`: Responds with `200 OK` as long as the server is running, used by Executors to check their Worker
+///   and as the liveness probe of orchestrators like Kubernetes.
+/// - `ready`: Responds with `200 OK` while a build is free to start, `503 Service Unavailable` while all builds
+///   of the `BuildLimit` are taken, used as the readiness probe and by Executors waiting for their Worker.
+/// - `info`: Responds with the `WorkerInfo` of the server, i.e. the tree shapes it builds,
+///   which Executors check against the shape of the Orchestrator before sending any entries.
+/// - `metrics`: Responds with the `TREE_METRICS` of the process in the Prometheus text format,
+///   i.e. the trees built, the entries processed, the rejected requests and a histogram of the build times.
+/// - `build_mst`: Builds the tree `create_mst` would respond with, without a server, used by in-process Executors.
+/// - `mini_tree_router`: Builds the `Router` serving all of the above,
+///   `POST /`, `POST /batch`, `POST /batch/stream`, `GET /health` (also as `GET /healthz`), `GET /readyz`,
+///   `GET /info` and `GET /metrics`.
+///   Request bodies sent with `Content-Encoding: gzip` are decompressed before they reach `create_mst`,
+///   and responses are gzip-compressed for clients sending `Accept-Encoding: gzip`.
+///   The trees built at once are bounded by the `BuildLimit` read from the environment, see `BuildLimit::from_env`,
+///   and the size of the requests by the `RequestLimit`, see `RequestLimit::from_env`.
 ///
 #[from_env]
 const N_CURRENCIES: usize = 2;
 #[from_env]
 const N_BYTES: usize = 14;
 
+pub const N_CURRENCIES_HEADER: &str = "x-n-currencies";
+pub const N_BYTES_HEADER: &str = "x-n-bytes";
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+// Keep in sync with the dispatch in `create_mst` and `build_tree_with_n_bytes`
+pub const SUPPORTED_N_CURRENCIES: [usize; 4] = [1, 2, 3, 4];
+pub const SUPPORTED_N_BYTES: [usize; 3] = [8, 14, 16];
+
+/// Environment variable setting how many requests the server builds trees for at once, unlimited if not set or 0.
+pub const MAX_BUILDS_ENV: &str = "MINI_TREE_MAX_BUILDS";
+/// Environment variable setting how many requests may wait for a build, as many as `MINI_TREE_MAX_BUILDS` if not set.
+pub const MAX_QUEUED_BUILDS_ENV: &str = "MINI_TREE_MAX_QUEUED_BUILDS";
+/// Environment variable setting the most entries of a request, over all its batches.
+pub const MAX_ENTRIES_ENV: &str = "MINI_TREE_MAX_ENTRIES";
+/// Environment variable setting the memory in bytes the trees of the server may take, bounding the entries of a request
+/// unless `MINI_TREE_MAX_ENTRIES` is set.
+pub const MEMORY_BUDGET_ENV: &str = "MINI_TREE_MEMORY_BUDGET";
+/// Environment variable setting the largest request body in bytes, after decompression.
+pub const MAX_BODY_BYTES_ENV: &str = "MINI_TREE_MAX_BODY_BYTES";
+
+// The body limit of axum, kept as the default
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+// The largest shape that can be requested, the supported values being sorted
+const LARGEST_N_CURRENCIES: usize = SUPPORTED_N_CURRENCIES[SUPPORTED_N_CURRENCIES.len() - 1];
+const LARGEST_N_BYTES: usize = SUPPORTED_N_BYTES[SUPPORTED_N_BYTES.len() - 1];
+
+/// Machine-readable reason of an `ErrorResponse`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorReason {
+    /// A shape header is not a number.
+    InvalidHeader,
+    /// The requested combination of currencies and bytes is not supported.
+    UnsupportedShape,
+    /// An entry has a wrong number of balances or a malformed balance.
+    InvalidEntry,
+    /// The tree could not be built from the entries, e.g. the balances exceed the range given by `N_BYTES`.
+    TreeConstruction,
+    /// The number of cryptocurrencies differs from the number of currencies of the tree.
+    InvalidCryptocurrencies,
+    /// All builds are taken and the queue is full, sent with `503 Service Unavailable` so that the request is retried.
+    Overloaded,
+    /// The request holds more entries than the `RequestLimit` allows, sent with `413 Payload Too Large`.
+    TooManyEntries,
+}
+
+/// Body of the `400 Bad Request`, `413 Payload Too Large` and `503 Service Unavailable` responses of `create_mst`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub reason: ErrorReason,
+    pub message: String,
+    /// Index of the failing entry in the request, for `InvalidEntry`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry_index: Option<usize>,
+}
+
+/// Body of the `info` route, describing the tree shapes the Worker builds, see `Executor::check_tree_shape`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerInfo {
+    /// Number of currencies of the trees built for requests without `N_CURRENCIES_HEADER`.
+    pub n_currencies: usize,
+    /// Number of bytes of the trees built for requests without `N_BYTES_HEADER`.
+    pub n_bytes: usize,
+    pub supported_n_currencies: Vec<usize>,
+    pub supported_n_bytes: Vec<usize>,
+}
+
+impl WorkerInfo {
+    /// Returns the info of the tree building code of this process, as served by the `info` route.
+    pub fn local() -> Self {
+        WorkerInfo {
+            n_currencies: N_CURRENCIES,
+            n_bytes: N_BYTES,
+            supported_n_currencies: SUPPORTED_N_CURRENCIES.to_vec(),
+            supported_n_bytes: SUPPORTED_N_BYTES.to_vec(),
+        }
+    }
+
+    /// Returns the info of this process with the default shape read from the `N_CURRENCIES` and `N_BYTES`
+    /// environment variables, falling back to the defaults it was compiled with.
+    ///
+    /// Fails if a variable is not a number, or the shape is not one this process can build.
+    pub fn from_env() -> Result<Self, String> {
+        WorkerInfo::with_default_shape(
+            read_env_usize("N_CURRENCIES")?.unwrap_or(N_CURRENCIES),
+            read_env_usize("N_BYTES")?.unwrap_or(N_BYTES),
+        )
+    }
+
+    /// Returns the info of this process with `n_currencies` and `n_bytes` as the default shape,
+    /// failing if it is not one this process can build.
+    pub fn with_default_shape(n_currencies: usize, n_bytes: usize) -> Result<Self, String> {
+        let local = WorkerInfo::local();
+        if !local.supports(n_currencies, n_bytes) {
+            return Err(format!(
+                "Unsupported default tree shape: {} currencies with {} bytes",
+                n_currencies, n_bytes
+            ));
+        }
+        Ok(WorkerInfo {
+            n_currencies,
+            n_bytes,
+            ..local
+        })
+    }
+
+    /// Returns whether the Worker builds trees of `n_currencies` currencies with `n_bytes` bytes when requested.
+    pub fn supports(&self, n_currencies: usize, n_bytes: usize) -> bool {
+        (n_currencies, n_bytes) == (self.n_currencies, self.n_bytes)
+            || (self.supported_n_currencies.contains(&n_currencies)
+                && self.supported_n_bytes.contains(&n_bytes))
+    }
+}
+
+/// Body of the tree building routes, holding the entries of a tree for `create_mst`, or the batches for the batch routes.
+///
+/// The entries are either sent alone, or with the cryptocurrencies of their balances, which the trees are then built with.
+/// Without them, the cryptocurrencies of the trees are named "DUMMY" on "ETH".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TreeRequest<T> {
+    Entries(T),
+    WithCryptocurrencies {
+        cryptocurrencies: Vec<JsonCryptocurrency>,
+        entries: T,
+    },
+}
+
+impl<T> TreeRequest<T> {
+    fn into_parts(self) -> (Vec<JsonCryptocurrency>, T) {
+        match self {
+            TreeRequest::Entries(entries) => (Vec::new(), entries),
+            TreeRequest::WithCryptocurrencies {
+                cryptocurrencies,
+                entries,
+            } => (cryptocurrencies, entries),
+        }
+    }
+}
+
+/// Query parameters accepted by the tree building routes.
+#[derive(Debug, Default, Deserialize)]
+pub struct TreeQuery {
+    /// Responds with the trees without their `nodes`, which the client rebuilds from the entries.
+    #[serde(default)]
+    pub omit_nodes: bool,
+    /// Sorts the entries by username before building the trees, as some proof schemes require.
+    #[serde(default)]
+    pub sorted: bool,
+}
+
+/// One item of the response of `create_mst_batch`, either the tree built from a batch or why it could not be built.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItem {
+    Tree(JsonMerkleSumTree),
+    Error(ErrorResponse),
+}
+
+type ErrorResult<T> = Result<T, (StatusCode, Json<ErrorResponse>)>;
+
+/// Bounds the requests the tree building routes serve at once, protecting a Worker from running out of memory
+/// when many large chunks are sent to it.
+///
+/// A request arriving while `max_builds` requests are being served waits for one of them to finish,
+/// unless `max_queued` requests are already waiting. It is then rejected with `503 Service Unavailable`
+/// and `ErrorReason::Overloaded`, which Executors retry according to their retry policy.
+/// A batch request takes a single build, as its trees are built one after the other.
+#[derive(Debug, Clone)]
+pub struct BuildLimit {
+    max_builds: usize,
+    semaphore: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+impl BuildLimit {
+    pub fn new(max_builds: usize, max_queued: usize) -> Self {
+        BuildLimit {
+            max_builds,
+            semaphore: Arc::new(Semaphore::new(max_builds)),
+            queued: Arc::default(),
+            max_queued,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        BuildLimit::new(Semaphore::MAX_PERMITS, 0)
+    }
+
+    /// Returns the number of requests served at once, `None` if unlimited.
+    pub fn max_builds(&self) -> Option<usize> {
+        (self.max_builds < Semaphore::MAX_PERMITS).then_some(self.max_builds)
+    }
+
+    /// Returns whether a request arriving now would start building at once, instead of waiting for a build.
+    pub fn is_available(&self) -> bool {
+        self.semaphore.available_permits() > 0
+    }
+
+    /// Reads the limit from `MINI_TREE_MAX_BUILDS` and `MINI_TREE_MAX_QUEUED_BUILDS`, unlimited if the former is not set or 0.
+    ///
+    /// Fails if a variable is set but not a number, rather than serving without the limit.
+    pub fn from_env() -> Result<Self, String> {
+        let max_builds = read_env_usize(MAX_BUILDS_ENV)?;
+        let max_queued = read_env_usize(MAX_QUEUED_BUILDS_ENV)?;
+        Ok(match max_builds {
+            Some(max_builds) if max_builds > 0 => {
+                BuildLimit::new(max_builds, max_queued.unwrap_or(max_builds))
+            }
+            _ => BuildLimit::unlimited(),
+        })
+    }
+
+    /// Waits for a build, failing with `503 Service Unavailable` if the queue is full.
+    /// The build is released when the returned permit is dropped.
+    pub async fn acquire(&self) -> ErrorResult<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let queued = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < self.max_queued).then_some(queued + 1)
+            });
+        if queued.is_err() {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse {
+                    reason: ErrorReason::Overloaded,
+                    message: format!(
+                        "All {} builds are taken and {} requests are waiting",
+                        self.max_builds, self.max_queued
+                    ),
+                    entry_index: None,
+                }),
+            ));
+        }
+
+        // Leave the queue even if the request is dropped while waiting, e.g. when the client disconnects
+        let _queued = QueueSlot(&self.queued);
+        Ok(self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The build semaphore is never closed"))
+    }
+}
+
+impl Default for BuildLimit {
+    fn default() -> Self {
+        BuildLimit::unlimited()
+    }
+}
+
+/// Bounds the size of the requests to the tree building routes, so that a single huge request cannot exhaust
+/// the memory of a Worker before its entries are even checked.
+///
+/// A request body larger than `max_body_bytes`, after decompression, is rejected with `413 Payload Too Large`
+/// before it is parsed. A request holding more than `max_entries` entries, over all its batches, is rejected
+/// with `413 Payload Too Large` and `ErrorReason::TooManyEntries` before any tree is built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimit {
+    pub max_entries: Option<usize>,
+    pub max_body_bytes: usize,
+}
+
+impl Default for RequestLimit {
+    fn default() -> Self {
+        RequestLimit {
+            max_entries: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+}
+
+impl RequestLimit {
+    /// Reads the limit from `MINI_TREE_MAX_ENTRIES` and `MINI_TREE_MAX_BODY_BYTES`, the body defaulting to 2 MB.
+    ///
+    /// Without `MINI_TREE_MAX_ENTRIES`, the entries are bounded by `MINI_TREE_MEMORY_BUDGET` if set, which is shared
+    /// by the builds of the `build_limit`, see `max_entries_for_memory`.
+    ///
+    /// Fails if a variable is set but not a number, rather than serving without the limit.
+    pub fn from_env(build_limit: &BuildLimit) -> Result<Self, String> {
+        let max_entries = match read_env_usize(MAX_ENTRIES_ENV)? {
+            Some(max_entries) => Some(max_entries),
+            None => read_env_usize(MEMORY_BUDGET_ENV)?.map(|memory_budget| {
+                let builds = build_limit.max_builds().unwrap_or(1);
+                RequestLimit::max_entries_for_memory(memory_budget / builds)
+            }),
+        };
+        Ok(RequestLimit {
+            max_entries,
+            max_body_bytes: read_env_usize(MAX_BODY_BYTES_ENV)?.unwrap_or(DEFAULT_MAX_BODY_BYTES),
+        })
+    }
+
+    /// Returns the most entries of a tree that fits in `memory_budget` bytes whatever shape is requested,
+    /// i.e. for the default shape and the largest of `SUPPORTED_N_CURRENCIES` and `SUPPORTED_N_BYTES`,
+    /// as estimated by `AggregationMerkleSumTree::estimate_memory` for a single mini tree, and at least 1.
+    ///
+    /// As the trees are padded to a power of two, this is a power of two.
+    pub fn max_entries_for_memory(memory_budget: usize) -> usize {
+        let mut max_entries = 1usize;
+        while max_entries < usize::MAX / 4 && largest_tree_memory(2 * max_entries) <= memory_budget
+        {
+            max_entries *= 2;
+        }
+        max_entries
+    }
+
+    fn check_entries(&self, n_entries: usize) -> ErrorResult<()> {
+        match self.max_entries {
+            Some(max_entries) if n_entries > max_entries => {
+                TREE_METRICS.record_error();
+                Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(ErrorResponse {
+                        reason: ErrorReason::TooManyEntries,
+                        message: format!(
+                            "The request holds {} entries, at most {} are accepted",
+                            n_entries, max_entries
+                        ),
+                        entry_index: None,
+                    }),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// The estimated memory of a tree of `entries` entries in the largest shape the server builds
+fn largest_tree_memory(entries: usize) -> usize {
+    AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::estimate_memory(1, entries).max(
+        AggregationMerkleSumTree::<LARGEST_N_CURRENCIES, LARGEST_N_BYTES>::estimate_memory(
+            1, entries,
+        ),
+    )
+}
+
+// Reads a number from the environment variable `name`, `None` if it is not set or empty
+fn read_env_usize(name: &str) -> Result<Option<usize>, String> {
+    match env::var(name) {
+        Ok(value) if !value.is_empty() => value
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| format!("Invalid {}: {}", name, value)),
+        _ => Ok(None),
+    }
+}
+
+// Frees a place in the queue of a BuildLimit when dropped
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn bad_request<T>(
+    reason: ErrorReason,
+    message: String,
+    entry_index: Option<usize>,
+) -> ErrorResult<T> {
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            reason,
+            message,
+            entry_index,
+        }),
+    ))
+}
+
 pub async fn create_mst(
-    Json(json_entries): Json<Vec<JsonEntry>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<JsonMerkleSumTree>)> {
+    Extension(worker_info): Extension<WorkerInfo>,
+    Extension(build_limit): Extension<BuildLimit>,
+    Extension(request_limit): Extension<RequestLimit>,
+    headers: HeaderMap,
+    Query(query): Query<TreeQuery>,
+    Json(request): Json<TreeRequest<Vec<JsonEntry>>>,
+) -> ErrorResult<impl IntoResponse> {
+    let (cryptocurrencies, json_entries) = request.into_parts();
+    request_limit.check_entries(json_entries.len())?;
+    let _build = build_limit.acquire().await?;
+    // The tree is built on a blocking thread, so the runtime keeps serving other requests meanwhile
+    let json_tree = tokio::task::spawn_blocking(move || {
+        build_requested_tree(
+            &worker_info,
+            &headers,
+            &query,
+            &cryptocurrencies,
+            &json_entries,
+        )
+    })
+    .await
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+    if json_tree.is_err() {
+        TREE_METRICS.record_error();
+    }
+    Ok((StatusCode::OK, Json(json_tree?)))
+}
+
+pub async fn create_mst_batch(
+    Extension(worker_info): Extension<WorkerInfo>,
+    Extension(build_limit): Extension<BuildLimit>,
+    Extension(request_limit): Extension<RequestLimit>,
+    headers: HeaderMap,
+    Query(query): Query<TreeQuery>,
+    Json(request): Json<TreeRequest<Vec<Vec<JsonEntry>>>>,
+) -> ErrorResult<impl IntoResponse> {
+    let (cryptocurrencies, batches) = request.into_parts();
+    request_limit.check_entries(batches.iter().map(Vec::len).sum())?;
+    let _build = build_limit.acquire().await?;
+    // The trees are built on a blocking thread, so the runtime keeps serving other requests meanwhile
+    let items = tokio::task::spawn_blocking(move || {
+        batches
+            .iter()
+            .map(|json_entries| {
+                build_batch_item(
+                    &worker_info,
+                    &headers,
+                    &query,
+                    &cryptocurrencies,
+                    json_entries,
+                )
+            })
+            .collect::<Vec<BatchItem>>()
+    })
+    .await
+    // A panic while building is raised in the handler, as if the trees were built in place
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+
+    Ok((StatusCode::OK, Json(items)))
+}
+
+pub async fn create_mst_batch_stream(
+    Extension(worker_info): Extension<WorkerInfo>,
+    Extension(build_limit): Extension<BuildLimit>,
+    Extension(request_limit): Extension<RequestLimit>,
+    headers: HeaderMap,
+    Query(query): Query<TreeQuery>,
+    Json(request): Json<TreeRequest<Vec<Vec<JsonEntry>>>>,
+) -> ErrorResult<impl IntoResponse> {
+    let (cryptocurrencies, batches) = request.into_parts();
+    request_limit.check_entries(batches.iter().map(Vec::len).sum())?;
+    let build = build_limit.acquire().await?;
+    // The trees are built on a blocking thread and sent as soon as each of them is done
+    let (line_tx, line_rx) = mpsc::channel::<Result<Vec<u8>, Infallible>>(1);
+    tokio::task::spawn_blocking(move || {
+        // The build is held until the last tree is sent, not only until the response starts
+        let _build = build;
+        for json_entries in &batches {
+            let item = build_batch_item(
+                &worker_info,
+                &headers,
+                &query,
+                &cryptocurrencies,
+                json_entries,
+            );
+            let mut line = serde_json::to_vec(&item).unwrap();
+            line.push(b'\n');
+            // Stop building if the client is gone
+            if line_tx.blocking_send(Ok(line)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let lines = stream::unfold(line_rx, |mut line_rx| async move {
+        line_rx.recv().await.map(|line| (line, line_rx))
+    });
+    Ok((
+        [(CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        StreamBody::new(lines),
+    ))
+}
+
+fn build_batch_item(
+    worker_info: &WorkerInfo,
+    headers: &HeaderMap,
+    query: &TreeQuery,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> BatchItem {
+    match build_requested_tree(worker_info, headers, query, cryptocurrencies, json_entries) {
+        Ok(json_tree) => BatchItem::Tree(json_tree),
+        Err((_, Json(error))) => {
+            TREE_METRICS.record_error();
+            BatchItem::Error(error)
+        }
+    }
+}
+
+// Builds the tree in the shape requested by the headers, or the default shape of the Worker without them,
+// without the nodes if requested by the query
+fn build_requested_tree(
+    worker_info: &WorkerInfo,
+    headers: &HeaderMap,
+    query: &TreeQuery,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> ErrorResult<JsonMerkleSumTree> {
+    let n_currencies = parse_shape_header(headers, N_CURRENCIES_HEADER, worker_info.n_currencies)?;
+    let n_bytes = parse_shape_header(headers, N_BYTES_HEADER, worker_info.n_bytes)?;
+    build_tree_with_query(n_currencies, n_bytes, query, cryptocurrencies, json_entries)
+}
+
+/// Builds the tree `create_mst` responds with to a request for `n_currencies` currencies with `n_bytes` bytes,
+/// without going through a server.
+///
+/// The tree is built on the calling thread, which it blocks for as long as the tree takes to build.
+/// The entries are rejected with the same `ErrorResponse` as by `create_mst`.
+pub fn build_mst(
+    n_currencies: usize,
+    n_bytes: usize,
+    query: &TreeQuery,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> Result<JsonMerkleSumTree, ErrorResponse> {
+    build_tree_with_query(n_currencies, n_bytes, query, cryptocurrencies, json_entries)
+        .map_err(|(_, Json(error))| error)
+}
+
+fn build_tree_with_query(
+    n_currencies: usize,
+    n_bytes: usize,
+    query: &TreeQuery,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> ErrorResult<JsonMerkleSumTree> {
+    let json_tree = build_tree_with_shape(
+        n_currencies,
+        n_bytes,
+        query.sorted,
+        cryptocurrencies,
+        json_entries,
+    )?;
+    if query.omit_nodes {
+        Ok(json_tree.without_nodes())
+    } else {
+        Ok(json_tree)
+    }
+}
+
+fn build_tree_with_shape(
+    n_currencies: usize,
+    n_bytes: usize,
+    sorted: bool,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> ErrorResult<JsonMerkleSumTree> {
+    if (n_currencies, n_bytes) == (N_CURRENCIES, N_BYTES) {
+        build_tree::<N_CURRENCIES, N_BYTES>(sorted, cryptocurrencies, json_entries)
+    } else {
+        match n_currencies {
+            1 => build_tree_with_n_bytes::<1>(n_bytes, sorted, cryptocurrencies, json_entries),
+            2 => build_tree_with_n_bytes::<2>(n_bytes, sorted, cryptocurrencies, json_entries),
+            3 => build_tree_with_n_bytes::<3>(n_bytes, sorted, cryptocurrencies, json_entries),
+            4 => build_tree_with_n_bytes::<4>(n_bytes, sorted, cryptocurrencies, json_entries),
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            bad_request(
+                ErrorReason::UnsupportedShape,
+                format!(
+                    "Unsupported tree shape: {} currencies with {} bytes, supported are {:?} currencies with {:?} bytes",
+                    n_currencies, n_bytes, SUPPORTED_N_CURRENCIES, SUPPORTED_N_BYTES
+                ),
+                None,
+            )
+        })
+    }
+}
+
+fn parse_shape_header(headers: &HeaderMap, name: &str, default: usize) -> ErrorResult<usize> {
+    match headers.get(name) {
+        Some(value) => match value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+        {
+            Some(value) => Ok(value),
+            None => bad_request(
+                ErrorReason::InvalidHeader,
+                format!("Invalid {} header", name),
+                None,
+            ),
+        },
+        None => Ok(default),
+    }
+}
+
+fn build_tree_with_n_bytes<const N_CURRENCIES: usize>(
+    n_bytes: usize,
+    sorted: bool,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> Option<ErrorResult<JsonMerkleSumTree>>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
+    match n_bytes {
+        8 => Some(build_tree::<N_CURRENCIES, 8>(
+            sorted,
+            cryptocurrencies,
+            json_entries,
+        )),
+        14 => Some(build_tree::<N_CURRENCIES, 14>(
+            sorted,
+            cryptocurrencies,
+            json_entries,
+        )),
+        16 => Some(build_tree::<N_CURRENCIES, 16>(
+            sorted,
+            cryptocurrencies,
+            json_entries,
+        )),
+        _ => None,
+    }
+}
+
+fn build_tree<const N_CURRENCIES: usize, const N_BYTES: usize>(
+    sorted: bool,
+    cryptocurrencies: &[JsonCryptocurrency],
+    json_entries: &[JsonEntry],
+) -> ErrorResult<JsonMerkleSumTree>
+where
+    [usize; N_CURRENCIES + 1]: Sized,
+    [usize; N_CURRENCIES + 2]: Sized,
+{
     // Convert `JsonEntry` -> `Entry<N_CURRENCIES>`
-    let entries = json_entries
-        .iter()
-        .map(|json_entry| json_entry.to_entry())
-        .collect::<Vec<Entry<N_CURRENCIES>>>();
-    let crypcocurrencies = vec![
-        Cryptocurrency {
-            name: "DUMMY".to_string(),
-            chain: "ETH".to_string(),
-        };
-        N_CURRENCIES
-    ];
+    let mut entries = Vec::with_capacity(json_entries.len());
+    for (index, json_entry) in json_entries.iter().enumerate() {
+        match json_entry.to_entry::<N_CURRENCIES>() {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                return bad_request(
+                    ErrorReason::InvalidEntry,
+                    format!("Invalid entry {:?}: {}", json_entry.username, err),
+                    Some(index),
+                )
+            }
+        }
+    }
+    let cryptocurrencies = if cryptocurrencies.is_empty() {
+        vec![
+            Cryptocurrency {
+                name: "DUMMY".to_string(),
+                chain: "ETH".to_string(),
+            };
+            N_CURRENCIES
+        ]
+    } else if cryptocurrencies.len() == N_CURRENCIES {
+        cryptocurrencies
+            .iter()
+            .map(JsonCryptocurrency::to_cryptocurrency)
+            .collect()
+    } else {
+        return bad_request(
+            ErrorReason::InvalidCryptocurrencies,
+            format!(
+                "Expected {} cryptocurrencies, got {}",
+                N_CURRENCIES,
+                cryptocurrencies.len()
+            ),
+            None,
+        );
+    };
 
-    #[cfg(not(test))]
     let entries_length = entries.len();
-    #[cfg(not(test))]
-    let starting_time = std::time::Instant::now();
+    let starting_time = Instant::now();
 
     // Create `MerkleSumTree<N_CURRENCIES, N_BYTES>` from `parsed_entries`
-    let tree =
-        MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(entries, crypcocurrencies, false)
-            .unwrap();
+    let tree = match MerkleSumTree::<N_CURRENCIES, N_BYTES>::from_entries(
+        entries,
+        cryptocurrencies,
+        sorted,
+    ) {
+        Ok(tree) => tree,
+        Err(err) => return bad_request(ErrorReason::TreeConstruction, err.to_string(), None),
+    };
 
-    #[cfg(not(test))]
-    println!(
-        "Time to create tree({} entries): {}ms",
-        entries_length,
-        starting_time.elapsed().as_millis()
-    );
+    let build_time = starting_time.elapsed();
+    TREE_METRICS.record_tree(entries_length, build_time);
 
     // Convert `MerkleSumTree<N_CURRENCIES, N_BYTES>` to `JsonMerkleSumTree`
-    let json_tree = JsonMerkleSumTree::from_tree(tree);
+    Ok(JsonMerkleSumTree::from_tree(&tree)
+        .with_sorted(sorted)
+        .with_build_ms(build_time.as_millis() as u64))
+}
+
+pub async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+pub async fn ready(Extension(build_limit): Extension<BuildLimit>) -> StatusCode {
+    if build_limit.is_available() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+pub async fn info(Extension(worker_info): Extension<WorkerInfo>) -> Json<WorkerInfo> {
+    Json(worker_info)
+}
+
+pub async fn metrics() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        TREE_METRICS.render(),
+    )
+}
+
+/// Builds the router serving the default shape and limits read from the environment.
+///
+/// Panics if the default shape or a limit in the environment is invalid, see `WorkerInfo::from_env`,
+/// `BuildLimit::from_env` and `RequestLimit::from_env`.
+pub fn mini_tree_router() -> Router {
+    let build_limit = BuildLimit::from_env().unwrap_or_else(|e| panic!("{}", e));
+    let request_limit = RequestLimit::from_env(&build_limit).unwrap_or_else(|e| panic!("{}", e));
+    mini_tree_router_with_limits(build_limit, request_limit)
+}
+
+/// Builds the router of `mini_tree_router` with the given limits instead of those of the environment.
+pub fn mini_tree_router_with_limits(
+    build_limit: BuildLimit,
+    request_limit: RequestLimit,
+) -> Router {
+    let worker_info = WorkerInfo::from_env().unwrap_or_else(|e| panic!("{}", e));
+    router(worker_info, build_limit, request_limit)
+}
+
+/// Builds the router of `mini_tree_router` with the default shape of `worker_info` instead of that of the environment.
+pub fn mini_tree_router_with_shape(worker_info: WorkerInfo) -> Router {
+    let build_limit = BuildLimit::from_env().unwrap_or_else(|e| panic!("{}", e));
+    let request_limit = RequestLimit::from_env(&build_limit).unwrap_or_else(|e| panic!("{}", e));
+    router(worker_info, build_limit, request_limit)
+}
+
+fn router(worker_info: WorkerInfo, build_limit: BuildLimit, request_limit: RequestLimit) -> Router {
+    Router::new()
+        .route("/", post(create_mst))
+        .route("/batch", post(create_mst_batch))
+        .route("/batch/stream", post(create_mst_batch_stream))
+        .route("/health", get(health))
+        .route("/healthz", get(health))
+        .route("/readyz", get(ready))
+        .route("/info", get(info))
+        .route("/metrics", get(metrics))
+        .layer(Extension(worker_info))
+        .layer(Extension(build_limit))
+        .layer(Extension(request_limit))
+        .layer(DefaultBodyLimit::max(request_limit.max_body_bytes))
+        .layer(map_request_with_state(request_limit, decompress_request))
+        .layer(from_fn(compress_response))
+}
+
+/// Decompresses request bodies sent with `Content-Encoding: gzip`, other requests pass through unchanged.
+///
+/// Neither the compressed nor the decompressed body may exceed the `max_body_bytes` of the `RequestLimit`.
+async fn decompress_request(
+    State(request_limit): State<RequestLimit>,
+    request: Request<Body>,
+) -> Result<Request<Body>, (StatusCode, String)> {
+    let is_gzip = request
+        .headers()
+        .get(CONTENT_ENCODING)
+        .map_or(false, |encoding| {
+            encoding.as_bytes().eq_ignore_ascii_case(b"gzip")
+        });
+    if !is_gzip {
+        return Ok(request);
+    }
+
+    let max_body_bytes = request_limit.max_body_bytes;
+    let body_too_large = || {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("The request body exceeds {} bytes", max_body_bytes),
+        )
+    };
+
+    let (mut parts, mut body) = request.into_parts();
+    let mut compressed = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        if compressed.len() + chunk.len() > max_body_bytes {
+            return Err(body_too_large());
+        }
+        compressed.extend_from_slice(&chunk);
+    }
+
+    // Reading one byte past the limit tells a body of exactly the limit from a larger one
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&compressed[..])
+        .take(max_body_bytes as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|err| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid gzip body: {}", err),
+            )
+        })?;
+    if decompressed.len() > max_body_bytes {
+        return Err(body_too_large());
+    }
+
+    parts.headers.remove(CONTENT_ENCODING);
+    parts.headers.remove(CONTENT_LENGTH);
+    Ok(Request::from_parts(parts, Body::from(decompressed)))
+}
+
+/// Compresses the response body with gzip if the client advertises `Accept-Encoding: gzip`.
+async fn compress_response(request: Request<Body>, next: Next<Body>) -> Response {
+    let accepts_gzip = request
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|encodings| encodings.to_str().ok())
+        .map_or(false, |encodings| {
+            encodings
+                .split(',')
+                .any(|encoding| encoding.split(';').next().map(str::trim) == Some("gzip"))
+        });
+
+    let response = next.run(request).await;
+    // Compressing would buffer the whole body, which defeats streaming the trees one by one
+    let is_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .map_or(false, |content_type| content_type == NDJSON_CONTENT_TYPE);
+    if !accepts_gzip || is_stream || response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let compressed = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&bytes).and_then(|_| encoder.finish())
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+
+    match compressed {
+        Ok(compressed) => {
+            parts
+                .headers
+                .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, boxed(Body::from(compressed)))
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_json_entries, spawn_test_server};
+    use reqwest::Client;
+    use summa_backend::merkle_sum_tree::utils::parse_csv_to_entries;
+
+    #[tokio::test]
+    async fn test_gzip_response() {
+        let url = spawn_test_server(mini_tree_router());
+
+        let json_entries = load_json_entries("csv/entry_16.csv");
+
+        // Disable automatic decompression to inspect the raw response
+        let client = Client::builder().no_gzip().build().unwrap();
+        let plain_tree = client
+            .post(&url)
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+
+        let response = client
+            .post(&url)
+            .header(ACCEPT_ENCODING, "gzip")
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&response.bytes().await.unwrap()[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        let gzip_tree = serde_json::from_slice::<JsonMerkleSumTree>(&decompressed).unwrap();
+
+        assert_eq!(plain_tree.root.hash, gzip_tree.root.hash);
+        assert_eq!(plain_tree.root.balances, gzip_tree.root.balances);
+    }
+
+    #[tokio::test]
+    async fn test_sorted_tree() {
+        let url = spawn_test_server(mini_tree_router());
+
+        let json_entries = load_json_entries("csv/entry_16.csv");
+
+        let client = Client::new();
+        let unsorted_tree = client
+            .post(&url)
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert!(!unsorted_tree.is_sorted);
+
+        let sorted_tree = client
+            .post(format!("{}?sorted=true", url))
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert!(sorted_tree.is_sorted);
+        let usernames = sorted_tree
+            .entries
+            .iter()
+            .map(|entry| entry.username.clone())
+            .collect::<Vec<_>>();
+        let mut sorted_usernames = usernames.clone();
+        sorted_usernames.sort();
+        assert_eq!(usernames, sorted_usernames);
+        assert_ne!(sorted_tree.root.hash, unsorted_tree.root.hash);
+
+        // `is_sorted` survives serialization, and the sorted tree is rebuilt as sent
+        let round_tripped = serde_json::from_str::<JsonMerkleSumTree>(
+            &serde_json::to_string(&sorted_tree).unwrap(),
+        )
+        .unwrap();
+        assert!(round_tripped.is_sorted);
+        let tree = round_tripped.to_mst::<2, 14>().unwrap();
+        assert_eq!(
+            JsonMerkleSumTree::from_tree(&tree).root.hash,
+            sorted_tree.root.hash
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requested_tree_shape() {
+        let url = spawn_test_server(mini_tree_router());
+
+        // Keep only the first currency of each entry
+        let (_, entries) = parse_csv_to_entries::<_, 2, 14>("csv/entry_16.csv").unwrap();
+        let json_entries = entries
+            .iter()
+            .map(|entry| {
+                JsonEntry::new(
+                    entry.username().to_string(),
+                    vec![entry.balances()[0].to_string()],
+                )
+            })
+            .collect::<Vec<JsonEntry>>();
+
+        let client = Client::new();
+        let json_tree = client
+            .post(&url)
+            .header(N_CURRENCIES_HEADER, "1")
+            .header(N_BYTES_HEADER, "8")
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert_eq!(json_tree.root.balances.len(), 1);
+
+        let response = client
+            .post(&url)
+            .header(N_CURRENCIES_HEADER, "1")
+            .header(N_BYTES_HEADER, "20")
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error = response.json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.reason, ErrorReason::UnsupportedShape);
+    }
+
+    #[tokio::test]
+    async fn test_default_tree_shape() {
+        // A Worker started with `N_CURRENCIES=1` builds trees of one currency without any header
+        let worker_info = WorkerInfo::with_default_shape(1, 14).unwrap();
+        let url = spawn_test_server(mini_tree_router_with_shape(worker_info.clone()));
+
+        let (_, entries) = parse_csv_to_entries::<_, 2, 14>("csv/entry_16.csv").unwrap();
+        let json_entries = entries
+            .iter()
+            .map(|entry| {
+                JsonEntry::new(
+                    entry.username().to_string(),
+                    vec![entry.balances()[0].to_string()],
+                )
+            })
+            .collect::<Vec<JsonEntry>>();
+
+        let client = Client::new();
+        let json_tree = client
+            .post(&url)
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert_eq!(json_tree.root.balances.len(), 1);
+
+        let served_info = client
+            .get(format!("{}/info", url))
+            .send()
+            .await
+            .unwrap()
+            .json::<WorkerInfo>()
+            .await
+            .unwrap();
+        assert_eq!(served_info, worker_info);
+
+        assert!(WorkerInfo::with_default_shape(5, 14).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cryptocurrencies_request() {
+        let url = spawn_test_server(mini_tree_router());
+
+        let (cryptocurrencies, entries) =
+            parse_csv_to_entries::<_, 2, 14>("csv/entry_16.csv").unwrap();
+        let cryptocurrencies = cryptocurrencies
+            .iter()
+            .map(JsonCryptocurrency::from_cryptocurrency)
+            .collect::<Vec<_>>();
+        let json_entries = entries
+            .iter()
+            .map(JsonEntry::from_entry)
+            .collect::<Vec<JsonEntry>>();
+
+        let client = Client::new();
+        let json_tree = client
+            .post(&url)
+            .json(&TreeRequest::WithCryptocurrencies {
+                cryptocurrencies: cryptocurrencies.clone(),
+                entries: &json_entries,
+            })
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert_eq!(json_tree.cryptocurrencies[1].name, "USDT");
+
+        // The entries alone are still accepted
+        let json_tree = client
+            .post(&url)
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap()
+            .json::<JsonMerkleSumTree>()
+            .await
+            .unwrap();
+        assert_eq!(json_tree.cryptocurrencies[1].name, "DUMMY");
+
+        let response = client
+            .post(&url)
+            .json(&TreeRequest::WithCryptocurrencies {
+                cryptocurrencies: cryptocurrencies[..1].to_vec(),
+                entries: &json_entries,
+            })
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let error = response.json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.reason, ErrorReason::InvalidCryptocurrencies);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_entry_response() {
+        let url = spawn_test_server(mini_tree_router());
+
+        let json_entries = vec![
+            JsonEntry::new(
+                "alice".to_string(),
+                vec!["100".to_string(), "200".to_string()],
+            ),
+            JsonEntry::new(
+                "bob".to_string(),
+                vec!["100".to_string(), "1O0".to_string()],
+            ),
+        ];
+        let response = Client::new()
+            .post(&url)
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let error = response.json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.reason, ErrorReason::InvalidEntry);
+        assert_eq!(error.entry_index, Some(1));
+        assert!(error.message.contains("\"1O0\""));
+    }
+
+    #[tokio::test]
+    async fn test_build_limit() {
+        let build_limit = BuildLimit::new(1, 1);
+        let running = build_limit.acquire().await.unwrap();
+
+        // The second request waits for the running build, the third finds the queue full
+        let queued_limit = build_limit.clone();
+        let queued = tokio::spawn(async move { queued_limit.acquire().await.map(|_| ()) });
+        while build_limit.queued.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let (status, Json(error)) = build_limit.acquire().await.unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.reason, ErrorReason::Overloaded);
+
+        drop(running);
+        assert!(queued.await.unwrap().is_ok());
+        assert_eq!(build_limit.queued.load(Ordering::SeqCst), 0);
+
+        // The tree building routes respond with 503 while the build is taken, which the Executor retries
+        let router_limit = BuildLimit::new(1, 0);
+        let url = spawn_test_server(mini_tree_router_with_limits(
+            router_limit.clone(),
+            RequestLimit::default(),
+        ));
+
+        let json_entries = vec![JsonEntry::new(
+            "alice".to_string(),
+            vec!["100".to_string(), "200".to_string()],
+        )];
+        let client = Client::new();
+        let response = client.post(&url).json(&json_entries).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let _taken = router_limit.acquire().await.unwrap();
+        let response = client.post(&url).json(&json_entries).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let error = response.json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.reason, ErrorReason::Overloaded);
+        // The worker is alive, but not ready for another build
+        let response = client.get(format!("{}/healthz", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client.get(format!("{}/readyz", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        drop(_taken);
+        let response = client.get(format!("{}/readyz", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_request_limit() {
+        let request_limit = RequestLimit {
+            max_entries: Some(2),
+            max_body_bytes: 1024,
+        };
+        let url = spawn_test_server(mini_tree_router_with_limits(
+            BuildLimit::unlimited(),
+            request_limit,
+        ));
+
+        let entry = |username: &str| {
+            JsonEntry::new(
+                username.to_string(),
+                vec!["100".to_string(), "200".to_string()],
+            )
+        };
+        let client = Client::new();
+        let response = client
+            .post(&url)
+            .json(&vec![entry("alice"), entry("bob")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Too many entries, in a single tree or over the batches
+        let response = client
+            .post(&url)
+            .json(&vec![entry("alice"), entry("bob"), entry("carol")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let error = response.json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.reason, ErrorReason::TooManyEntries);
+        let response = client
+            .post(format!("{}/batch", url))
+            .json(&vec![
+                vec![entry("alice"), entry("bob")],
+                vec![entry("carol")],
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Too large bodies, plain or once decompressed
+        let large_entries = (0..2)
+            .map(|i| entry(&format!("{}{}", "a".repeat(600), i)))
+            .collect::<Vec<_>>();
+        let body = serde_json::to_vec(&large_entries).unwrap();
+        let response = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < 1024);
+        let response = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_ENCODING, "gzip")
+            .body(compressed)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_max_entries_for_memory() {
+        let max_entries = RequestLimit::max_entries_for_memory(100_000_000);
+        assert!(max_entries.is_power_of_two());
+        assert!(largest_tree_memory(max_entries) <= 100_000_000);
+        assert!(largest_tree_memory(2 * max_entries) > 100_000_000);
+        // A tree of the largest requestable shape fits as well
+        assert!(AggregationMerkleSumTree::<4, 16>::estimate_memory(1, max_entries) <= 100_000_000);
+        assert_eq!(RequestLimit::max_entries_for_memory(0), 1);
+    }
+
+    #[test]
+    fn test_read_env_usize() {
+        // A variable of its own, as the tests share the environment of the process
+        let name = "MINI_TREE_TEST_READ_ENV_USIZE";
+        assert_eq!(read_env_usize(name), Ok(None));
+        env::set_var(name, "");
+        assert_eq!(read_env_usize(name), Ok(None));
+        env::set_var(name, "1024");
+        assert_eq!(read_env_usize(name), Ok(Some(1024)));
+        env::set_var(name, "10k");
+        assert_eq!(read_env_usize(name), Err(format!("Invalid {}: 10k", name)));
+        env::remove_var(name);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_route() {
+        let url = spawn_test_server(mini_tree_router());
+
+        let json_entries = load_json_entries("csv/entry_16.csv");
+
+        // The metrics are process-wide, so other tests may add to them concurrently
+        let trees_built = TREE_METRICS.trees_built();
+        let entries_processed = TREE_METRICS.entries_processed();
+        let errors = TREE_METRICS.errors();
+
+        let client = Client::new();
+        client.post(&url).json(&json_entries).send().await.unwrap();
+        client
+            .post(&url)
+            .header(N_CURRENCIES_HEADER, "5")
+            .json(&json_entries)
+            .send()
+            .await
+            .unwrap();
+
+        assert!(TREE_METRICS.trees_built() > trees_built);
+        assert!(TREE_METRICS.entries_processed() >= entries_processed + 16);
+        assert!(TREE_METRICS.errors() > errors);
 
-    Ok((StatusCode::OK, Json(json_tree)))
+        let response = client.get(format!("{}/metrics", url)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("# TYPE mini_tree_trees_built_total counter"));
+        assert!(body.contains("# TYPE mini_tree_build_seconds histogram"));
+    }
 }
@@ -0,0 +1,828 @@
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    sync::{Arc, Mutex},
+};
+use summa_backend::merkle_sum_tree::{utils::parse_csv_to_entries, Cryptocurrency};
+
+#[cfg(feature = "object-store")]
+use super::ObjectStoreReader;
+use crate::json_mst::JsonEntry;
+
+/// EntrySource
+///
+/// Provides the entries of one mini-tree to the Orchestrator. Implementing it for another storage,
+/// e.g. a Parquet file or a database query, lets the Orchestrator aggregate the entries without converting them to CSV first.
+///
+/// The entries are loaded by the distribution threads of the Orchestrator, several sources are loaded in parallel.
+pub trait EntrySource: Send + Sync {
+    /// Identifies the source in logs and progress events, e.g. a file path.
+    fn name(&self) -> String;
+
+    /// Loads the entries of the mini-tree.
+    fn load_entries(&self) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>>;
+
+    /// Counts the entries without loading them, if that is cheaper than loading them.
+    ///
+    /// The count of the first source is used to estimate the memory of the aggregation tree,
+    /// which is skipped if it returns `None`.
+    fn count_entries(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the cryptocurrencies of the balances in their order, if the source knows them,
+    /// e.g. from the header of a CSV file.
+    ///
+    /// The Orchestrator passes them to the `AggregationMerkleSumTree`, and fails if two sources return different ones.
+    fn cryptocurrencies(&self) -> Option<Vec<Cryptocurrency>> {
+        None
+    }
+
+    /// Loads the entries like `load_entries`, failing unless the SHA-256 digest of the data they are read from
+    /// is `expected_sha256`, see `Orchestrator::with_checksums`.
+    ///
+    /// By default, the file named by `name` is verified with `verify_sha256` before the entries are loaded,
+    /// so it is read twice. Sources that can hash their data while loading it should override this.
+    fn load_verified_entries(
+        &self,
+        expected_sha256: &str,
+    ) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>> {
+        verify_sha256(&self.name(), expected_sha256)?;
+        self.load_entries()
+    }
+}
+
+/// The layout of the balances in a CSV file, see `CsvFormat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceColumns {
+    /// One column per currency. Without `names`, all columns following the username are balances.
+    Separate { names: Option<Vec<String>> },
+    /// A single column holding the balances of all currencies, joined by `separator`.
+    /// Without `name`, it is the column following the username.
+    Combined {
+        name: Option<String>,
+        separator: char,
+    },
+}
+
+/// CsvFormat
+///
+/// Describes the CSV files of a `CsvEntrySource`. The files always start with a header row,
+/// which is used to find the columns by their names if these are given.
+///
+/// The default is the format of `summa-backend`: comma separated, the username in the first column
+/// and one balance column per currency after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvFormat {
+    pub delimiter: u8,
+    /// Name of the username column, the first column if not set.
+    pub username_column: Option<String>,
+    pub balances: BalanceColumns,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat {
+            delimiter: b',',
+            username_column: None,
+            balances: BalanceColumns::Separate { names: None },
+        }
+    }
+}
+
+impl CsvFormat {
+    fn reader(
+        &self,
+        path: &str,
+    ) -> Result<csv::Reader<Box<dyn Read + Send>>, Box<dyn Error + Send + Sync>> {
+        Ok(self.reader_from(open_csv(path)?))
+    }
+
+    fn reader_from(&self, reader: Box<dyn Read + Send>) -> csv::Reader<Box<dyn Read + Send>> {
+        csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .from_reader(reader)
+    }
+}
+
+// Whether the path is the URL of an object in S3 or Google Cloud Storage rather than a local file
+fn is_object_url(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("gs://")
+}
+
+// Opens a CSV file, streaming it from object storage if its path is an object URL
+fn open_csv(path: &str) -> Result<Box<dyn Read + Send>, Box<dyn Error + Send + Sync>> {
+    if is_object_url(path) {
+        #[cfg(feature = "object-store")]
+        return Ok(Box::new(ObjectStoreReader::open(path)?));
+        #[cfg(not(feature = "object-store"))]
+        return Err(format!("Reading {:?} requires the `object-store` feature", path).into());
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Checks that the SHA-256 digest of the file at `path` is `expected_sha256`, given in hex like `sha256sum` prints it.
+///
+/// The file is streamed through the hash, so it is never held in memory. Like for `CsvEntrySource`,
+/// the path may be the URL of an object in object storage.
+pub fn verify_sha256(
+    path: &str,
+    expected_sha256: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut open_csv(path)?, &mut hasher)?;
+    check_sha256(path, hasher, expected_sha256)
+}
+
+// Fails if the digest of the bytes fed to `hasher` is not `expected_sha256`
+fn check_sha256(
+    path: &str,
+    hasher: Sha256,
+    expected_sha256: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let sha256 = format!("{:x}", hasher.finalize());
+    if !sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+        return Err(format!(
+            "SHA-256 of {:?} is {}, expected {}",
+            path, sha256, expected_sha256
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Hashes the bytes as they are read, so a file is verified by the same read that parses it
+struct Sha256Reader {
+    inner: Box<dyn Read + Send>,
+    hasher: Arc<Mutex<Sha256>>,
+}
+
+impl Read for Sha256Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.lock().unwrap().update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+// The columns of a CSV file, found in its header by `CsvFormat`
+enum ColumnIndices {
+    Separate {
+        username: usize,
+        // All columns except the username if `None`
+        balances: Option<Vec<usize>>,
+    },
+    Combined {
+        username: usize,
+        balances: usize,
+        separator: char,
+    },
+}
+
+impl ColumnIndices {
+    fn new(
+        format: &CsvFormat,
+        headers: &csv::StringRecord,
+        n_currencies: usize,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let find_column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| format!("Column {:?} not found in the header", name))
+        };
+        let username = match &format.username_column {
+            Some(name) => find_column(name)?,
+            None => 0,
+        };
+
+        match &format.balances {
+            BalanceColumns::Separate { names: None } => Ok(ColumnIndices::Separate {
+                username,
+                balances: None,
+            }),
+            BalanceColumns::Separate { names: Some(names) } => {
+                if names.len() != n_currencies {
+                    return Err(format!(
+                        "Expected {} balance columns, got {}",
+                        n_currencies,
+                        names.len()
+                    )
+                    .into());
+                }
+                let balances = names
+                    .iter()
+                    .map(|name| find_column(name))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                Ok(ColumnIndices::Separate {
+                    username,
+                    balances: Some(balances),
+                })
+            }
+            BalanceColumns::Combined { name, separator } => {
+                let balances = match name {
+                    Some(name) => find_column(name)?,
+                    None => username + 1,
+                };
+                Ok(ColumnIndices::Combined {
+                    username,
+                    balances,
+                    separator: *separator,
+                })
+            }
+        }
+    }
+
+    fn username<'a>(&self, record: &'a csv::StringRecord) -> Option<&'a str> {
+        match self {
+            ColumnIndices::Separate { username, .. } | ColumnIndices::Combined { username, .. } => {
+                record.get(*username)
+            }
+        }
+    }
+
+    fn balances<'a>(&self, record: &'a csv::StringRecord) -> Result<Vec<&'a str>, String> {
+        let column = |index: usize| {
+            record
+                .get(index)
+                .ok_or_else(|| format!("Missing column {}", index))
+        };
+        match self {
+            ColumnIndices::Separate {
+                username,
+                balances: None,
+            } => Ok(record
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| index != username)
+                .map(|(_, balance)| balance)
+                .collect()),
+            ColumnIndices::Separate {
+                balances: Some(balances),
+                ..
+            } => balances.iter().map(|index| column(*index)).collect(),
+            ColumnIndices::Combined {
+                balances,
+                separator,
+                ..
+            } => Ok(column(*balances)?
+                .split(*separator)
+                .map(str::trim)
+                .collect()),
+        }
+    }
+}
+
+/// CsvEntrySource
+///
+/// Loads the entries from a CSV file with the balances of `N_CURRENCIES` currencies in the range of `N_BYTES` bytes.
+/// `Orchestrator::new` creates one for each given CSV file.
+///
+/// A balance that does not fit in `N_BYTES` bytes fails the loading with its line, rather than the aggregation later on.
+///
+/// By default, the file is in the format of `summa-backend` and parsed by its `parse_csv_to_entries`, which holds
+/// all of its entries twice while converting them to `JsonEntry`. For large files, `with_batch_size` parses it
+/// with `CsvEntryBatches` instead, which is also used for files in another format, see `with_format`.
+///
+/// With the `object-store` feature, the path may also be an `s3://<bucket>/<key>` or `gs://<bucket>/<key>` URL.
+/// The object is then streamed by an `ObjectStoreReader` into `CsvEntryBatches`, as `parse_csv_to_entries` only reads local files.
+///
+/// With an expected SHA-256 digest, the file is hashed while `CsvEntryBatches` parses it, see `load_verified_entries`.
+/// It is read only once, so it cannot change between the check and the parsing.
+#[derive(Debug, Clone)]
+pub struct CsvEntrySource<const N_CURRENCIES: usize, const N_BYTES: usize> {
+    path: String,
+    format: CsvFormat,
+    batch_size: Option<usize>,
+}
+
+// The batch size for files in another format than the default, if not set by `with_batch_size`
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> CsvEntrySource<N_CURRENCIES, N_BYTES> {
+    pub fn new(path: String) -> Self {
+        CsvEntrySource {
+            path,
+            format: CsvFormat::default(),
+            batch_size: None,
+        }
+    }
+
+    /// Parses the file in the given format instead of the format of `summa-backend`.
+    pub fn with_format(mut self, format: CsvFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Parses the file in batches of `batch_size` entries, so only the loaded entries and one batch are held in memory.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "Batch size must be greater than 0");
+        self.batch_size = Some(batch_size);
+        self
+    }
+}
+
+impl<const N_CURRENCIES: usize, const N_BYTES: usize> EntrySource
+    for CsvEntrySource<N_CURRENCIES, N_BYTES>
+{
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    fn load_entries(&self) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>> {
+        if self.batch_size.is_some()
+            || self.format != CsvFormat::default()
+            || is_object_url(&self.path)
+        {
+            let batches = CsvEntryBatches::<N_CURRENCIES>::with_format(
+                &self.path,
+                self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+                &self.format,
+            )?
+            .with_balance_bytes(N_BYTES);
+            let mut entries = Vec::new();
+            for batch in batches {
+                entries.extend(batch?);
+            }
+            return Ok(entries);
+        }
+
+        // `parse_csv_to_entries` ignores any balance columns beyond `N_CURRENCIES`,
+        // all rows have as many columns as the header or the CSV reader fails
+        let balance_columns = self
+            .format
+            .reader(&self.path)?
+            .headers()?
+            .len()
+            .saturating_sub(1);
+        if balance_columns != N_CURRENCIES {
+            return Err(format!(
+                "Expected {} balances in line 1 of {:?}, got {}",
+                N_CURRENCIES, self.path, balance_columns
+            )
+            .into());
+        }
+        let (_, entries) = parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>(&self.path)
+            .map_err(|e| format!("Error in {:?}: {}", self.path, e))?;
+        // The entries follow the header, one per line
+        for (index, entry) in entries.iter().enumerate() {
+            let location = format!("line {} of {:?}", index + 2, self.path);
+            for balance in entry.balances() {
+                check_balance_range(balance, N_BYTES, &location)?;
+            }
+        }
+        Ok(entries.iter().map(JsonEntry::from_entry).collect())
+    }
+
+    // The entries are only returned once the whole file is read and its digest matches
+    fn load_verified_entries(
+        &self,
+        expected_sha256: &str,
+    ) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>> {
+        let hasher = Arc::new(Mutex::new(Sha256::new()));
+        let reader = Sha256Reader {
+            inner: open_csv(&self.path)?,
+            hasher: hasher.clone(),
+        };
+        let batches = CsvEntryBatches::<N_CURRENCIES>::from_reader(
+            &self.path,
+            self.format.reader_from(Box::new(reader)),
+            self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            &self.format,
+        )?
+        .with_balance_bytes(N_BYTES);
+        let mut entries = Vec::new();
+        for batch in batches {
+            entries.extend(batch?);
+        }
+
+        let hasher = hasher.lock().unwrap().clone();
+        check_sha256(&self.path, hasher, expected_sha256)?;
+        Ok(entries)
+    }
+
+    // Counts the records without parsing them, an object in object storage is not fetched for this
+    fn count_entries(&self) -> Option<usize> {
+        if is_object_url(&self.path) {
+            return None;
+        }
+        let mut reader = self.format.reader(&self.path).ok()?;
+        let mut count = 0;
+        for record in reader.records() {
+            record.ok()?;
+            count += 1;
+        }
+        Some(count)
+    }
+
+    // Named by the balance column headers in the format of `summa-backend`, i.e. `balance_<name>_<chain>`
+    fn cryptocurrencies(&self) -> Option<Vec<Cryptocurrency>> {
+        if let BalanceColumns::Combined { .. } = self.format.balances {
+            return None;
+        }
+        let mut reader = self.format.reader(&self.path).ok()?;
+        let headers = reader.headers().ok()?;
+        let columns = ColumnIndices::new(&self.format, headers, N_CURRENCIES).ok()?;
+        columns
+            .balances(headers)
+            .ok()?
+            .into_iter()
+            .map(|header| match header.split('_').collect::<Vec<_>>()[..] {
+                ["balance", name, chain] => Some(Cryptocurrency {
+                    name: name.to_string(),
+                    chain: chain.to_string(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// CsvEntryBatches
+///
+/// Parses a CSV file lazily, yielding its entries in batches of up to `batch_size`.
+/// The file is read as the batches are consumed, so the memory used for parsing is bounded by the batch size
+/// rather than by the size of the file. Like for `CsvEntrySource`, the path may be the URL of an object in object storage.
+///
+/// Each row must hold exactly `N_CURRENCIES` balances, otherwise the error names the file, the line and the count.
+/// Each balance is checked to be a non-negative integer, and written in its canonical form like `parse_csv_to_entries` does.
+/// With `with_balance_bytes`, it is also checked to fit in the given number of bytes.
+/// After an error, the iterator yields no more batches.
+pub struct CsvEntryBatches<const N_CURRENCIES: usize> {
+    path: String,
+    records: csv::StringRecordsIntoIter<Box<dyn Read + Send>>,
+    columns: ColumnIndices,
+    batch_size: usize,
+    balance_bytes: Option<usize>,
+    failed: bool,
+}
+
+// Fails if the balance is not below `2^(8 * n_bytes)`, the range of the balances of a tree with `N_BYTES = n_bytes`
+fn check_balance_range(
+    balance: &BigUint,
+    n_bytes: usize,
+    location: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if balance.bits() > 8 * n_bytes as u64 {
+        return Err(format!(
+            "Balance {} in {} does not fit in {} bytes",
+            balance, location, n_bytes
+        )
+        .into());
+    }
+    Ok(())
+}
+
+impl<const N_CURRENCIES: usize> CsvEntryBatches<N_CURRENCIES> {
+    /// Parses a file in the format of `summa-backend`.
+    pub fn new(path: &str, batch_size: usize) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::with_format(path, batch_size, &CsvFormat::default())
+    }
+
+    /// Parses a file in the given format, failing if a named column is not found in its header.
+    pub fn with_format(
+        path: &str,
+        batch_size: usize,
+        format: &CsvFormat,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::from_reader(path, format.reader(path)?, batch_size, format)
+    }
+
+    // Parses the CSV read by `reader`, naming it `path` in the errors
+    fn from_reader(
+        path: &str,
+        mut reader: csv::Reader<Box<dyn Read + Send>>,
+        batch_size: usize,
+        format: &CsvFormat,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        assert!(batch_size > 0, "Batch size must be greater than 0");
+        let columns = ColumnIndices::new(format, reader.headers()?, N_CURRENCIES)?;
+        Ok(CsvEntryBatches {
+            path: path.to_string(),
+            records: reader.into_records(),
+            columns,
+            batch_size,
+            balance_bytes: None,
+            failed: false,
+        })
+    }
+
+    /// Rejects the balances that do not fit in `n_bytes` bytes, naming their line.
+    pub fn with_balance_bytes(mut self, n_bytes: usize) -> Self {
+        self.balance_bytes = Some(n_bytes);
+        self
+    }
+
+    fn parse_record(
+        &self,
+        record: csv::StringRecord,
+    ) -> Result<JsonEntry, Box<dyn Error + Send + Sync>> {
+        // Lines are counted from 1, including the header
+        let location = format!(
+            "line {} of {:?}",
+            record.position().map_or(0, |position| position.line()),
+            self.path
+        );
+        let username = self
+            .columns
+            .username(&record)
+            .ok_or_else(|| format!("Missing username in {}", location))?
+            .to_string();
+        let balances = self
+            .columns
+            .balances(&record)
+            .map_err(|e| format!("{} in {}", e, location))?;
+        if balances.len() != N_CURRENCIES {
+            return Err(format!(
+                "Expected {} balances in {}, got {}",
+                N_CURRENCIES,
+                location,
+                balances.len()
+            )
+            .into());
+        }
+
+        let balances = balances
+            .into_iter()
+            .map(|balance| {
+                let parsed = balance
+                    .parse::<BigUint>()
+                    .map_err(|e| format!("Invalid balance {:?} in {}: {}", balance, location, e))?;
+                if let Some(n_bytes) = self.balance_bytes {
+                    check_balance_range(&parsed, n_bytes, &location)?;
+                }
+                Ok(parsed.to_string())
+            })
+            .collect::<Result<Vec<String>, Box<dyn Error + Send + Sync>>>()?;
+        Ok(JsonEntry::new(username, balances))
+    }
+}
+
+impl<const N_CURRENCIES: usize> Iterator for CsvEntryBatches<N_CURRENCIES> {
+    type Item = Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while batch.len() < self.batch_size {
+            let entry = match self.records.next() {
+                Some(record) => record
+                    .map_err(|e| e.into())
+                    .and_then(|record| self.parse_record(record)),
+                None => break,
+            };
+            match entry {
+                Ok(entry) => batch.push(entry),
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_entry_source() {
+        let source = CsvEntrySource::<2, 14>::new("csv/entry_16.csv".to_string());
+
+        assert_eq!(source.name(), "csv/entry_16.csv");
+        assert_eq!(source.count_entries(), Some(16));
+        assert_eq!(source.load_entries().unwrap().len(), 16);
+
+        let cryptocurrencies = source.cryptocurrencies().unwrap();
+        assert_eq!(cryptocurrencies.len(), 2);
+        assert_eq!(cryptocurrencies[0].name, "ETH");
+        assert_eq!(cryptocurrencies[0].chain, "ETH");
+        assert_eq!(cryptocurrencies[1].name, "USDT");
+        assert_eq!(cryptocurrencies[1].chain, "ETH");
+    }
+
+    #[test]
+    fn test_csv_entry_batches() {
+        let batches = CsvEntryBatches::<2>::new("csv/entry_16.csv", 5)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let batch_sizes: Vec<usize> = batches.iter().map(|batch| batch.len()).collect();
+        assert_eq!(batch_sizes, vec![5, 5, 5, 1]);
+
+        // The batches hold the same entries as the eager parser returns
+        let eager_entries = CsvEntrySource::<2, 14>::new("csv/entry_16.csv".to_string())
+            .load_entries()
+            .unwrap();
+        let batched_entries = CsvEntrySource::<2, 14>::new("csv/entry_16.csv".to_string())
+            .with_batch_size(5)
+            .load_entries()
+            .unwrap();
+        for (eager, batched) in eager_entries
+            .iter()
+            .zip(batches.iter().flatten())
+            .chain(eager_entries.iter().zip(batched_entries.iter()))
+        {
+            assert_eq!(eager.username, batched.username);
+            assert_eq!(eager.balances, batched.balances);
+        }
+        assert_eq!(batched_entries.len(), 16);
+    }
+
+    #[test]
+    fn test_csv_format() {
+        let dir = std::env::temp_dir().join("summa_aggregation_csv_format");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Tab separated, with the balances in one column after an unrelated column
+        let path = dir.join("combined.tsv");
+        std::fs::write(
+            &path,
+            "id\tbalances\tname\n1\t11888, 41163\tdxGaEAii\n2\t67823,18651\tMBlfbBGI\n",
+        )
+        .unwrap();
+        let format = CsvFormat {
+            delimiter: b'\t',
+            username_column: Some("name".to_string()),
+            balances: BalanceColumns::Combined {
+                name: Some("balances".to_string()),
+                separator: ',',
+            },
+        };
+        let entries = CsvEntrySource::<2, 14>::new(path.to_str().unwrap().to_string())
+            .with_format(format.clone())
+            .load_entries()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].username, "dxGaEAii");
+        assert_eq!(entries[0].balances, vec!["11888", "41163"]);
+        assert_eq!(entries[1].balances, vec!["67823", "18651"]);
+        // The combined column does not name the currencies
+        assert!(
+            CsvEntrySource::<2, 14>::new(path.to_str().unwrap().to_string())
+                .with_format(format.clone())
+                .cryptocurrencies()
+                .is_none()
+        );
+
+        // The third line has a single balance
+        let path = dir.join("missing_balance.tsv");
+        std::fs::write(
+            &path,
+            "id\tbalances\tname\n1\t11888,41163\tdxGaEAii\n2\t67823\tMBlfbBGI\n",
+        )
+        .unwrap();
+        let error = CsvEntrySource::<2, 14>::new(path.to_str().unwrap().to_string())
+            .with_format(format)
+            .load_entries()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Expected 2 balances in line 3 of {:?}, got 1",
+                path.to_str().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_format_missing_column() {
+        let format = CsvFormat {
+            balances: BalanceColumns::Separate {
+                names: Some(vec![
+                    "balance_ETH_ETH".to_string(),
+                    "balance_BTC_BTC".to_string(),
+                ]),
+            },
+            ..CsvFormat::default()
+        };
+        let error = CsvEntryBatches::<2>::with_format("csv/entry_16.csv", 16, &format)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Column \"balance_BTC_BTC\" not found in the header"
+        );
+    }
+
+    #[test]
+    fn test_csv_entry_source_balance_count() {
+        // The file holds the balances of 2 currencies
+        let error = CsvEntrySource::<3, 14>::new("csv/entry_16.csv".to_string())
+            .load_entries()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected 3 balances in line 1 of \"csv/entry_16.csv\", got 2"
+        );
+
+        let error = CsvEntrySource::<1, 14>::new("csv/entry_16.csv".to_string())
+            .with_batch_size(4)
+            .load_entries()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expected 1 balances in line 2 of \"csv/entry_16.csv\", got 2"
+        );
+    }
+
+    #[test]
+    fn test_csv_entry_source_balance_range() {
+        let dir = std::env::temp_dir().join("summa_aggregation_balance_range");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 2^16 - 1 fits in 2 bytes, 2^16 in the third line does not
+        let path = dir.join("entries.csv");
+        std::fs::write(
+            &path,
+            "username,balance_ETH_ETH,balance_USDT_ETH\ndxGaEAii,65535,1\nMBlfbBGI,2,65536\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let expected_error = format!(
+            "Balance 65536 in line 3 of {:?} does not fit in 2 bytes",
+            path
+        );
+
+        let error = CsvEntrySource::<2, 2>::new(path.clone())
+            .load_entries()
+            .unwrap_err();
+        assert_eq!(error.to_string(), expected_error);
+        let error = CsvEntrySource::<2, 2>::new(path.clone())
+            .with_batch_size(1)
+            .load_entries()
+            .unwrap_err();
+        assert_eq!(error.to_string(), expected_error);
+
+        // Both fit in 3 bytes
+        let entries = CsvEntrySource::<2, 3>::new(path).load_entries().unwrap();
+        assert_eq!(entries[1].balances, vec!["2", "65536"]);
+    }
+
+    #[cfg(not(feature = "object-store"))]
+    #[test]
+    fn test_csv_entry_source_object_url() {
+        let source = CsvEntrySource::<2, 14>::new("s3://bucket/entry_16.csv".to_string());
+        assert_eq!(source.count_entries(), None);
+        assert_eq!(
+            source.load_entries().unwrap_err().to_string(),
+            "Reading \"s3://bucket/entry_16.csv\" requires the `object-store` feature"
+        );
+    }
+
+    #[test]
+    fn test_verify_sha256() {
+        let sha256 = "956241b8ce6fbb6ef64fb2df67a38a1389bce82a1eba2625c70dc75b8989704b";
+        verify_sha256("csv/entry_16.csv", sha256).unwrap();
+        verify_sha256("csv/entry_16.csv", &sha256.to_uppercase()).unwrap();
+
+        let error = verify_sha256("csv/entry_16_2.csv", sha256).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "SHA-256 of \"csv/entry_16_2.csv\" is {}, expected {}",
+                "9fbd3e0050d6880031b411aa85444d7694ba25e98807ee525dc59c59cc28833c", sha256
+            )
+        );
+        assert!(verify_sha256("csv/no_exist.csv", sha256).is_err());
+
+        // Verified while it is parsed, into the same entries as without a digest
+        let source = CsvEntrySource::<2, 14>::new("csv/entry_16.csv".to_string());
+        let entries = source.load_verified_entries(sha256).unwrap();
+        let expected_entries = source.load_entries().unwrap();
+        assert_eq!(entries.len(), 16);
+        for (entry, expected_entry) in entries.iter().zip(expected_entries.iter()) {
+            assert_eq!(entry.username, expected_entry.username);
+            assert_eq!(entry.balances, expected_entry.balances);
+        }
+        let source = CsvEntrySource::<2, 14>::new("csv/entry_16_2.csv".to_string());
+        assert_eq!(
+            source
+                .load_verified_entries(sha256)
+                .unwrap_err()
+                .to_string(),
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_csv_entry_source_missing_file() {
+        let source = CsvEntrySource::<2, 14>::new("csv/no_exist.csv".to_string());
+
+        assert_eq!(source.count_entries(), None);
+        assert!(source.load_entries().is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{error::Error, fmt};
+
+/// AggregationFailure
+///
+/// The error of `Orchestrator::create_aggregation_mst` once the entry sources are being distributed,
+/// telling which mini-trees were built before the processing failed and which entry sources failed.
+///
+/// The sources are identified by their index, i.e. the position of their mini-tree. With `Orchestrator::with_mini_tree_dir`,
+/// the built mini-trees are kept on disk, so a round can be retried with `Orchestrator::with_resume`
+/// once the failed sources are fixed, building only the mini-trees that are still missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregationFailure {
+    /// The error that stopped the processing.
+    pub error: String,
+    /// Sources whose mini-tree was built, or restored, before the processing stopped.
+    pub built: Vec<usize>,
+    /// Sources that failed to load, or whose task failed on every attempt.
+    pub failed: Vec<SourceFailure>,
+    /// Sources that neither failed nor were built, as the processing stopped before.
+    pub pending: Vec<usize>,
+}
+
+/// SourceFailure
+///
+/// An entry source whose mini-tree could not be built, see `AggregationFailure`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFailure {
+    pub task_id: usize,
+    /// The name of the source, e.g. the path of its CSV file.
+    pub source: String,
+    pub error: String,
+}
+
+impl fmt::Display for AggregationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} of {} mini trees built",
+            self.error,
+            self.built.len(),
+            self.built.len() + self.failed.len() + self.pending.len()
+        )?;
+        if !self.failed.is_empty() {
+            let failed_sources = self
+                .failed
+                .iter()
+                .map(|failure| failure.source.as_str())
+                .collect::<Vec<_>>();
+            write!(f, ", failed: {}", failed_sources.join(", "))?;
+        }
+        if !self.pending.is_empty() {
+            write!(f, ", {} pending", self.pending.len())?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Error for AggregationFailure {}
+
+impl fmt::Display for SourceFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task {} ({}) {}", self.task_id, self.source, self.error)
+    }
+}
+
+impl Error for SourceFailure {}
@@ -1,14 +1,50 @@
+mod entry_source;
+mod failure;
+#[cfg(feature = "object-store")]
+mod object_store_reader;
+mod progress;
+mod round_stats;
+#[cfg(test)]
 mod test;
+mod validation;
 
-use futures::future::join_all;
-use std::{cmp::min, error::Error};
-use summa_backend::merkle_sum_tree::{utils::parse_csv_to_entries, Cryptocurrency, MerkleSumTree};
-use tokio::sync::mpsc;
-use tokio_util::sync::CancellationToken;
+pub use entry_source::{
+    verify_sha256, BalanceColumns, CsvEntryBatches, CsvEntrySource, CsvFormat, EntrySource,
+};
+pub use failure::{AggregationFailure, SourceFailure};
+#[cfg(feature = "object-store")]
+pub use object_store_reader::ObjectStoreReader;
+pub use progress::ProgressEvent;
+pub use round_stats::RoundStats;
+pub use tokio_util::sync::CancellationToken;
+pub use validation::ValidationReport;
 
-use crate::aggregation_merkle_sum_tree::AggregationMerkleSumTree;
-use crate::executor::ExecutorSpawner;
-use crate::json_mst::JsonEntry;
+use num_bigint::BigUint;
+use rayon::prelude::*;
+use std::{
+    cmp::min,
+    collections::{hash_map, BTreeMap, HashMap},
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use summa_backend::merkle_sum_tree::{Cryptocurrency, MerkleSumTree, Tree};
+use sysinfo::System;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::aggregation_merkle_sum_tree::{
+    same_cryptocurrencies, total_balance_overflow, AggregationMerkleSumTree,
+};
+use crate::executor::{Executor, ExecutorError, ExecutorSpawner, SpawnError, StaticSpawner};
+use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
 
 /// The Orchestrator in Summa Aggregation
 ///
@@ -18,20 +54,341 @@ use crate::json_mst::JsonEntry;
 /// Functions include dynamically spawning Executors, managing task distribution,
 /// handling errors and pipeline control, and building the `AggregationMerkleSumTree`
 /// by aggregating mini-trees constructed by the Workers.
+///
+/// By default, all mini-trees are collected before the `AggregationMerkleSumTree` is built.
+/// With `with_incremental_aggregation`, each mini-tree is appended to the tree as soon as it arrives in order instead.
+///
+/// Usernames are checked to be unique over all entry sources, see `with_duplicate_detection`.
+///
+/// With `with_mini_tree_dir`, each mini-tree is also written to disk as it arrives,
+/// and `with_resume` lets a later run pick up the mini-trees of a failed one.
 pub struct Orchestrator<const N_CURRENCIES: usize, const N_BYTES: usize> {
     executor_spawner: Box<dyn ExecutorSpawner>,
-    entry_csvs: Vec<String>,
+    entry_sources: Vec<Arc<dyn EntrySource>>,
+    incremental_aggregation: bool,
+    duplicate_detection: bool,
+    progress_tx: Option<mpsc::Sender<ProgressEvent>>,
+    cancel_token: CancellationToken,
+    max_task_attempts: u32,
+    channel_sizes: ChannelSizes,
+    mini_tree_dir: Option<PathBuf>,
+    resume: bool,
+    executor_weights: Vec<usize>,
+    deadline: Option<Duration>,
+    stall_timeout: Option<Duration>,
+    checksums: Option<HashMap<String, String>>,
+}
+
+// Duplicate usernames listed one by one in a `ValidationReport`, the others are only counted
+const MAX_REPORTED_DUPLICATES: usize = 10;
+
+// The size of the channel buffers unless `CHANNEL_SIZE` is set
+const DEFAULT_CHANNEL_SIZE: usize = 32;
+
+/// Buffer sizes of the channels between the distributors, the executors and the orchestrator.
+///
+/// A buffer that is too small makes each stage wait for the next one, while one that is too large
+/// holds more entry batches or mini-trees in memory than needed, so the best sizes depend on the load.
+///
+/// By default, both sizes are read from the `CHANNEL_SIZE` environment variable, or 32 if it is not set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelSizes {
+    /// Number of loaded entry sources queued for each executor.
+    pub entries_buffer: usize,
+    /// Number of mini-trees of each executor queued until the orchestrator collects them.
+    pub results_buffer: usize,
+}
+
+impl Default for ChannelSizes {
+    fn default() -> Self {
+        let channel_size = std::env::var("CHANNEL_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<usize>().ok())
+            .filter(|size| *size > 0)
+            .unwrap_or(DEFAULT_CHANNEL_SIZE);
+        ChannelSizes {
+            entries_buffer: channel_size,
+            results_buffer: channel_size,
+        }
+    }
+}
+
+const CANCELLED_ERROR: &str = "Aggregation was cancelled";
+
+// Index of the source in `entry_sources` a task was created from, which is also the position of its mini-tree
+type TaskId = usize;
+
+// Estimated bytes a username takes in the `UsernameRegistry`, its key and task plus the heap of the username
+const REGISTERED_USERNAME_BYTES: usize = size_of::<(String, TaskId)>() + 32;
+
+// The entries of a source to build a mini-tree from
+struct Task {
+    id: TaskId,
+    entries: Vec<JsonEntry>,
+    // Number of executors that tried to build the mini-tree so far
+    attempts: u32,
+}
+
+// Usernames loaded so far in the round, with the task they were loaded in
+#[derive(Default)]
+struct UsernameRegistry {
+    tasks_by_username: HashMap<String, TaskId>,
+    // The first username loaded twice, with the tasks it was loaded in
+    duplicate: Option<(String, TaskId, TaskId)>,
+}
+
+impl UsernameRegistry {
+    // Registers the usernames loaded in `task_id`, returns false if one of them was loaded before
+    fn register<'a>(
+        &mut self,
+        task_id: TaskId,
+        usernames: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        for username in usernames {
+            if let Some(first_task_id) =
+                self.tasks_by_username.insert(username.to_string(), task_id)
+            {
+                self.duplicate = Some((username.to_string(), first_task_id, task_id));
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Sent by the executors to the orchestrator for each task they received
+enum TaskResult<const N_CURRENCIES: usize, const N_BYTES: usize> {
+    Built {
+        executor_index: usize,
+        task_id: TaskId,
+        tree: MerkleSumTree<N_CURRENCIES, N_BYTES>,
+        // As reported by the Worker
+        build_duration: Option<Duration>,
+    },
+    // The task failed, or was returned untried by an executor that failed before
+    Failed {
+        executor_index: usize,
+        task: Task,
+        error: String,
+    },
+    // The mini-tree was written by a previous run and read back from disk, see `Orchestrator::with_resume`
+    Restored {
+        task_id: TaskId,
+        tree: MerkleSumTree<N_CURRENCIES, N_BYTES>,
+    },
 }
 
 impl<const N_CURRENCIES: usize, const N_BYTES: usize> Orchestrator<N_CURRENCIES, N_BYTES> {
+    /// Creates an Orchestrator that builds a mini-tree from each of the `entry_csvs` files, in the format of `summa-backend`.
     pub fn new(executor_spawner: Box<dyn ExecutorSpawner>, entry_csvs: Vec<String>) -> Self {
+        Self::from_csvs(executor_spawner, entry_csvs, CsvFormat::default())
+    }
+
+    /// Creates an Orchestrator that builds the mini-trees of the `entry_csvs` files with the given `executors`,
+    /// whose Workers are neither spawned nor terminated by the round, see `StaticSpawner`.
+    pub fn with_executors(executors: Vec<Executor>, entry_csvs: Vec<String>) -> Self {
+        Self::new(Box::new(StaticSpawner::new(executors)), entry_csvs)
+    }
+
+    /// Creates an Orchestrator that builds a mini-tree from each of the `entry_csvs` files, in the given format.
+    pub fn from_csvs(
+        executor_spawner: Box<dyn ExecutorSpawner>,
+        entry_csvs: Vec<String>,
+        csv_format: CsvFormat,
+    ) -> Self {
+        let entry_sources = entry_csvs
+            .into_iter()
+            .map(|path| {
+                Box::new(
+                    CsvEntrySource::<N_CURRENCIES, N_BYTES>::new(path)
+                        .with_format(csv_format.clone()),
+                ) as Box<dyn EntrySource>
+            })
+            .collect();
+        Self::from_entry_sources(executor_spawner, entry_sources)
+    }
+
+    /// Creates an Orchestrator that builds a mini-tree from each of the `entry_sources`, see `EntrySource`.
+    pub fn from_entry_sources(
+        executor_spawner: Box<dyn ExecutorSpawner>,
+        entry_sources: Vec<Box<dyn EntrySource>>,
+    ) -> Self {
         Self {
             executor_spawner,
-            entry_csvs,
+            entry_sources: entry_sources.into_iter().map(Arc::from).collect(),
+            incremental_aggregation: false,
+            duplicate_detection: true,
+            progress_tx: None,
+            cancel_token: CancellationToken::new(),
+            max_task_attempts: 1,
+            channel_sizes: ChannelSizes::default(),
+            mini_tree_dir: None,
+            resume: false,
+            executor_weights: Vec::new(),
+            deadline: None,
+            stall_timeout: None,
+            checksums: None,
         }
     }
 
-    /// Calculate the range of tasks to be assigned to a executor.
+    /// Sets the buffer sizes of the channels, instead of taking them from `CHANNEL_SIZE`.
+    pub fn with_channel_sizes(mut self, channel_sizes: ChannelSizes) -> Self {
+        assert!(
+            channel_sizes.entries_buffer > 0 && channel_sizes.results_buffer > 0,
+            "Channel sizes must be greater than 0"
+        );
+        self.channel_sizes = channel_sizes;
+        self
+    }
+
+    /// Lets up to `max_task_attempts` executors try to build each mini-tree.
+    ///
+    /// If an executor fails on the entries of a source, they are queued again for the other executors,
+    /// and the failed executor takes no more entries. Only when a source has failed `max_task_attempts` times,
+    /// or all executors have failed, the processing is cancelled.
+    ///
+    /// By default, every source is attempted once, i.e. the first failure cancels the processing.
+    pub fn with_task_attempts(mut self, max_task_attempts: u32) -> Self {
+        assert!(
+            max_task_attempts > 0,
+            "Task attempts must be greater than 0"
+        );
+        self.max_task_attempts = max_task_attempts;
+        self
+    }
+
+    /// Lets the caller cancel `create_aggregation_mst`, e.g. on Ctrl-C.
+    ///
+    /// Cancelling `cancel_token` stops spawning executors and distributing entries, terminates the executors
+    /// spawned so far and makes `create_aggregation_mst` return an error, just like an error during the processing does.
+    /// Errors during the processing do not cancel the given token.
+    pub fn with_cancellation_token(mut self, cancel_token: CancellationToken) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Bounds the whole processing of `create_aggregation_mst` by `deadline`, from spawning the executors
+    /// to receiving the last mini-tree.
+    ///
+    /// Once the deadline is exceeded, the processing is cancelled like by `with_cancellation_token`, the executors
+    /// are terminated, and an `AggregationFailure` is returned whose `pending` sources were still outstanding.
+    /// Unlike the request timeout of the Executors, which bounds each request to a Worker, this bounds the round.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Gives up on an executor that produces no mini-tree for `stall_timeout`, e.g. as its Worker hangs
+    /// without closing the connection or its requests are never sent.
+    ///
+    /// The stalled executor takes no more tasks, and its task is queued again for the other executors
+    /// like a failed one, see `with_task_attempts`. The timeout should exceed the time a Worker takes
+    /// to build the largest mini-tree, including the retries of the Executor.
+    pub fn with_stall_timeout(mut self, stall_timeout: Duration) -> Self {
+        self.stall_timeout = Some(stall_timeout);
+        self
+    }
+
+    /// Reports the progress of `create_aggregation_mst` as `ProgressEvent`s to `progress_tx`.
+    ///
+    /// The events are sent as they happen, so a full channel holds up the orchestration.
+    /// Dropping the receiver stops the reporting, not the orchestration.
+    pub fn with_progress(mut self, progress_tx: mpsc::Sender<ProgressEvent>) -> Self {
+        self.progress_tx = Some(progress_tx);
+        self
+    }
+
+    /// Appends each mini-tree to the `AggregationMerkleSumTree` as soon as all mini-trees before it have arrived,
+    /// instead of collecting all of them first.
+    ///
+    /// This caps the peak memory of large rounds, as the collected mini-trees and the aggregation nodes
+    /// are never held twice. Mini-trees arriving ahead of their turn are buffered until the gap is filled.
+    ///
+    /// If the number of mini-trees is not a power of two, the aggregation tree is padded with empty leaves,
+    /// see `AggregationMerkleSumTree::push_mini_tree`.
+    pub fn with_incremental_aggregation(mut self, incremental_aggregation: bool) -> Self {
+        self.incremental_aggregation = incremental_aggregation;
+        self
+    }
+
+    /// Checks that every username appears only once over all entry sources, enabled by default.
+    ///
+    /// Each mini-tree is built independently, so a user in two sources would otherwise get two leaves
+    /// in the `AggregationMerkleSumTree`. The usernames are collected as the sources are loaded,
+    /// and the first duplicate cancels the processing with an error naming both sources.
+    ///
+    /// Disabling the check saves holding all usernames of the round in memory, for rounds whose sources are known to be disjoint.
+    pub fn with_duplicate_detection(mut self, duplicate_detection: bool) -> Self {
+        self.duplicate_detection = duplicate_detection;
+        self
+    }
+
+    /// Writes each mini-tree to `{mini_tree_dir}/{stem}.json` as soon as it arrives, `stem` being the file name
+    /// of its entry source without extension, e.g. `entry_16_1` for `csv/entry_16_1.csv`.
+    ///
+    /// The files hold the `JsonMerkleSumTree` of the mini-tree, whose `source` names the entry source,
+    /// so the mini-trees of a round can be audited, or read back with `JsonMerkleSumTree::to_mst` after a crash.
+    /// The directory is created if needed, and existing files are overwritten.
+    /// Entry sources whose names share a stem fail the processing before any executor is spawned.
+    pub fn with_mini_tree_dir<P: Into<PathBuf>>(mut self, mini_tree_dir: P) -> Self {
+        self.mini_tree_dir = Some(mini_tree_dir.into());
+        self
+    }
+
+    /// Resumes a previous run that wrote its mini-trees with `with_mini_tree_dir`, e.g. one that failed on a worker outage.
+    ///
+    /// The mini-trees found in the directory are read back instead of being built again, so only the remaining
+    /// entry sources are distributed to the executors, before all mini-trees are aggregated as usual.
+    /// Each file is checked before it is used: it must name the same entry source, pass `JsonMerkleSumTree::to_mst`
+    /// for N_CURRENCIES and N_BYTES, have the same cryptocurrencies as the entry sources and, if the source can count them,
+    /// as many entries and the same depth as a mini-tree built from the source now. A file failing any check fails the processing.
+    ///
+    /// Requires `with_mini_tree_dir`.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Verifies the entry sources against a manifest of their expected SHA-256 digests, keyed by the name of the source,
+    /// i.e. the path of its CSV file.
+    ///
+    /// Each listed source is verified as it is loaded, see `EntrySource::load_verified_entries`, and a mismatch fails
+    /// the processing with the path and both digests, so a tampered or truncated file never makes it into the tree.
+    /// A `CsvEntrySource` hashes its file by the same read that parses it.
+    /// Sources missing from the manifest are not verified, while a manifest entry naming no entry source
+    /// fails the processing before any executor is spawned. `validate` verifies the digests as well.
+    pub fn with_checksums(mut self, checksums: HashMap<String, String>) -> Self {
+        self.checksums = Some(checksums);
+        self
+    }
+
+    /// Lets the executor at index `i` work on up to `executor_weights[i]` mini-trees at once, one if not given.
+    ///
+    /// The executors take their tasks from a shared queue as soon as they are idle, so a worker on a stronger node,
+    /// e.g. with twice the CPUs, builds about twice as many mini-trees with a weight of 2, instead of idling
+    /// on a single request at a time. The weights follow the order in which the spawner spawns the executors,
+    /// e.g. the order of the worker URLs of a `CloudSpawner`.
+    pub fn with_executor_weights(mut self, executor_weights: Vec<usize>) -> Self {
+        assert!(
+            executor_weights.iter().all(|weight| *weight > 0),
+            "Executor weights must be greater than 0"
+        );
+        self.executor_weights = executor_weights;
+        self
+    }
+
+    // The number of tasks the executor at `executor_index` works on at once
+    fn executor_weight(&self, executor_index: usize) -> usize {
+        self.executor_weights
+            .get(executor_index)
+            .copied()
+            .unwrap_or(1)
+    }
+
+    /// Calculate the range of tasks to be parsed by the distribution thread of a executor.
+    ///
+    /// The executors take the parsed tasks from a shared queue, so a faster executor builds more mini-trees
+    /// than its own range. The ranges only split the loading of the entry sources.
     ///
     /// Parameters:
     /// * `executor_index` - The index of the executor.
@@ -53,7 +410,7 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Orchestrator<N_CURRENCIES,
         executor_index: usize,
         total_executors: usize,
     ) -> (usize, usize) {
-        let total_tasks = self.entry_csvs.len();
+        let total_tasks = self.entry_sources.len();
         let base_tasks_per_executor = total_tasks / total_executors;
         let extra_tasks = total_tasks % total_executors;
 
@@ -64,133 +421,814 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Orchestrator<N_CURRENCIES,
         (start, min(end, total_tasks))
     }
 
-    /// Processes a list of CSV files concurrently using executors and aggregates the results.
-    /// This involves splitting the CSV files based on available executors, distributing tasks,
+    /// Estimates the memory needed for the `AggregationMerkleSumTree`, plus the usernames held for
+    /// `with_duplicate_detection`, assuming all entry sources hold as many entries as the first one,
+    /// and fails if it exceeds the memory available on this machine.
+    ///
+    /// This turns running out of memory, which gets the process killed, into an error before any work is done.
+    /// The check is skipped if the first source cannot count its entries, e.g. a CSV file in object storage,
+    /// which would have to be downloaded for this, or one that cannot be read, whose error is reported by the distribution instead.
+    fn check_available_memory(&self) -> Result<(), Box<dyn Error>> {
+        let entries_per_tree = match self
+            .entry_sources
+            .first()
+            .and_then(|source| source.count_entries())
+        {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+        let mut estimated_bytes =
+            AggregationMerkleSumTree::<N_CURRENCIES, N_BYTES>::estimate_memory(
+                self.entry_sources.len(),
+                entries_per_tree,
+            );
+        if self.duplicate_detection {
+            estimated_bytes = self
+                .entry_sources
+                .len()
+                .saturating_mul(entries_per_tree)
+                .saturating_mul(REGISTERED_USERNAME_BYTES)
+                .saturating_add(estimated_bytes);
+        }
+
+        let mut system = System::new();
+        system.refresh_memory();
+        let available_bytes = system.available_memory() as usize;
+        info!(
+            mini_trees = self.entry_sources.len(),
+            estimated_mb = estimated_bytes / 1_000_000,
+            available_mb = available_bytes / 1_000_000,
+            "estimated memory for the aggregation tree"
+        );
+
+        // No available memory is reported on unsupported platforms
+        if available_bytes > 0 && estimated_bytes > available_bytes {
+            return Err(format!(
+                "Building the aggregation tree of {} mini trees with {} entries each needs an estimated {} MB, \
+                 but only {} MB of memory is available",
+                self.entry_sources.len(),
+                entries_per_tree,
+                estimated_bytes / 1_000_000,
+                available_bytes / 1_000_000
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fails with the username registered twice, if any, naming the sources it was loaded from.
+    fn check_duplicate_usernames(
+        &self,
+        usernames: &Option<Arc<std::sync::Mutex<UsernameRegistry>>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let duplicate = usernames
+            .as_ref()
+            .and_then(|usernames| usernames.lock().unwrap().duplicate.take());
+        match duplicate {
+            Some((username, first_task_id, task_id)) if first_task_id == task_id => Err(format!(
+                "Username {:?} appears more than once in {:?}",
+                username,
+                self.entry_sources[task_id].name()
+            )
+            .into()),
+            Some((username, first_task_id, task_id)) => Err(format!(
+                "Username {:?} appears in both {:?} and {:?}",
+                username,
+                self.entry_sources[first_task_id].name(),
+                self.entry_sources[task_id].name()
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    // Fails if the manifest of `with_checksums` lists a file that is not an entry source of the round
+    fn check_checksum_manifest(&self) -> Result<(), Box<dyn Error>> {
+        let checksums = match &self.checksums {
+            Some(checksums) => checksums,
+            None => return Ok(()),
+        };
+        let names = self
+            .entry_sources
+            .iter()
+            .map(|entry_source| entry_source.name())
+            .collect::<Vec<_>>();
+        let mut unknown_paths = checksums
+            .keys()
+            .filter(|path| !names.contains(path))
+            .collect::<Vec<_>>();
+        unknown_paths.sort();
+        match unknown_paths.first() {
+            Some(path) => Err(format!(
+                "Checksum given for {:?}, which is not an entry source of the round",
+                path
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    // The SHA-256 digest the entry source of `task_id` is expected to have, if it is listed in the manifest
+    fn expected_sha256(&self, task_id: TaskId) -> Option<String> {
+        self.checksums
+            .as_ref()
+            .and_then(|checksums| checksums.get(&self.entry_sources[task_id].name()))
+            .cloned()
+    }
+
+    /// Returns the cryptocurrencies of the entry sources, see `EntrySource::cryptocurrencies`,
+    /// failing if two sources return different ones or not N_CURRENCIES of them.
+    ///
+    /// Returns `None` if no source knows its cryptocurrencies.
+    fn cryptocurrencies(&self) -> Result<Option<Vec<Cryptocurrency>>, Box<dyn Error>> {
+        Ok(sources_cryptocurrencies(&self.entry_sources, N_CURRENCIES)?)
+    }
+
+    // The given executor count, or the one of the spawner, warning if they differ
+    fn executor_count(&self, executor_count: Option<usize>) -> Result<usize, Box<dyn Error>> {
+        let default_executors = self.executor_spawner.default_executors();
+        match (executor_count, default_executors) {
+            (Some(executor_count), Some(default_executors))
+                if executor_count != default_executors =>
+            {
+                warn!(
+                    executor_count,
+                    default_executors, "executor count differs from the workers of the spawner"
+                );
+                Ok(executor_count)
+            }
+            (Some(executor_count), _) => Ok(executor_count),
+            (None, Some(default_executors)) => Ok(default_executors),
+            (None, None) => Err(
+                "No executor count given, and the spawner is not set up for a number of executors"
+                    .into(),
+            ),
+        }
+    }
+
+    // The file each mini-tree is written to, in the order of the entry sources, if `with_mini_tree_dir` is set
+    fn mini_tree_paths(&self) -> Result<Option<Vec<PathBuf>>, Box<dyn Error>> {
+        let mini_tree_dir = match &self.mini_tree_dir {
+            Some(mini_tree_dir) => mini_tree_dir,
+            None => return Ok(None),
+        };
+        let mut sources_by_stem = HashMap::new();
+        let mut paths = Vec::with_capacity(self.entry_sources.len());
+        for entry_source in &self.entry_sources {
+            let name = entry_source.name();
+            let stem = Path::new(&name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .filter(|stem| !stem.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "Entry source {:?} has no file stem to name its mini tree",
+                        name
+                    )
+                })?;
+            if let Some(first_name) = sources_by_stem.insert(stem.clone(), name.clone()) {
+                return Err(format!(
+                    "Entry sources {:?} and {:?} would both write their mini tree to {}.json",
+                    first_name, name, stem
+                )
+                .into());
+            }
+            paths.push(mini_tree_dir.join(format!("{}.json", stem)));
+        }
+        fs::create_dir_all(mini_tree_dir)?;
+        Ok(Some(paths))
+    }
+
+    // Reads back the mini-trees written to `mini_tree_paths` by a previous run, in the order of the entry sources,
+    // `None` for those without a file
+    fn restore_mini_trees(
+        &self,
+        mini_tree_paths: &[PathBuf],
+        cryptocurrencies: &Option<Vec<Cryptocurrency>>,
+    ) -> Result<Vec<Option<MerkleSumTree<N_CURRENCIES, N_BYTES>>>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let restored_trees = mini_tree_paths
+            .par_iter()
+            .zip(self.entry_sources.par_iter())
+            .map(|(path, entry_source)| {
+                if !path.exists() {
+                    return Ok(None);
+                }
+                Self::restore_mini_tree(path, entry_source.as_ref(), cryptocurrencies)
+                    .map(Some)
+                    .map_err(|e| format!("Cannot resume from {}: {}", path.display(), e))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(restored_trees)
+    }
+
+    fn restore_mini_tree(
+        path: &Path,
+        entry_source: &dyn EntrySource,
+        cryptocurrencies: &Option<Vec<Cryptocurrency>>,
+    ) -> Result<MerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let json_tree: JsonMerkleSumTree =
+            serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let name = entry_source.name();
+        if json_tree.source.as_deref() != Some(name.as_str()) {
+            return Err(format!(
+                "the mini tree was built from {:?}, not from {:?}",
+                json_tree.source, name
+            )
+            .into());
+        }
+        let tree = json_tree.to_mst::<N_CURRENCIES, N_BYTES>()?;
+
+        if let Some(cryptocurrencies) = cryptocurrencies {
+            if !same_cryptocurrencies(tree.cryptocurrencies(), cryptocurrencies) {
+                return Err(
+                    "the cryptocurrencies of the mini tree differ from those of the entry sources"
+                        .into(),
+                );
+            }
+        }
+        if let Some(expected_entries) = entry_source.count_entries() {
+            // Padding entries have empty usernames
+            let entries = tree
+                .entries()
+                .iter()
+                .filter(|entry| !entry.username().is_empty())
+                .count();
+            let expected_depth = expected_entries.next_power_of_two().trailing_zeros() as usize;
+            if entries != expected_entries || *tree.depth() != expected_depth {
+                return Err(format!(
+                    "the mini tree holds {} entries at depth {}, but the entry source holds {} entries, i.e. depth {}",
+                    entries,
+                    tree.depth(),
+                    expected_entries,
+                    expected_depth
+                )
+                .into());
+            }
+        }
+        Ok(tree)
+    }
+
+    // Writes the mini-tree of `task_id` to `path`, through a temporary file so a crash never leaves a truncated tree
+    fn save_mini_tree(
+        &self,
+        task_id: TaskId,
+        tree: &MerkleSumTree<N_CURRENCIES, N_BYTES>,
+        path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let json_tree =
+            JsonMerkleSumTree::from_tree(tree).with_source(self.entry_sources[task_id].name());
+        let temp_path = path.with_extension("json.tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &json_tree)?;
+        writer.flush()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Checks the entry sources of the round without spawning any executor or building any tree,
+    /// so that a broken source or an overflow is found in seconds instead of after the distributed build.
+    ///
+    /// Every source is loaded like the distributors do, e.g. a CSV file is parsed, and its entries are checked to hold
+    /// N_CURRENCIES balances. The cryptocurrencies of the sources must agree, the usernames must be unique unless disabled
+    /// with `with_duplicate_detection`, and the total balance of each currency must lie in the range given by N_BYTES.
+    ///
+    /// Returns the report of the round, or fails with it if it holds any error.
+    pub fn validate(&self) -> Result<ValidationReport, ValidationReport> {
+        let mut errors = Vec::new();
+        let cryptocurrencies = match self.cryptocurrencies() {
+            Ok(cryptocurrencies) => cryptocurrencies.unwrap_or_default(),
+            Err(e) => {
+                errors.push(e.to_string());
+                Vec::new()
+            }
+        };
+
+        if let Err(e) = self.check_checksum_manifest() {
+            errors.push(e.to_string());
+        }
+
+        // The sources are loaded in parallel, keeping only their usernames and total balances.
+        // The errors are converted to strings, as they are not `Send`
+        let expected_sha256s = (0..self.entry_sources.len())
+            .map(|task_id| self.expected_sha256(task_id))
+            .collect::<Vec<_>>();
+        let loaded_sources = self
+            .entry_sources
+            .par_iter()
+            .zip(expected_sha256s)
+            .map(|(entry_source, expected_sha256)| {
+                let json_entries = load_verified(entry_source.as_ref(), expected_sha256.as_deref())
+                    .map_err(|e| {
+                        format!(
+                            "Error while loading entries from {:?}: {}",
+                            entry_source.name(),
+                            e
+                        )
+                    })?;
+                let mut usernames = Vec::with_capacity(json_entries.len());
+                let mut balances = vec![BigUint::from(0_u32); N_CURRENCIES];
+                for json_entry in json_entries {
+                    let entry = json_entry.to_entry::<N_CURRENCIES>().map_err(|e| {
+                        format!(
+                            "Invalid entry {:?} in {:?}: {}",
+                            json_entry.username,
+                            entry_source.name(),
+                            e
+                        )
+                    })?;
+                    for (total, balance) in balances.iter_mut().zip(entry.balances()) {
+                        *total += balance;
+                    }
+                    usernames.push(json_entry.username);
+                }
+                Ok((usernames, balances))
+            })
+            .collect::<Vec<Result<_, String>>>();
+
+        let mut entries_per_source = Vec::with_capacity(loaded_sources.len());
+        let mut total_balances = vec![BigUint::from(0_u32); N_CURRENCIES];
+        let mut sources_by_username = HashMap::new();
+        let mut duplicates = 0;
+        for (task_id, loaded_source) in loaded_sources.into_iter().enumerate() {
+            let (usernames, balances) = match loaded_source {
+                Ok(loaded_source) => loaded_source,
+                Err(e) => {
+                    entries_per_source.push(0);
+                    errors.push(e);
+                    continue;
+                }
+            };
+            entries_per_source.push(usernames.len());
+            for (total, balance) in total_balances.iter_mut().zip(balances) {
+                *total += balance;
+            }
+            if !self.duplicate_detection {
+                continue;
+            }
+            for username in usernames {
+                match sources_by_username.entry(username) {
+                    hash_map::Entry::Vacant(vacant) => {
+                        vacant.insert(task_id);
+                    }
+                    hash_map::Entry::Occupied(occupied) => {
+                        duplicates += 1;
+                        if duplicates > MAX_REPORTED_DUPLICATES {
+                            continue;
+                        }
+                        let first_task_id = *occupied.get();
+                        errors.push(if first_task_id == task_id {
+                            format!(
+                                "Username {:?} appears more than once in {:?}",
+                                occupied.key(),
+                                self.entry_sources[task_id].name()
+                            )
+                        } else {
+                            format!(
+                                "Username {:?} appears in both {:?} and {:?}",
+                                occupied.key(),
+                                self.entry_sources[first_task_id].name(),
+                                self.entry_sources[task_id].name()
+                            )
+                        });
+                    }
+                }
+            }
+        }
+        if duplicates > MAX_REPORTED_DUPLICATES {
+            errors.push(format!(
+                "{} more duplicate usernames",
+                duplicates - MAX_REPORTED_DUPLICATES
+            ));
+        }
+
+        errors.extend(total_balances.iter().enumerate().filter_map(|(i, total)| {
+            total_balance_overflow::<N_BYTES>("Total", i, total, &cryptocurrencies)
+        }));
+
+        let report = ValidationReport {
+            total_entries: entries_per_source.iter().sum(),
+            entries_per_source,
+            total_balances,
+            errors,
+        };
+        if report.is_valid() {
+            Ok(report)
+        } else {
+            Err(report)
+        }
+    }
+
+    /// Processes a list of entry sources, e.g. CSV files, concurrently using executors and aggregates the results.
+    /// This involves splitting the sources based on available executors, distributing tasks,
     /// and aggregating the results into an `AggregationMerkleSumTree`.
     ///
-    /// * `executor_count` - The number of executors to use.\
+    /// * `executor_count` - The number of executors to use, or `None` for the number the spawner is set up for,
+    ///   see `ExecutorSpawner::default_executors`, e.g. the replicas of the service of a `CloudSpawner`.
     ///
     /// Note: After processing, executors are terminated to release resources.
+    /// The same happens if the caller cancels the processing, see `with_cancellation_token`.
+    ///
+    /// Each spawned executor checks that its Worker builds trees of N_CURRENCIES and N_BYTES, see `Executor::check_tree_shape`.
+    ///
+    /// Before spawning any executor, the memory needed for the tree is estimated and compared to the available memory,
+    /// see `check_available_memory`. The cryptocurrencies of the trees are taken from the entry sources
+    /// and sent to the Workers, see `EntrySource::cryptocurrencies`. If no source knows them, they are named "DUMMY".
+    ///
+    /// Unless disabled with `with_duplicate_detection`, a username loaded from more than one source fails the processing.
+    /// Once the sources are being distributed, errors are returned as an `AggregationFailure`,
+    /// listing the mini-trees built so far and the sources that failed.
+    /// With `with_mini_tree_dir`, each mini-tree is written to disk as soon as it is received, before it is aggregated.
+    /// With `with_resume`, the mini-trees written by a previous run are read back, and only the other sources are distributed.
+    /// With `with_checksums`, the files of the sources are verified against their expected SHA-256 digests as they are loaded.
     ///
     /// Data flow
     ///
-    /// 1. Splits the list of CSV files into segments based on the number of available executors.
-    /// 2. A distribution thread loads each CSV file, parses it into `entries`, and sends these to `entries_tx`.
-    /// 3. Each executor receives `entries` from `entries_rx`, requests tasks to Worker, and sends results back through `tree_tx`.
-    /// 4. The processed data from all executors, collected from `tree_rx`, is aggregated into an `AggregationMerkleSumTree`.
+    /// 1. Splits the list of entry sources into segments based on the number of available executors.
+    /// 2. A distribution thread per segment loads the `entries` of each source, and sends these to
+    ///    the shared `task_tx` queue as a task identified by the index of the source.
+    /// 3. Each idle executor takes the next task from `task_rx`, requests tasks to Worker, and sends results back
+    ///    through `result_tx`. A failed task is queued again for the other executors, if `with_task_attempts` allows it.
+    /// 4. The processed data from all executors, collected from `result_rx`, is aggregated into an `AggregationMerkleSumTree`
+    ///    in the order of the task identifiers.
+    ///    With incremental aggregation, each mini-tree is appended to the tree as soon as it is next in order.
     /// 5. After processing, executors are terminated to release resources.
     ///
+    /// Logs with `tracing` in a `create_aggregation_mst` span, within which each executor and distributor has its own span.
     pub async fn create_aggregation_mst(
         self,
-        executor_count: usize,
+        executor_count: impl Into<Option<usize>>,
     ) -> Result<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, Box<dyn Error>>
     where
         [usize; N_CURRENCIES + 1]: Sized,
         [usize; N_CURRENCIES + 2]: Sized,
     {
-        let entries_per_executor = self.entry_csvs.len() / executor_count;
+        self.create_aggregation_mst_with_stats(executor_count)
+            .await
+            .map(|(aggregation_mst, _)| aggregation_mst)
+    }
+
+    /// Like `create_aggregation_mst`, but also returns the `RoundStats` of the round:
+    /// the mini-trees built by each executor, the build time of each mini-tree as reported by its Worker,
+    /// and the time taken by the aggregation and by the whole round.
+    #[tracing::instrument(name = "create_aggregation_mst", skip_all, fields(entry_sources = self.entry_sources.len(), executor_count))]
+    pub async fn create_aggregation_mst_with_stats(
+        self,
+        executor_count: impl Into<Option<usize>>,
+    ) -> Result<(AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>, RoundStats), Box<dyn Error>>
+    where
+        [usize; N_CURRENCIES + 1]: Sized,
+        [usize; N_CURRENCIES + 2]: Sized,
+    {
+        let start = Instant::now();
+        let mut round_stats = RoundStats {
+            build_durations: vec![None; self.entry_sources.len()],
+            ..RoundStats::default()
+        };
+        let executor_count = self.executor_count(executor_count.into())?;
+        tracing::Span::current().record("executor_count", executor_count);
+        self.check_available_memory()?;
+        let mini_tree_paths = self.mini_tree_paths()?;
+        self.check_checksum_manifest()?;
+        // The headers of sources in object storage are fetched, which must not block the executors' tasks
+        let entry_sources = self.entry_sources.clone();
+        let known_cryptocurrencies = tokio::task::spawn_blocking(move || {
+            sources_cryptocurrencies(&entry_sources, N_CURRENCIES)
+        })
+        .await??;
+        let cryptocurrencies = known_cryptocurrencies
+            .clone()
+            .unwrap_or_else(|| dummy_cryptocurrencies(N_CURRENCIES));
+        let restored_trees = match (&mini_tree_paths, self.resume) {
+            (Some(mini_tree_paths), true) => {
+                self.restore_mini_trees(mini_tree_paths, &known_cryptocurrencies)?
+            }
+            (None, true) => {
+                return Err(
+                    "Resuming requires a mini tree directory, see `with_mini_tree_dir`".into(),
+                )
+            }
+            (_, false) => vec![None; self.entry_sources.len()],
+        };
+        // The sources whose mini-trees are restored are not distributed
+        let restored_ids = Arc::new(
+            restored_trees
+                .iter()
+                .map(|tree| tree.is_some())
+                .collect::<Vec<_>>(),
+        );
+        let pending_tasks = restored_ids.iter().filter(|restored| !**restored).count();
+        info!(
+            restored = self.entry_sources.len() - pending_tasks,
+            pending = pending_tasks,
+            "mini trees to build"
+        );
 
         let mut executors = Vec::new();
-        let mut result_collectors = Vec::new();
+        // The URL of each executor, naming it in the logs and errors
+        let mut executor_urls = Vec::new();
 
-        let channel_size = std::env::var("CHANNEL_SIZE")
-            .unwrap_or_default()
-            .parse::<usize>()
-            .unwrap_or(32);
+        // Cancelled by the caller or on errors, the latter do not propagate to the caller's token
+        let cancel_token = self.cancel_token.child_token();
+        let usernames = self
+            .duplicate_detection
+            .then(|| Arc::new(std::sync::Mutex::new(UsernameRegistry::default())));
+        if let Some(usernames) = &usernames {
+            for (task_id, tree) in restored_trees.iter().enumerate() {
+                if let Some(tree) = tree {
+                    let restored_usernames = tree
+                        .entries()
+                        .iter()
+                        .map(|entry| entry.username())
+                        .filter(|username| !username.is_empty());
+                    if !usernames
+                        .lock()
+                        .unwrap()
+                        .register(task_id, restored_usernames)
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        self.check_duplicate_usernames(&usernames)?;
+        // Sources that failed to load, reported in the `AggregationFailure`
+        let failed_sources = Arc::new(std::sync::Mutex::new(Vec::new()));
+        // No executor is needed if every mini-tree was restored
+        let actual_number_of_workers = if pending_tasks == 0 {
+            0
+        } else {
+            min(executor_count, self.entry_sources.len())
+        };
+        if let Some(max_executors) = self.executor_spawner.max_executors() {
+            if actual_number_of_workers > max_executors {
+                return Err(Box::new(SpawnError::Exhausted {
+                    requested: actual_number_of_workers,
+                    available: max_executors,
+                }));
+            }
+        }
 
-        let cancel_token = CancellationToken::new();
-        let actual_number_of_workers = min(executor_count, self.entry_csvs.len());
+        // Declare channels for communication
+        //
+        // - The `task_tx` channel is a queue shared by all executors, each executor takes the next task when it is idle.
+        //   The distribution threads send the parsed entries to it, and the orchestrator sends failed tasks back to it.
+        // - The `result_tx` channel is used by the executors to send the results of the tasks.
+        //
+        // Both buffer as many items as a channel per executor would.
+        let channel_multiplier = actual_number_of_workers.max(1);
+
+        // Cancels the processing once the deadline is exceeded, unless it was cancelled before
+        let deadline_exceeded = Arc::new(AtomicBool::new(false));
+        if let Some(deadline) = self.deadline {
+            let deadline_exceeded = deadline_exceeded.clone();
+            let cancel_token = cancel_token.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = tokio::time::sleep(deadline) => {
+                        warn!(?deadline, "deadline exceeded, cancelling the aggregation");
+                        deadline_exceeded.store(true, Ordering::SeqCst);
+                        cancel_token.cancel();
+                    },
+                    _ = cancel_token.cancelled() => {},
+                }
+            });
+        }
+
+        let (task_tx, task_rx) =
+            mpsc::channel::<Task>(self.channel_sizes.entries_buffer * channel_multiplier);
+        let task_rx = Arc::new(Mutex::new(task_rx));
+        let (result_tx, result_rx) =
+            mpsc::channel(self.channel_sizes.results_buffer * channel_multiplier);
         for i in 0..actual_number_of_workers {
-            // Declare channels for communication
-            //
-            // There are three channels are used inthis method.
-            //
-            // - A `entries_tx` receives parsed data from the entry parser to distribute tasks to executors.
-            // - A `tree_tx` channel is used by the executors to send the results of the tasks.
-            //
-            let (entries_tx, mut entries_rx) = mpsc::channel(channel_size);
-            let (tree_tx, tree_rx) = mpsc::channel(channel_size);
             // Executor
             //
             // Spawn executors that process entries with Worker.
             //
-            // - Receives 'entries' from [entries_rx] channel.
+            // - Takes the next task from the shared [task_rx] channel.
             // - Processes 'entries' to build a merkle sum tree (done by worker).
-            // - Sends the resulting 'tree' back via [tree_tx] channel.
+            // - Sends the resulting 'tree', or the failed task, back via [result_tx] channel.
+            // - Runs as many of these loops as the weight of the executor, see `with_executor_weights`.
             //
-            let executor = self.executor_spawner.spawn_executor().await;
-            result_collectors.push((i, tree_rx));
+            let spawned_executor = tokio::select! {
+                biased;
+                _ = self.cancel_token.cancelled() => {
+                    info!(executor = i, "cancel signal received, stop spawning executors");
+                    self.executor_spawner.terminate_executors().await;
+                    return Err(CANCELLED_ERROR.into());
+                },
+                // Cancelled on an error or the deadline, the collection below reports why
+                _ = cancel_token.cancelled() => {
+                    info!(executor = i, "processing cancelled, stop spawning executors");
+                    break;
+                },
+                spawned_executor = self
+                    .executor_spawner
+                    .spawn_executor()
+                    .instrument(info_span!("spawn_executor", executor = i)) => spawned_executor,
+            };
+            let executor = match spawned_executor {
+                Ok(executor) => executor,
+                Err(e) => {
+                    error!(executor = i, error = %e, "error while spawning executor");
+                    // Stop the executors and distributors spawned so far
+                    cancel_token.cancel();
+                    self.executor_spawner.terminate_executors().await;
+                    return Err(Box::new(e));
+                }
+            };
+            let executor = match &known_cryptocurrencies {
+                Some(cryptocurrencies) => executor.with_cryptocurrencies(cryptocurrencies),
+                None => executor,
+            };
+            // A Worker built for another tree shape fails the processing before it gets any entries,
+            // while an unreachable one is left to fail its first task, which can be retried on other executors
+            match executor.check_tree_shape::<N_CURRENCIES, N_BYTES>().await {
+                Ok(()) => {}
+                Err(e @ ExecutorError::UnsupportedShape { .. }) => {
+                    error!(executor = i, error = %e, "worker cannot build the trees");
+                    cancel_token.cancel();
+                    self.executor_spawner.terminate_executors().await;
+                    return Err(Box::new(e));
+                }
+                Err(e) => {
+                    warn!(executor = i, url = %executor.get_url(), error = %e, "cannot check the tree shape of worker")
+                }
+            }
 
-            let cloned_cancel_token = cancel_token.clone();
-            executors.push(tokio::spawn(async move {
+            let executor_span = info_span!(
+                "executor",
+                executor = i,
+                url = %executor.get_url(),
+                name = executor.get_name().unwrap_or_default()
+            );
+            executor_urls.push(executor.get_url());
+
+            // Set once a task failed on the executor, so that none of its slots takes another task
+            let executor_failed = Arc::new(AtomicBool::new(false));
+            for _ in 0..self.executor_weight(i) {
+                let executor = executor.clone();
+                let executor_failed = executor_failed.clone();
+                let task_rx = task_rx.clone();
+                let result_tx = result_tx.clone();
+                let max_task_attempts = self.max_task_attempts;
+                let stall_timeout = self.stall_timeout;
+                let cloned_cancel_token = cancel_token.clone();
+                executors.push(tokio::spawn(async move {
                         loop {
                             tokio::select! {
-                                entries_data = entries_rx.recv() => {
-                                    // When the distribution thread is finished, the channel will be closed.
-                                    let entries = match entries_data {
-                                        Some(entries) => entries,
+                                task = async { task_rx.lock().await.recv().await } => {
+                                    // When the distribution threads are finished and the orchestrator has collected all trees,
+                                    // the channel will be closed.
+                                    let mut task = match task {
+                                        Some(task) => task,
                                         None => break,
                                     };
-                                    let processed_task = match executor.generate_tree::<N_CURRENCIES, N_BYTES>(entries).await {
-                                        Ok(entries) => entries,
-                                        Err(e) => {
-                                            eprintln!("Executor_{:?}: error while processing entries {:?}", i, e);
-                                            cloned_cancel_token.cancel();
-                                            break;
+                                    // Taken while another slot failed, the task is returned untried
+                                    if executor_failed.load(Ordering::SeqCst) {
+                                        let error = "The executor failed on another task".to_string();
+                                        let _ = result_tx.send(TaskResult::Failed { executor_index: i, task, error }).await;
+                                        break;
+                                    }
+                                    task.attempts += 1;
+                                    // The entries are only kept if the task may be retried
+                                    let entries = if task.attempts < max_task_attempts {
+                                        task.entries.clone()
+                                    } else {
+                                        std::mem::take(&mut task.entries)
+                                    };
+                                    let generated_tree = executor
+                                        .generate_tree_with_build_time::<N_CURRENCIES, N_BYTES>(entries)
+                                        .instrument(info_span!("task", task_id = task.id, attempt = task.attempts));
+                                    // Dropping the request of a stalled executor cancels it
+                                    let generated_tree = match stall_timeout {
+                                        Some(stall_timeout) => match tokio::time::timeout(stall_timeout, generated_tree).await {
+                                            Ok(generated_tree) => generated_tree.map_err(|e| e.to_string()),
+                                            Err(_) => {
+                                                warn!(task_id = task.id, ?stall_timeout, "executor stalled");
+                                                Err(format!("No mini tree was produced within {:?}", stall_timeout))
+                                            }
+                                        },
+                                        None => generated_tree.await.map_err(|e| e.to_string()),
+                                    };
+                                    let (task_result, failed) = match generated_tree {
+                                        Ok((tree, build_duration)) => (TaskResult::Built { executor_index: i, task_id: task.id, tree, build_duration }, false),
+                                        Err(error) => {
+                                            warn!(task_id = task.id, error = %error, "error while processing task");
+                                            executor_failed.store(true, Ordering::SeqCst);
+                                            (TaskResult::Failed { executor_index: i, task, error }, true)
                                         }
                                     };
-                                    if tree_tx.send(processed_task).await.is_err() {
-                                        eprintln!("Executor_{:?}: Error while sending tree result", i);
+                                    if result_tx.send(task_result).await.is_err() {
+                                        error!("error while sending tree result");
                                         cloned_cancel_token.cancel();
                                         break;
                                     }
+                                    // A failed executor takes no more tasks, they are left to the other executors
+                                    if failed {
+                                        break;
+                                    }
                                 },
                                 _ = cloned_cancel_token.cancelled() => {
-                                    eprintln!("Executor_{:?}: cancel signal received, terminating.", i);
+                                    info!("cancel signal received, terminating");
                                     break;
                                 },
                             }
                         }
-            }));
+                }.instrument(executor_span.clone())));
+            }
 
             // Distributing Tasks
             //
-            // Spawn a distribution thread that distributes entries to executors
+            // Spawn a distribution thread that loads a range of the entry sources, so the sources are loaded in parallel
             //
-            // - Loads CSV file from [csv_file_path].
-            // - Parses CSV file into 'entries'.
-            // - Sends 'entries' to executors via [entries_tx] channel.
+            // - Loads 'entries' from [entry_source], e.g. parses a CSV file.
+            // - Sends 'entries' to the shared [task_tx] channel, identified by the index of the source.
             //
             let (start, end) = self.calculate_task_range(i, executor_count);
-            let entry_csvs_slice = self.entry_csvs[start..end].to_vec(); // Clone only the necessary slice
+            // Clone only the necessary slice, without the restored sources
+            let entry_sources_slice = (start..end)
+                .filter(|task_id| !restored_ids[*task_id])
+                .map(|task_id| {
+                    (
+                        task_id,
+                        self.entry_sources[task_id].clone(),
+                        self.expected_sha256(task_id),
+                    )
+                })
+                .collect::<Vec<_>>();
 
+            let entries_tx = task_tx.clone();
             let cloned_cancel_token = cancel_token.clone();
+            let progress_tx = self.progress_tx.clone();
+            let usernames = usernames.clone();
+            let failed_sources = failed_sources.clone();
             tokio::spawn(async move {
-                for file_path in entry_csvs_slice.iter() {
-                    let entries = match parse_csv_to_entries::<_, N_CURRENCIES, N_BYTES>(file_path)
-                    {
-                        Ok((_, entries)) => entries
-                            .iter()
-                            .map(JsonEntry::from_entry)
-                            .collect::<Vec<JsonEntry>>(),
+                for (task_id, entry_source, expected_sha256) in entry_sources_slice {
+                    // Loading reads files or object storage, which must not block the executors' tasks
+                    let loading_source = entry_source.clone();
+                    let loaded = tokio::task::spawn_blocking(move || {
+                        load_verified(loading_source.as_ref(), expected_sha256.as_deref())
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string().into()));
+                    let entries = match loaded {
+                        Ok(entries) => entries,
                         Err(e) => {
-                            eprintln!(
-                                "Executor_{:?}: Error while processing file {:?}: {:?}",
-                                i, file_path, e
-                            );
+                            error!(source = %entry_source.name(), error = %e, "error while loading entries");
+                            failed_sources.lock().unwrap().push(SourceFailure {
+                                task_id,
+                                source: entry_source.name(),
+                                error: format!("failed to load: {}", e),
+                            });
                             cloned_cancel_token.cancel();
                             break;
                         }
                     };
+                    if let Some(usernames) = &usernames {
+                        let loaded_usernames = entries.iter().map(|entry| entry.username.as_str());
+                        if !usernames.lock().unwrap().register(task_id, loaded_usernames) {
+                            error!(source = %entry_source.name(), "duplicate username");
+                            cloned_cancel_token.cancel();
+                            break;
+                        }
+                    }
+                    info!(
+                        task_id,
+                        source = %entry_source.name(),
+                        entries = entries.len(),
+                        "entries loaded"
+                    );
+                    report_progress(
+                        &progress_tx,
+                        ProgressEvent::EntriesLoaded {
+                            task_id,
+                            source: entry_source.name(),
+                            entries: entries.len(),
+                        },
+                    )
+                    .await;
 
+                    let task = Task {
+                        id: task_id,
+                        entries,
+                        attempts: 0,
+                    };
                     tokio::select! {
                         _ = cloned_cancel_token.cancelled() => {
-                            eprintln!("Executor_{:?}: cancel signal received, terminating distributor.", i);
+                            info!("cancel signal received, terminating distributor");
                             break;
                         },
-                        send_entries = entries_tx.send(entries) => {
+                        send_entries = entries_tx.send(task) => {
                             if let Err(e) = send_entries {
-                                eprintln!("Executor_{:?}: Error while sending entries: {:?}", i, e);
+                                error!(task_id = e.0.id, "error while sending entries");
                                 cloned_cancel_token.cancel();
                                 break;
                             }
@@ -198,61 +1236,404 @@ impl<const N_CURRENCIES: usize, const N_BYTES: usize> Orchestrator<N_CURRENCIES,
                     }
                 }
                 drop(entries_tx);
-            });
+            }.instrument(info_span!("distributor", distributor = i)));
         }
 
+        // The restored mini-trees are collected like the built ones, sent from another thread as the channel may be full
+        let restored_tx = result_tx.clone();
+        tokio::spawn(async move {
+            for (task_id, tree) in restored_trees.into_iter().enumerate() {
+                if let Some(tree) = tree {
+                    if restored_tx
+                        .send(TaskResult::Restored { task_id, tree })
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        });
+        drop(result_tx);
+
         // Collecting Results
         //
         // Collect `tree` results from executors
         //
-        //  - Receives processed 'tree' from [tree_rx] channel.
-        //  - Collects all 'tree' results into 'all_tree_results'.
-        //  - Aggregates 'all_tree_results' into 'ordered_tree_results'.
+        //  - Receives processed 'tree' from [result_rx] channel.
+        //  - Puts each 'tree' at the position of its task, or appends it to the tree once it is next in order.
         //
-        let mut all_tree_responses = Vec::new();
-        for (index, mut tree_rx) in result_collectors {
-            let executor_results = tokio::spawn(async move {
-                let mut trees = Vec::new();
-                while let Some(result) = tree_rx.recv().await {
-                    trees.push(result);
-                }
-                (index, trees)
-            });
-            all_tree_responses.push(executor_results);
-        }
+        if self.incremental_aggregation {
+            report_progress(
+                &self.progress_tx,
+                ProgressEvent::AggregationStarted {
+                    mini_trees: self.entry_sources.len(),
+                },
+            )
+            .await;
 
-        let all_tree_results = join_all(all_tree_responses).await;
+            // Trees arriving ahead of their turn wait in `pending_trees`
+            let mut aggregation_mst: Option<AggregationMerkleSumTree<N_CURRENCIES, N_BYTES>> = None;
+            let mut pending_trees = BTreeMap::new();
+            let mut next_position = 0;
+            let mut built = vec![false; self.entry_sources.len()];
+            let mut aggregation_duration = Duration::ZERO;
+            let collected = self
+                .collect_mini_trees(
+                    result_rx,
+                    task_tx,
+                    &executor_urls,
+                    mini_tree_paths.as_deref(),
+                    &cancel_token,
+                    &mut round_stats,
+                    |task_id, tree| {
+                        built[task_id] = true;
+                        pending_trees.insert(task_id, tree);
+                        let aggregation_start = Instant::now();
+                        while let Some(tree) = pending_trees.remove(&next_position) {
+                            match aggregation_mst.as_mut() {
+                                Some(aggregation_mst) => aggregation_mst.push_mini_tree(tree)?,
+                                None => {
+                                    aggregation_mst = Some(AggregationMerkleSumTree::new(
+                                        vec![tree],
+                                        cryptocurrencies.clone(),
+                                    )?)
+                                }
+                            }
+                            next_position += 1;
+                        }
+                        aggregation_duration += aggregation_start.elapsed();
+                        Ok(())
+                    },
+                )
+                .await;
 
-        // Aggregate results from all workers in order
-        let mut ordered_tree_results = vec![None; self.entry_csvs.len()];
-        for result in all_tree_results {
-            let (index, worker_results) = result.unwrap();
-            let start = index * entries_per_executor;
-            for (i, res) in worker_results.iter().enumerate() {
-                ordered_tree_results[start + i] = Some(res.clone());
-            }
+            // Stop the executors still running if the aggregation failed
+            cancel_token.cancel();
+            self.executor_spawner.terminate_executors().await;
+            self.check_round(collected, &usernames, &deadline_exceeded)
+                .map_err(|e| self.aggregation_failure(e, &built, &failed_sources))?;
+            let aggregation_mst = aggregation_mst
+                .ok_or_else(|| Box::<dyn Error>::from("No mini tree was generated"))?;
+            round_stats.aggregation_duration = aggregation_duration;
+            round_stats.total_duration = start.elapsed();
+            return Ok((aggregation_mst, round_stats));
         }
 
+        let mut ordered_tree_results = vec![None; self.entry_sources.len()];
+        let collected = self
+            .collect_mini_trees(
+                result_rx,
+                task_tx,
+                &executor_urls,
+                mini_tree_paths.as_deref(),
+                &cancel_token,
+                &mut round_stats,
+                |task_id, tree| {
+                    ordered_tree_results[task_id] = Some(tree);
+                    Ok(())
+                },
+            )
+            .await;
+
         // Terminate executors
+        cancel_token.cancel();
         self.executor_spawner.terminate_executors().await;
+        self.check_round(collected, &usernames, &deadline_exceeded)
+            .map_err(|e| {
+                let built = ordered_tree_results
+                    .iter()
+                    .map(Option::is_some)
+                    .collect::<Vec<_>>();
+                self.aggregation_failure(e, &built, &failed_sources)
+            })?;
 
         let all_merkle_sum_tree: Vec<MerkleSumTree<N_CURRENCIES, N_BYTES>> =
             ordered_tree_results.into_iter().flatten().collect();
 
-        // Occur error if the number of mini_tree in 'all_merkle_sum_tree' is not equal to the number of entry_csvs.
-        if all_merkle_sum_tree.len() != self.entry_csvs.len() {
+        report_progress(
+            &self.progress_tx,
+            ProgressEvent::AggregationStarted {
+                mini_trees: all_merkle_sum_tree.len(),
+            },
+        )
+        .await;
+        let aggregation_start = Instant::now();
+        let aggregation_mst = AggregationMerkleSumTree::new(all_merkle_sum_tree, cryptocurrencies)?;
+        round_stats.aggregation_duration = aggregation_start.elapsed();
+        round_stats.total_duration = start.elapsed();
+        Ok((aggregation_mst, round_stats))
+    }
+
+    // The error of the round once the mini-trees are collected: a cancellation by the caller first, then the deadline,
+    // then a duplicate username, whose loading cancelled the processing, and only then the error of the collection
+    fn check_round(
+        &self,
+        collected: Result<(), Box<dyn Error>>,
+        usernames: &Option<Arc<std::sync::Mutex<UsernameRegistry>>>,
+        deadline_exceeded: &AtomicBool,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.cancel_token.is_cancelled() {
+            return Err(CANCELLED_ERROR.into());
+        }
+        if deadline_exceeded.load(Ordering::SeqCst) {
+            return Err(format!(
+                "Aggregation exceeded its deadline of {:?}",
+                self.deadline.unwrap_or_default()
+            )
+            .into());
+        }
+        self.check_duplicate_usernames(usernames)?;
+        collected
+    }
+
+    // Wraps the error of the round with the mini-trees `built` so far and the sources that failed
+    fn aggregation_failure(
+        &self,
+        error: Box<dyn Error>,
+        built: &[bool],
+        failed_sources: &std::sync::Mutex<Vec<SourceFailure>>,
+    ) -> Box<dyn Error> {
+        let mut failed = std::mem::take(&mut *failed_sources.lock().unwrap());
+        if let Some(failure) = error.downcast_ref::<SourceFailure>() {
+            failed.push(failure.clone());
+        }
+        failed.sort_by_key(|failure| failure.task_id);
+
+        let (built_ids, unbuilt_ids): (Vec<TaskId>, Vec<TaskId>) =
+            (0..self.entry_sources.len()).partition(|task_id| built[*task_id]);
+        let pending = unbuilt_ids
+            .into_iter()
+            .filter(|task_id| failed.iter().all(|failure| failure.task_id != *task_id))
+            .collect();
+        Box::new(AggregationFailure {
+            error: error.to_string(),
+            built: built_ids,
+            failed,
+            pending,
+        })
+    }
+
+    /// Receives the results of the tasks from the executors and passes each tree to `on_tree` with its task identifier,
+    /// until a tree has been received for every entry source.
+    ///
+    /// A failed task is sent back to the shared queue through `task_tx` to be taken by another executor,
+    /// as long as it was attempted less than `max_task_attempts` times. The failed executor takes no more tasks,
+    /// so the processing is cancelled once all executors have failed, with an error naming the URL of the last one.
+    ///
+    /// If `mini_tree_paths` are given, each tree is written to the path of its task before it is passed on.
+    /// The number of trees built by each executor and their build times are recorded in `round_stats`.
+    ///
+    /// A task that cannot be retried fails the collection with a `SourceFailure`.
+    ///
+    /// Dropping `task_tx` on return lets the executors finish once the distributors are done.
+    #[allow(clippy::too_many_arguments)]
+    async fn collect_mini_trees(
+        &self,
+        mut result_rx: mpsc::Receiver<TaskResult<N_CURRENCIES, N_BYTES>>,
+        task_tx: mpsc::Sender<Task>,
+        executor_urls: &[String],
+        mini_tree_paths: Option<&[PathBuf]>,
+        cancel_token: &CancellationToken,
+        round_stats: &mut RoundStats,
+        mut on_tree: impl FnMut(
+            TaskId,
+            MerkleSumTree<N_CURRENCIES, N_BYTES>,
+        ) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut received_trees = vec![0; executor_urls.len()];
+        // An executor may report several failed tasks, one per slot, see `with_executor_weights`
+        let mut failed_executors = vec![false; executor_urls.len()];
+        let mut completed_tasks = 0;
+        while completed_tasks < self.entry_sources.len() {
+            let task_result = tokio::select! {
+                task_result = result_rx.recv() => task_result,
+                _ = cancel_token.cancelled() => None,
+            };
+            match task_result {
+                Some(TaskResult::Built {
+                    executor_index,
+                    task_id,
+                    tree,
+                    build_duration,
+                }) => {
+                    received_trees[executor_index] += 1;
+                    round_stats.build_durations[task_id] = build_duration;
+                    report_progress(
+                        &self.progress_tx,
+                        ProgressEvent::MiniTreeReceived {
+                            executor_index,
+                            received: received_trees[executor_index],
+                        },
+                    )
+                    .await;
+                    if let Some(mini_tree_paths) = mini_tree_paths {
+                        let path = &mini_tree_paths[task_id];
+                        self.save_mini_tree(task_id, &tree, path).map_err(|e| {
+                            format!(
+                                "Failed to write mini tree {} to {}: {}",
+                                task_id,
+                                path.display(),
+                                e
+                            )
+                        })?;
+                        info!(task_id, path = %path.display(), "mini tree written");
+                    }
+                    on_tree(task_id, tree)?;
+                    completed_tasks += 1;
+                }
+                Some(TaskResult::Failed {
+                    executor_index,
+                    task,
+                    error,
+                }) => {
+                    failed_executors[executor_index] = true;
+                    let url = &executor_urls[executor_index];
+                    if task.attempts >= self.max_task_attempts {
+                        error!(
+                            executor = executor_index,
+                            url = %url,
+                            task_id = task.id,
+                            attempts = task.attempts,
+                            "task failed on every attempt"
+                        );
+                        cancel_token.cancel();
+                        return Err(Box::new(SourceFailure {
+                            task_id: task.id,
+                            source: self.entry_sources[task.id].name(),
+                            error: format!(
+                                "failed after {} attempts, last on the executor at {}: {}",
+                                task.attempts, url, error
+                            ),
+                        }));
+                    }
+                    if failed_executors.iter().all(|failed| *failed) {
+                        error!(
+                            executor = executor_index,
+                            url = %url,
+                            task_id = task.id,
+                            "no executor left to retry task"
+                        );
+                        cancel_token.cancel();
+                        return Err(Box::new(SourceFailure {
+                            task_id: task.id,
+                            source: self.entry_sources[task.id].name(),
+                            error: format!(
+                                "failed on the executor at {}, no executor left to retry it: {}",
+                                url, error
+                            ),
+                        }));
+                    }
+
+                    warn!(
+                        executor = executor_index,
+                        url = %url,
+                        task_id = task.id,
+                        "retrying task on another executor"
+                    );
+                    report_progress(
+                        &self.progress_tx,
+                        ProgressEvent::TaskRetried {
+                            task_id: task.id,
+                            failed_executor: executor_index,
+                        },
+                    )
+                    .await;
+
+                    // Sent from another thread, as the queue may be full while the executors wait for their results
+                    // to be received here
+                    let task_tx = task_tx.clone();
+                    tokio::spawn(async move {
+                        let _ = task_tx.send(task).await;
+                    });
+                }
+                Some(TaskResult::Restored { task_id, tree }) => {
+                    report_progress(
+                        &self.progress_tx,
+                        ProgressEvent::MiniTreeRestored {
+                            task_id,
+                            source: self.entry_sources[task_id].name(),
+                        },
+                    )
+                    .await;
+                    on_tree(task_id, tree)?;
+                    completed_tasks += 1;
+                }
+                None => break,
+            }
+        }
+
+        // Occur error if the number of mini_tree is not equal to the number of entry_sources.
+        if completed_tasks != self.entry_sources.len() {
             return Err("Mismatch in generated mini tree counts and given CSV counts".into());
         }
+        round_stats.chunks_per_executor = received_trees;
+        Ok(())
+    }
+}
 
-        AggregationMerkleSumTree::new(
-            all_merkle_sum_tree,
-            vec![
-                Cryptocurrency {
-                    name: "DUMMY".to_string(),
-                    chain: "ETH".to_string(),
-                };
-                N_CURRENCIES
-            ],
-        )
+// Sends the event if progress is reported, a dropped receiver is ignored
+async fn report_progress(progress_tx: &Option<mpsc::Sender<ProgressEvent>>, event: ProgressEvent) {
+    if let Some(progress_tx) = progress_tx {
+        let _ = progress_tx.send(event).await;
+    }
+}
+
+// The cryptocurrencies of a tree whose entry sources do not know them
+fn dummy_cryptocurrencies(n_currencies: usize) -> Vec<Cryptocurrency> {
+    vec![
+        Cryptocurrency {
+            name: "DUMMY".to_string(),
+            chain: "ETH".to_string(),
+        };
+        n_currencies
+    ]
+}
+
+// The cryptocurrencies of `Orchestrator::cryptocurrencies`, which may read the sources, e.g. the header of a CSV file
+fn sources_cryptocurrencies(
+    entry_sources: &[Arc<dyn EntrySource>],
+    n_currencies: usize,
+) -> Result<Option<Vec<Cryptocurrency>>, String> {
+    let mut first: Option<(String, Vec<Cryptocurrency>)> = None;
+    for entry_source in entry_sources {
+        let cryptocurrencies = match entry_source.cryptocurrencies() {
+            Some(cryptocurrencies) => cryptocurrencies,
+            None => continue,
+        };
+        match &first {
+            Some((first_name, first_cryptocurrencies)) => {
+                if !same_cryptocurrencies(&cryptocurrencies, first_cryptocurrencies) {
+                    return Err(format!(
+                        "Cryptocurrencies of {:?} differ from those of {:?}",
+                        entry_source.name(),
+                        first_name
+                    ));
+                }
+            }
+            None => {
+                if cryptocurrencies.len() != n_currencies {
+                    return Err(format!(
+                        "Expected {} cryptocurrencies in {:?}, got {}",
+                        n_currencies,
+                        entry_source.name(),
+                        cryptocurrencies.len()
+                    ));
+                }
+                first = Some((entry_source.name(), cryptocurrencies));
+            }
+        }
+    }
+    Ok(first.map(|(_, cryptocurrencies)| cryptocurrencies))
+}
+
+// Loads the entries of the source, verifying the SHA-256 digest of its file if one is expected
+fn load_verified(
+    entry_source: &dyn EntrySource,
+    expected_sha256: Option<&str>,
+) -> Result<Vec<JsonEntry>, Box<dyn Error + Send + Sync>> {
+    match expected_sha256 {
+        Some(expected_sha256) => entry_source.load_verified_entries(expected_sha256),
+        None => entry_source.load_entries(),
     }
 }
@@ -0,0 +1,169 @@
+use futures::StreamExt;
+use object_store::{aws::AmazonS3Builder, gcp::GoogleCloudStorageBuilder, path::Path, ObjectStore};
+use std::{
+    error::Error,
+    io::{self, Cursor, Read},
+    sync::mpsc,
+    thread,
+};
+
+// The chunks fetched ahead of the reader, bounding the memory of an object that is read slowly
+const PREFETCHED_CHUNKS: usize = 16;
+
+/// ObjectStoreReader
+///
+/// Streams an object from S3 (`s3://<bucket>/<key>`) or Google Cloud Storage (`gs://<bucket>/<key>`),
+/// e.g. a CSV file of a `CsvEntrySource`. It is only available with the `object-store` feature.
+///
+/// The credentials and the region are read from the environment, see `AmazonS3Builder::from_env`
+/// and `GoogleCloudStorageBuilder::from_env` of the `object_store` crate.
+///
+/// The object is fetched by a thread with its own runtime, so it can be read from async tasks as well as from other threads.
+/// Only a few chunks are fetched ahead of the reader, the object is never held in memory as a whole.
+pub struct ObjectStoreReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl ObjectStoreReader {
+    /// Starts fetching the object at `url`, failing if the URL is invalid or the object cannot be fetched.
+    pub fn open(url: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (store, location) = parse_object_url(url)?;
+        Self::from_store(store, location)
+            .map_err(|e| format!("Cannot fetch {:?}: {}", url, e).into())
+    }
+
+    fn from_store(store: Box<dyn ObjectStore>, location: Path) -> Result<Self, String> {
+        let (opened_tx, opened_rx) = mpsc::sync_channel(1);
+        let (chunks_tx, chunks_rx) = mpsc::sync_channel(PREFETCHED_CHUNKS);
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let mut stream = match store.get(&location).await {
+                    Ok(result) => {
+                        let _ = opened_tx.send(Ok(()));
+                        result.into_stream()
+                    }
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(e.to_string()));
+                        return;
+                    }
+                };
+                while let Some(chunk) = stream.next().await {
+                    let chunk = chunk
+                        .map(|bytes| bytes.to_vec())
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+                    let failed = chunk.is_err();
+                    // Stops if the reader was dropped
+                    if chunks_tx.send(chunk).is_err() || failed {
+                        return;
+                    }
+                }
+            });
+        });
+
+        opened_rx
+            .recv()
+            .map_err(|_| "the fetching thread stopped".to_string())??;
+        Ok(ObjectStoreReader {
+            chunks: chunks_rx,
+            chunk: Cursor::new(Vec::new()),
+        })
+    }
+}
+
+impl Read for ObjectStoreReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.chunk.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.chunks.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk?),
+                // The object was read to its end
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+// The store of the bucket and the location of the key in it
+fn parse_object_url(
+    url: &str,
+) -> Result<(Box<dyn ObjectStore>, Path), Box<dyn Error + Send + Sync>> {
+    let invalid_url = || {
+        format!(
+            "Invalid object URL {:?}, expected <scheme>://<bucket>/<key>",
+            url
+        )
+    };
+    let (scheme, bucket_and_key) = url.split_once("://").ok_or_else(invalid_url)?;
+    let (bucket, key) = bucket_and_key
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(invalid_url)?;
+
+    let store: Box<dyn ObjectStore> = match scheme {
+        "s3" => Box::new(
+            AmazonS3Builder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        "gs" => Box::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_bucket_name(bucket)
+                .build()?,
+        ),
+        _ => return Err(format!("Unsupported object store {:?} in {:?}", scheme, url).into()),
+    };
+    Ok((store, Path::from(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn test_object_store_reader() {
+        let csv = "username,balance_ETH_ETH,balance_USDT_ETH\ndxGaEAii,11888,41163\n";
+        let store = InMemory::new();
+        let location = Path::from("round/entry_1.csv");
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(store.put(&location, csv.into()))
+            .unwrap();
+
+        let mut reader = ObjectStoreReader::from_store(Box::new(store), location).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, csv);
+
+        let missing =
+            ObjectStoreReader::from_store(Box::new(InMemory::new()), Path::from("missing.csv"));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_parse_object_url() {
+        for url in ["s3://bucket", "s3:///key.csv", "bucket/key.csv"] {
+            let error = parse_object_url(url).err().unwrap();
+            assert!(error.to_string().starts_with("Invalid object URL"));
+        }
+        let error = parse_object_url("ftp://bucket/key.csv").err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported object store \"ftp\" in \"ftp://bucket/key.csv\""
+        );
+    }
+}
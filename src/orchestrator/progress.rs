@@ -0,0 +1,32 @@
+/// ProgressEvent
+///
+/// Reported by the Orchestrator while building an `AggregationMerkleSumTree`, see `Orchestrator::with_progress`.
+///
+/// Counting `MiniTreeReceived` against the number of entry sources gives the overall progress,
+/// while `received` per executor shows if one of them lags behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// The entries of the source at index `task_id` were loaded, e.g. a CSV file was parsed,
+    /// and are about to be queued for the executors.
+    EntriesLoaded {
+        task_id: usize,
+        source: String,
+        entries: usize,
+    },
+    /// A mini-tree was received from the executor, `received` counts its mini-trees so far.
+    MiniTreeReceived {
+        executor_index: usize,
+        received: usize,
+    },
+    /// The mini-tree of the source at index `task_id` was written by a previous run and read back from disk,
+    /// instead of being built by an executor, see `Orchestrator::with_resume`.
+    MiniTreeRestored { task_id: usize, source: String },
+    /// The mini-tree of the source at index `task_id` failed on `failed_executor` and is queued again
+    /// for the other executors, see `Orchestrator::with_task_attempts`.
+    TaskRetried {
+        task_id: usize,
+        failed_executor: usize,
+    },
+    /// The mini-trees are being aggregated. In incremental mode, this is reported before the first mini-tree arrives.
+    AggregationStarted { mini_trees: usize },
+}
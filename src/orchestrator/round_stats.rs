@@ -0,0 +1,43 @@
+use std::{fmt, time::Duration};
+
+/// RoundStats
+///
+/// Counts and timings of a round, returned by `Orchestrator::create_aggregation_mst_with_stats`,
+/// e.g. for sizing the Workers: a long build time per mini-tree calls for stronger Workers,
+/// while an uneven number of mini-trees per executor shows that some Workers lag behind.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoundStats {
+    /// Number of mini-trees built by each executor, in the order they were spawned.
+    pub chunks_per_executor: Vec<usize>,
+    /// Time the Worker took to build each mini-tree, in the order of the entry sources, see `JsonMerkleSumTree::build_ms`.
+    /// `None` for a mini-tree that was restored from disk, or built by a Worker that does not report it.
+    pub build_durations: Vec<Option<Duration>>,
+    /// Time spent aggregating the mini-trees into the `AggregationMerkleSumTree`.
+    /// With incremental aggregation, the time spent appending each mini-tree as it arrived.
+    pub aggregation_duration: Duration,
+    /// Wall time of the whole round, from checking the entry sources to the aggregated tree.
+    pub total_duration: Duration,
+}
+
+impl fmt::Display for RoundStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reported_builds = self.build_durations.iter().flatten().count();
+        write!(
+            f,
+            "{} mini trees by {} executors in {:?}, aggregated in {:?}",
+            self.build_durations.len(),
+            self.chunks_per_executor.len(),
+            self.total_duration,
+            self.aggregation_duration
+        )?;
+        if reported_builds > 0 {
+            let total_build_duration = self.build_durations.iter().flatten().sum::<Duration>();
+            write!(
+                f,
+                ", {:?} per mini tree built on average",
+                total_build_duration / reported_builds as u32
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,65 @@
 #![allow(unused_imports)]
-use crate::executor::{CloudSpawner, LocalSpawner, MockSpawner};
-use crate::orchestrator::Orchestrator;
-use summa_backend::merkle_sum_tree::Tree;
+use axum::{body::Body, middleware::Next};
+use num_bigint::BigUint;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::TcpListener,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::executor::{
+    CloudSpawner, Executor, ExecutorSpawner, InProcessSpawner, LocalSpawner, MockSpawner,
+    SpawnError,
+};
+use crate::json_mst::{JsonEntry, JsonMerkleSumTree};
+use crate::mini_tree_generator::mini_tree_router;
+use crate::orchestrator::{
+    AggregationFailure, CancellationToken, ChannelSizes, CsvEntrySource, EntrySource, Orchestrator,
+    ProgressEvent,
+};
+use summa_backend::merkle_sum_tree::{utils::parse_csv_to_entries, Tree};
+
+// Spawns a Worker that delays every tree by `delay` for the first Executor, and regular Workers for the others
+struct HeterogeneousSpawner {
+    delay: Duration,
+    worker_counter: AtomicUsize,
+}
+
+impl ExecutorSpawner for HeterogeneousSpawner {
+    fn spawn_executor(&self) -> Pin<Box<dyn Future<Output = Result<Executor, SpawnError>> + Send>> {
+        let mut router = mini_tree_router();
+        if self.worker_counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            let delay = self.delay;
+            router = router.layer(axum::middleware::from_fn(
+                move |request: axum::http::Request<Body>, next: Next<Body>| async move {
+                    if request.method() == axum::http::Method::POST {
+                        sleep(delay).await;
+                    }
+                    next.run(request).await
+                },
+            ));
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+        Box::pin(async move { Ok(Executor::new(format!("http://{}", addr), None)) })
+    }
+
+    fn terminate_executors(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {})
+    }
+}
 
 #[tokio::test]
 async fn test_single_mock_worker() {
@@ -20,6 +78,351 @@ async fn test_single_mock_worker() {
     assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(1).entries().len());
 }
 
+#[tokio::test]
+async fn test_with_executors() {
+    // A fleet of two workers, reused over several rounds
+    let spawner = MockSpawner::new(None);
+    let executors = vec![
+        spawner.spawn_executor().await.unwrap(),
+        spawner.spawn_executor().await.unwrap(),
+    ];
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+    ];
+
+    let mut roots = Vec::new();
+    for _ in 0..2 {
+        let orchestrator =
+            Orchestrator::<2, 14>::with_executors(executors.clone(), entry_csvs.clone());
+        let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(None).await.unwrap();
+        roots.push(aggregation_merkle_sum_tree.root().hash);
+    }
+    assert_eq!(roots[0], roots[1]);
+    for executor in &executors {
+        assert!(executor.health_check().await.is_ok());
+    }
+
+    // No more executors than given can be used
+    let orchestrator = Orchestrator::<2, 14>::with_executors(executors, entry_csvs);
+    let err = orchestrator.create_aggregation_mst(3).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SpawnError>(),
+        Some(SpawnError::Exhausted {
+            requested: 3,
+            available: 2
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_in_process_spawner() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+    ];
+    let in_process_tree =
+        Orchestrator::<2, 14>::new(Box::new(InProcessSpawner::new()), entry_csvs.clone())
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+    let mock_tree = Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+        .create_aggregation_mst(2)
+        .await
+        .unwrap();
+
+    assert_eq!(in_process_tree.root().hash, mock_tree.root().hash);
+    assert_eq!(in_process_tree.root().balances, mock_tree.root().balances);
+}
+
+#[tokio::test]
+async fn test_uneven_task_ranges() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+        "csv/entry_16_4.csv".to_string(),
+    ];
+    let orchestrator =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone());
+
+    // The first executor gets two CSV files, the others one each
+    assert_eq!(orchestrator.calculate_task_range(0, 3), (0, 2));
+    assert_eq!(orchestrator.calculate_task_range(1, 3), (2, 3));
+    assert_eq!(orchestrator.calculate_task_range(2, 3), (3, 4));
+
+    let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(3).await.unwrap();
+
+    // Each mini-tree is at the position of its CSV file
+    for (i, entry_csv) in entry_csvs.iter().enumerate() {
+        let (_, entries) = parse_csv_to_entries::<_, 2, 14>(entry_csv).unwrap();
+        assert_eq!(
+            aggregation_merkle_sum_tree.mini_tree(i).entries()[0].username(),
+            entries[0].username()
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_channel_sizes() {
+    // Single slot buffers make the distributors, executors and the orchestrator wait for each other
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+            "csv/entry_16_3.csv".to_string(),
+            "csv/entry_16_4.csv".to_string(),
+        ],
+    )
+    .with_channel_sizes(ChannelSizes {
+        entries_buffer: 1,
+        results_buffer: 1,
+    });
+    let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(2).await.unwrap();
+
+    for i in 0..4 {
+        assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(i).entries().len());
+    }
+}
+
+#[tokio::test]
+async fn test_shared_queue_with_slow_worker() {
+    let delay = Duration::from_secs(1);
+    let spawner = HeterogeneousSpawner {
+        delay,
+        worker_counter: AtomicUsize::new(0),
+    };
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+            "csv/entry_16_3.csv".to_string(),
+            "csv/entry_16_4.csv".to_string(),
+        ],
+    )
+    .with_progress(progress_tx);
+
+    let start = Instant::now();
+    orchestrator.create_aggregation_mst(2).await.unwrap();
+    let elapsed = start.elapsed();
+
+    let mut received_trees = [0; 2];
+    while let Some(event) = progress_rx.recv().await {
+        if let ProgressEvent::MiniTreeReceived { executor_index, .. } = event {
+            received_trees[executor_index] += 1;
+        }
+    }
+
+    // While the slow worker builds a mini-tree, the fast one builds the others,
+    // instead of the two mini-trees of each executor's range taking twice the delay
+    assert_eq!(received_trees.iter().sum::<usize>(), 4);
+    assert!(received_trees[0] < received_trees[1]);
+    assert!(elapsed < delay * 2);
+}
+
+#[tokio::test]
+async fn test_executor_weights() {
+    // A single executor, whose worker delays every response
+    let delay = Duration::from_secs(1);
+    let spawner = HeterogeneousSpawner {
+        delay,
+        worker_counter: AtomicUsize::new(0),
+    };
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+            "csv/entry_16_3.csv".to_string(),
+            "csv/entry_16_4.csv".to_string(),
+        ],
+    )
+    .with_executor_weights(vec![4]);
+
+    let start = Instant::now();
+    orchestrator.create_aggregation_mst(1).await.unwrap();
+    let elapsed = start.elapsed();
+
+    // The four mini-trees are requested at once, instead of one after the other
+    assert!(elapsed < delay * 2);
+}
+
+#[tokio::test]
+async fn test_stall_timeout() {
+    // The first worker hangs on every mini-tree, far beyond the stall timeout
+    let delay = Duration::from_secs(60);
+    let spawner = HeterogeneousSpawner {
+        delay,
+        worker_counter: AtomicUsize::new(0),
+    };
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+            "csv/entry_16_3.csv".to_string(),
+            "csv/entry_16_4.csv".to_string(),
+        ],
+    )
+    .with_task_attempts(2)
+    .with_stall_timeout(Duration::from_millis(500))
+    .with_progress(progress_tx);
+
+    let start = Instant::now();
+    orchestrator.create_aggregation_mst(2).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // The task of the stalled executor is built by the other one
+    let mut retried_tasks = 0;
+    let mut received_trees = [0; 2];
+    while let Some(event) = progress_rx.recv().await {
+        match event {
+            ProgressEvent::TaskRetried {
+                failed_executor, ..
+            } => {
+                assert_eq!(failed_executor, 0);
+                retried_tasks += 1;
+            }
+            ProgressEvent::MiniTreeReceived { executor_index, .. } => {
+                received_trees[executor_index] += 1;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(retried_tasks, 1);
+    assert_eq!(received_trees, [0, 4]);
+}
+
+#[tokio::test]
+async fn test_deadline() {
+    // The only worker takes longer for each mini-tree than the whole round may take
+    let delay = Duration::from_secs(3);
+    let spawner = HeterogeneousSpawner {
+        delay,
+        worker_counter: AtomicUsize::new(0),
+    };
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    )
+    .with_deadline(Duration::from_millis(500));
+
+    let start = Instant::now();
+    let error = orchestrator.create_aggregation_mst(1).await.err().unwrap();
+    assert!(start.elapsed() < delay);
+    assert!(error
+        .to_string()
+        .starts_with("Aggregation exceeded its deadline of 500ms"));
+
+    let failure = error.downcast_ref::<AggregationFailure>().unwrap();
+    assert!(failure.built.is_empty());
+    assert!(failure.failed.is_empty());
+    assert_eq!(failure.pending, vec![0, 1]);
+}
+
+// Holds the entries in memory, like a source that queries them from a database
+struct InMemoryEntrySource(Vec<JsonEntry>);
+
+impl EntrySource for InMemoryEntrySource {
+    fn name(&self) -> String {
+        "in-memory".to_string()
+    }
+
+    fn load_entries(&self) -> Result<Vec<JsonEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.0.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_custom_entry_sources() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+    ];
+    let entry_sources = entry_csvs
+        .iter()
+        .map(|path| {
+            let entries = CsvEntrySource::<2, 14>::new(path.clone())
+                .load_entries()
+                .unwrap();
+            Box::new(InMemoryEntrySource(entries)) as Box<dyn EntrySource>
+        })
+        .collect();
+
+    let aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::from_entry_sources(Box::new(MockSpawner::new(None)), entry_sources)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+    let csv_aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+
+    assert_eq!(
+        aggregation_merkle_sum_tree.root().hash,
+        csv_aggregation_merkle_sum_tree.root().hash
+    );
+
+    // Only the CSV files name their cryptocurrencies
+    assert_eq!(
+        aggregation_merkle_sum_tree.cryptocurrencies()[1].name,
+        "DUMMY"
+    );
+    assert_eq!(
+        csv_aggregation_merkle_sum_tree.cryptocurrencies()[1].name,
+        "USDT"
+    );
+    // and the Workers build the mini-trees with them
+    assert_eq!(
+        csv_aggregation_merkle_sum_tree
+            .mini_tree(0)
+            .cryptocurrencies()[1]
+            .name,
+        "USDT"
+    );
+}
+
+#[tokio::test]
+async fn test_differing_cryptocurrencies() {
+    // The entries of entry_16_2.csv, with the balances of the second currency on another chain
+    let path = std::env::temp_dir().join("summa_aggregation_differing_cryptocurrencies.csv");
+    let csv = std::fs::read_to_string("csv/entry_16_2.csv").unwrap();
+    std::fs::write(
+        &path,
+        csv.replacen("balance_USDT_ETH", "balance_USDT_TRX", 1),
+    )
+    .unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec!["csv/entry_16_1.csv".to_string(), path.clone()],
+    );
+    match orchestrator.create_aggregation_mst(2).await {
+        Ok(_) => panic!("Expected an error"),
+        Err(e) => assert_eq!(
+            e.to_string(),
+            format!(
+                "Cryptocurrencies of {:?} differ from those of \"csv/entry_16_1.csv\"",
+                path
+            )
+        ),
+    }
+}
+
 #[tokio::test]
 async fn test_none_exist_csv() {
     let spawner = MockSpawner::new(None);
@@ -36,10 +439,101 @@ async fn test_none_exist_csv() {
             assert!(e
                 .to_string()
                 .contains("Mismatch in generated mini tree counts and given CSV counts"));
+
+            // The failed source is named, the other one was built or left pending
+            let failure = e.downcast_ref::<AggregationFailure>().unwrap();
+            assert_eq!(failure.failed.len(), 1);
+            assert_eq!(failure.failed[0].task_id, 1);
+            assert_eq!(failure.failed[0].source, "csv/no_exist.csv");
+            assert!(failure.failed[0].error.starts_with("failed to load"));
+            assert_eq!(failure.built.len() + failure.pending.len(), 1);
         }
     }
 }
 
+#[tokio::test]
+async fn test_duplicate_usernames() {
+    // Both files contain the user "dxGaEAii", among others
+    let entry_csvs = vec![
+        "csv/entry_16.csv".to_string(),
+        "csv/entry_16_1.csv".to_string(),
+    ];
+
+    let orchestrator =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone());
+    match orchestrator.create_aggregation_mst(2).await {
+        Ok(_) => panic!("Expected an error"),
+        Err(e) => {
+            let error = e.to_string();
+            assert!(error.contains("appears in both"));
+            assert!(error.contains("csv/entry_16.csv"));
+            assert!(error.contains("csv/entry_16_1.csv"));
+        }
+    }
+
+    // Without the check, each user gets a leaf per file
+    let orchestrator = Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+        .with_duplicate_detection(false);
+    let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(2).await.unwrap();
+    assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(1).entries().len());
+}
+
+#[test]
+fn test_validate() {
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    );
+    let report = orchestrator.validate().unwrap();
+    assert_eq!(report.entries_per_source, [16, 16]);
+    assert_eq!(report.total_entries, 32);
+    assert_eq!(
+        report.total_balances,
+        [BigUint::from(556862_u32 * 2), BigUint::from(556862_u32 * 2)]
+    );
+
+    // The accumulated balances of entry_16.csv and entry_16_no_overflow.csv exceed 2^64 - 1
+    let orchestrator = Orchestrator::<2, 8>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16.csv".to_string(),
+            "csv/entry_16_no_overflow.csv".to_string(),
+            "csv/no_exist.csv".to_string(),
+        ],
+    )
+    .with_duplicate_detection(false);
+    let report = orchestrator.validate().unwrap_err();
+    assert_eq!(report.entries_per_source, [16, 16, 0]);
+    // Both currencies overflow
+    assert_eq!(report.errors.len(), 3);
+    assert!(report.errors[0].starts_with("Error while loading entries from \"csv/no_exist.csv\""));
+    assert_eq!(
+        report.errors[1],
+        "Total balance of currency 0 (ETH on ETH) is 18446744073710108477, \
+         which exceeds the maximum of 2^64 - 1 = 18446744073709551615 by 556862, \
+         proof generation will fail!"
+    );
+
+    // Both files hold the same 16 users, of which the first 10 duplicates are listed
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16.csv".to_string(),
+            "csv/entry_16_no_overflow.csv".to_string(),
+        ],
+    );
+    let report = orchestrator.validate().unwrap_err();
+    assert_eq!(report.errors.len(), 11);
+    assert_eq!(
+        report.errors[0],
+        "Username \"dxGaEAii\" appears in both \"csv/entry_16.csv\" and \"csv/entry_16_no_overflow.csv\""
+    );
+    assert_eq!(report.errors[10], "6 more duplicate usernames");
+}
+
 #[tokio::test]
 async fn test_none_exist_worker() {
     let non_exist_worker_url = vec!["127.0.0.1:40".to_string()]; // unsignable port
@@ -56,9 +550,96 @@ async fn test_none_exist_worker() {
     match orchestrator.create_aggregation_mst(2).await {
         Ok(_) => panic!("Expected an error"),
         Err(e) => {
-            assert!(e
-                .to_string()
-                .contains("Mismatch in generated mini tree counts and given CSV counts"));
+            // The error names the worker the task failed on
+            let error = e.to_string();
+            assert!(error.contains("failed"));
+            assert!(error.contains("127.0.0.1:40"));
+
+            let failure = e.downcast_ref::<AggregationFailure>().unwrap();
+            assert_eq!(failure.failed.len(), 1);
+            assert_eq!(
+                failure.built.len() + failure.failed.len() + failure.pending.len(),
+                2
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_reassign_task_of_none_exist_worker() {
+    let non_exist_worker_url = vec!["127.0.0.1:40".to_string()]; // unsignable port
+    let spawner = MockSpawner::new(Some(non_exist_worker_url));
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    )
+    .with_task_attempts(2)
+    .with_progress(progress_tx);
+    let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(2).await.unwrap();
+
+    // The task taken by the first executor failed and was built by the second one
+    assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(0).entries().len());
+    assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(1).entries().len());
+
+    let mut events = Vec::new();
+    while let Some(event) = progress_rx.recv().await {
+        events.push(event);
+    }
+    assert!(events.iter().any(|event| matches!(
+        event,
+        ProgressEvent::TaskRetried {
+            failed_executor: 0,
+            ..
+        }
+    )));
+    assert!(!events.iter().any(|event| matches!(
+        event,
+        ProgressEvent::MiniTreeReceived {
+            executor_index: 0,
+            ..
+        }
+    )));
+}
+
+#[tokio::test]
+async fn test_default_executor_count() {
+    // The MockSpawner is not set up for a number of executors
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec!["csv/entry_16_1.csv".to_string()],
+    );
+    let result = orchestrator.create_aggregation_mst(None).await;
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .starts_with("No executor count given"));
+}
+
+#[tokio::test]
+async fn test_more_executors_than_worker_urls() {
+    let spawner = CloudSpawner::new(None, vec!["127.0.0.1:4000".to_string()], 4000).unwrap();
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    );
+
+    match orchestrator.create_aggregation_mst(2).await {
+        Ok(_) => panic!("Expected an error"),
+        Err(e) => {
+            assert_eq!(
+                e.to_string(),
+                "Requested 2 executors but only 1 worker URLs are configured"
+            );
         }
     }
 }
@@ -91,7 +672,8 @@ async fn test_with_swarm_service() {
         Some(("mini_tree".to_string(), "docker-compose.yml".to_string())),
         vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()],
         4000,
-    );
+    )
+    .unwrap();
 
     let orchestrator = Orchestrator::<2, 14>::new(
         Box::new(spawner),
@@ -104,3 +686,356 @@ async fn test_with_swarm_service() {
     assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(0).entries().len());
     assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(1).entries().len());
 }
+
+#[cfg(feature = "docker-swarm")]
+#[tokio::test]
+async fn test_with_swarm_service_endpoint() {
+    // The executors reach the service at the port it publishes, one per replica of the compose file
+    let spawner = CloudSpawner::new(
+        Some(("mini_tree".to_string(), "docker-compose.yml".to_string())),
+        vec![],
+        4000,
+    )
+    .unwrap();
+    assert_eq!(spawner.max_executors(), None);
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(spawner),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    );
+    let aggregation_merkle_sum_tree = orchestrator.create_aggregation_mst(None).await.unwrap();
+    assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(0).entries().len());
+    assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(1).entries().len());
+}
+
+#[tokio::test]
+async fn test_incremental_aggregation() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+        "csv/entry_16_4.csv".to_string(),
+    ];
+
+    let aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+    let incremental_aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+            .with_incremental_aggregation(true)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+
+    assert_eq!(
+        aggregation_merkle_sum_tree.root().hash,
+        incremental_aggregation_merkle_sum_tree.root().hash
+    );
+    assert_eq!(
+        aggregation_merkle_sum_tree.root().balances,
+        incremental_aggregation_merkle_sum_tree.root().balances
+    );
+    for i in 0..4 {
+        assert_eq!(
+            aggregation_merkle_sum_tree.mini_tree(i).root().hash,
+            incremental_aggregation_merkle_sum_tree
+                .mini_tree(i)
+                .root()
+                .hash
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_mini_tree_dir() {
+    let mini_tree_dir = std::env::temp_dir().join("summa_aggregation_mini_tree_dir");
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+    ];
+
+    let aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .with_mini_tree_dir(&mini_tree_dir)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+
+    for (i, entry_csv) in entry_csvs.iter().enumerate() {
+        let path = mini_tree_dir.join(format!("entry_16_{}.json", i + 1));
+        let json_tree: JsonMerkleSumTree =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json_tree.source.as_ref(), Some(entry_csv));
+        let mini_tree = json_tree.to_mst::<2, 14>().unwrap();
+        assert_eq!(
+            mini_tree.root().hash,
+            aggregation_merkle_sum_tree.mini_tree(i).root().hash
+        );
+    }
+    std::fs::remove_dir_all(&mini_tree_dir).unwrap();
+
+    // Sources sharing a file stem would overwrite each other's mini tree
+    let result = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "entry_16_1.csv".to_string(),
+        ],
+    )
+    .with_mini_tree_dir(&mini_tree_dir)
+    .create_aggregation_mst(2)
+    .await;
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("would both write their mini tree to entry_16_1.json"));
+}
+
+#[tokio::test]
+async fn test_resume() {
+    let mini_tree_dir = std::env::temp_dir().join("summa_aggregation_resume");
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+        "csv/entry_16_4.csv".to_string(),
+    ];
+    let aggregation_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .with_mini_tree_dir(&mini_tree_dir)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+
+    // A run that failed before the second and the fourth mini-tree arrived
+    std::fs::remove_file(mini_tree_dir.join("entry_16_2.json")).unwrap();
+    std::fs::remove_file(mini_tree_dir.join("entry_16_4.json")).unwrap();
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    let resumed_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .with_mini_tree_dir(&mini_tree_dir)
+            .with_resume(true)
+            .with_progress(progress_tx)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+    assert_eq!(
+        aggregation_merkle_sum_tree.root().hash,
+        resumed_merkle_sum_tree.root().hash
+    );
+
+    let mut events = Vec::new();
+    while let Ok(event) = progress_rx.try_recv() {
+        events.push(event);
+    }
+    let restored_tasks = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::MiniTreeRestored { task_id, .. } => Some(*task_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(restored_tasks, vec![0, 2]);
+    let loaded_tasks = events
+        .iter()
+        .filter(|event| matches!(event, ProgressEvent::EntriesLoaded { .. }))
+        .count();
+    assert_eq!(loaded_tasks, 2);
+
+    // With every mini-tree on disk, no executor is spawned
+    let unreachable_worker = MockSpawner::new(Some(vec!["127.0.0.1:40".to_string()]));
+    let restored_merkle_sum_tree =
+        Orchestrator::<2, 14>::new(Box::new(unreachable_worker), entry_csvs.clone())
+            .with_mini_tree_dir(&mini_tree_dir)
+            .with_resume(true)
+            .create_aggregation_mst(2)
+            .await
+            .unwrap();
+    assert_eq!(
+        aggregation_merkle_sum_tree.root().hash,
+        restored_merkle_sum_tree.root().hash
+    );
+
+    // A file written for another entry source is rejected
+    std::fs::copy(
+        mini_tree_dir.join("entry_16_1.json"),
+        mini_tree_dir.join("entry_16_3.json"),
+    )
+    .unwrap();
+    let result = Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+        .with_mini_tree_dir(&mini_tree_dir)
+        .with_resume(true)
+        .create_aggregation_mst(2)
+        .await;
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("the mini tree was built from Some(\"csv/entry_16_1.csv\"), not from \"csv/entry_16_3.csv\""));
+    std::fs::remove_dir_all(&mini_tree_dir).unwrap();
+
+    // Nothing to resume from
+    let result = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec!["csv/entry_16_1.csv".to_string()],
+    )
+    .with_resume(true)
+    .create_aggregation_mst(1)
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_progress_events() {
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    )
+    .with_progress(progress_tx);
+    orchestrator.create_aggregation_mst(2).await.unwrap();
+
+    // The sender is dropped with the orchestrator, so all events are received
+    let mut events = Vec::new();
+    while let Some(event) = progress_rx.recv().await {
+        events.push(event);
+    }
+
+    for task_id in 0..2 {
+        assert!(events.contains(&ProgressEvent::EntriesLoaded {
+            task_id,
+            source: format!("csv/entry_16_{}.csv", task_id + 1),
+            entries: 16,
+        }));
+    }
+    // Either executor may have built either mini-tree
+    let received_trees = events
+        .iter()
+        .filter(|event| matches!(event, ProgressEvent::MiniTreeReceived { .. }))
+        .count();
+    assert_eq!(received_trees, 2);
+    assert_eq!(
+        events.last(),
+        Some(&ProgressEvent::AggregationStarted { mini_trees: 2 })
+    );
+    assert_eq!(events.len(), 5);
+}
+
+#[tokio::test]
+async fn test_cancelled_by_caller() {
+    let cancel_token = CancellationToken::new();
+    let orchestrator = Orchestrator::<2, 14>::new(
+        Box::new(MockSpawner::new(None)),
+        vec![
+            "csv/entry_16_1.csv".to_string(),
+            "csv/entry_16_2.csv".to_string(),
+        ],
+    )
+    .with_cancellation_token(cancel_token.clone());
+
+    cancel_token.cancel();
+    let result = orchestrator.create_aggregation_mst(2).await;
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Aggregation was cancelled"
+    );
+}
+
+#[tokio::test]
+async fn test_checksums() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+    ];
+    let checksums = |entry_16_2_sha256: &str| {
+        HashMap::from([
+            (
+                "csv/entry_16_1.csv".to_string(),
+                "956241b8ce6fbb6ef64fb2df67a38a1389bce82a1eba2625c70dc75b8989704b".to_string(),
+            ),
+            (
+                "csv/entry_16_2.csv".to_string(),
+                entry_16_2_sha256.to_string(),
+            ),
+        ])
+    };
+
+    let orchestrator =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .with_checksums(checksums(
+                "9fbd3e0050d6880031b411aa85444d7694ba25e98807ee525dc59c59cc28833c",
+            ));
+    assert!(orchestrator.validate().is_ok());
+    orchestrator.create_aggregation_mst(2).await.unwrap();
+
+    // The digest of another file, as if `entry_16_2.csv` was tampered with
+    let tampered_sha256 = "93e6214fe305710d189cb498ca8c34a9fc4a4908d2574e01c595babbe9f920cc";
+    let orchestrator =
+        Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+            .with_checksums(checksums(tampered_sha256));
+    let report = orchestrator.validate().unwrap_err();
+    assert!(report.errors[0].contains("SHA-256 of \"csv/entry_16_2.csv\" is 9fbd3e00"));
+    let error = orchestrator.create_aggregation_mst(2).await.unwrap_err();
+    let failure = error.downcast_ref::<AggregationFailure>().unwrap();
+    assert_eq!(failure.failed.len(), 1);
+    assert_eq!(failure.failed[0].source, "csv/entry_16_2.csv");
+    assert!(failure.failed[0]
+        .error
+        .ends_with(&format!("expected {}", tampered_sha256)));
+
+    // A manifest entry for a file outside the round
+    let mut manifest = checksums(tampered_sha256);
+    manifest.insert(
+        "csv/entry_16_3.csv".to_string(),
+        tampered_sha256.to_string(),
+    );
+    let error = Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs)
+        .with_checksums(manifest)
+        .create_aggregation_mst(2)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Checksum given for \"csv/entry_16_3.csv\", which is not an entry source of the round"
+    );
+}
+
+#[tokio::test]
+async fn test_round_stats() {
+    let entry_csvs = vec![
+        "csv/entry_16_1.csv".to_string(),
+        "csv/entry_16_2.csv".to_string(),
+        "csv/entry_16_3.csv".to_string(),
+    ];
+
+    for incremental_aggregation in [false, true] {
+        let orchestrator =
+            Orchestrator::<2, 14>::new(Box::new(MockSpawner::new(None)), entry_csvs.clone())
+                .with_incremental_aggregation(incremental_aggregation);
+        let (aggregation_merkle_sum_tree, round_stats) = orchestrator
+            .create_aggregation_mst_with_stats(2)
+            .await
+            .unwrap();
+        assert_eq!(16, aggregation_merkle_sum_tree.mini_tree(2).entries().len());
+
+        assert_eq!(round_stats.chunks_per_executor.len(), 2);
+        assert_eq!(round_stats.chunks_per_executor.iter().sum::<usize>(), 3);
+        // The Workers report the build time of every mini-tree
+        assert_eq!(round_stats.build_durations.len(), 3);
+        assert!(round_stats.build_durations.iter().all(Option::is_some));
+        assert!(round_stats.aggregation_duration <= round_stats.total_duration);
+        assert!(round_stats
+            .to_string()
+            .starts_with("3 mini trees by 2 executors in"));
+    }
+}
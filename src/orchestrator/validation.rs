@@ -0,0 +1,46 @@
+use num_bigint::BigUint;
+use std::{error::Error, fmt};
+
+/// ValidationReport
+///
+/// The outcome of `Orchestrator::validate`, checking the entry sources of a round without spawning any executor.
+///
+/// The counts and totals cover the entries of the sources that could be loaded, the `errors` list everything
+/// that would fail the round, e.g. a CSV file that does not parse or a total balance that exceeds the range given by N_BYTES.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Number of entries of each entry source, in their order, zero for a source that could not be loaded.
+    pub entries_per_source: Vec<usize>,
+    pub total_entries: usize,
+    /// Total balance of each currency over all entries.
+    pub total_balances: Vec<BigUint>,
+    pub errors: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entries in {} entry sources",
+            self.total_entries,
+            self.entries_per_source.len()
+        )?;
+        if !self.errors.is_empty() {
+            write!(
+                f,
+                ", {} errors: {}",
+                self.errors.len(),
+                self.errors.join("; ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationReport {}
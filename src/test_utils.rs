@@ -0,0 +1,20 @@
+use axum::Router;
+use std::net::SocketAddr;
+use summa_backend::merkle_sum_tree::utils::parse_csv_to_entries;
+
+use crate::json_mst::JsonEntry;
+
+// Serves the router on a free local port in the background, returns its URL
+pub(crate) fn spawn_test_server(router: Router) -> String {
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+// Parses a CSV file with 2 currencies of 14 bytes into the entries sent to the Workers
+pub(crate) fn load_json_entries(path: &str) -> Vec<JsonEntry> {
+    let (_, entries) = parse_csv_to_entries::<_, 2, 14>(path).unwrap();
+    entries.iter().map(JsonEntry::from_entry).collect()
+}